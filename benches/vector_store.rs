@@ -2,7 +2,9 @@ use criterion::{BenchmarkId, Criterion, Throughput, black_box, criterion_group,
 use object_store::ObjectStore;
 use object_store::memory::InMemory;
 use rand::Rng;
-use smolpuff::VectorStore;
+use smolpuff::{
+    DistanceMetric, DuplicatePolicy, PreparedQuery, VectorRecord, VectorStore, VectorStoreOptions,
+};
 use std::sync::Arc;
 
 const VECTOR_DIM: usize = 128;
@@ -123,6 +125,134 @@ fn bench_write_throughput(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_batch_add(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("batch_add");
+    let num_vectors = 5000;
+    group.throughput(Throughput::Elements(num_vectors as u64));
+
+    for &batch_size in &[100, 1000, 5000] {
+        group.bench_with_input(
+            BenchmarkId::new("batch_size", batch_size),
+            &batch_size,
+            |b, &batch_size| {
+                b.iter_custom(|iters| {
+                    rt.block_on(async {
+                        let mut total_elapsed = std::time::Duration::ZERO;
+
+                        for _ in 0..iters {
+                            let object_store: Arc<dyn ObjectStore + 'static> =
+                                Arc::new(InMemory::new());
+                            let store = VectorStoreOptions::new()
+                                .batch_size(batch_size)
+                                .build_and_open("/bench/vectors", object_store)
+                                .await
+                                .expect("Failed to open store");
+                            let items = (0..num_vectors)
+                                .map(|i| {
+                                    (
+                                        format!("doc{}", i),
+                                        generate_random_vector(VECTOR_DIM),
+                                        Some(generate_random_metadata()),
+                                    )
+                                })
+                                .collect();
+
+                            let start = std::time::Instant::now();
+                            store
+                                .batch_add(black_box(items), DuplicatePolicy::KeepLast)
+                                .await
+                                .expect("Failed to add batch");
+                            total_elapsed += start.elapsed();
+
+                            let _ = store.close().await;
+                        }
+
+                        total_elapsed
+                    })
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+fn bench_add_batch_vs_add(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("add_batch_vs_add");
+    let num_vectors = 1000;
+    group.throughput(Throughput::Elements(num_vectors as u64));
+
+    let generate_records = || -> Vec<VectorRecord> {
+        (0..num_vectors)
+            .map(|i| VectorRecord {
+                id: format!("doc{}", i),
+                vector: generate_random_vector(VECTOR_DIM),
+                metadata: Some(generate_random_metadata()),
+            })
+            .collect()
+    };
+
+    group.bench_function("add_loop", |b| {
+        b.iter_custom(|iters| {
+            rt.block_on(async {
+                let mut total_elapsed = std::time::Duration::ZERO;
+
+                for _ in 0..iters {
+                    let store = setup_store().await;
+                    let records = generate_records();
+
+                    let start = std::time::Instant::now();
+                    for record in records {
+                        store
+                            .add(&record.id, record.vector, record.metadata)
+                            .await
+                            .expect("Failed to add");
+                    }
+                    total_elapsed += start.elapsed();
+
+                    let _ = store.close().await;
+                }
+
+                total_elapsed
+            })
+        });
+    });
+
+    group.bench_function("add_batch", |b| {
+        b.iter_custom(|iters| {
+            rt.block_on(async {
+                let mut total_elapsed = std::time::Duration::ZERO;
+
+                for _ in 0..iters {
+                    let store = setup_store().await;
+                    let records = generate_records();
+
+                    let start = std::time::Instant::now();
+                    store
+                        .add_batch(black_box(records))
+                        .await
+                        .expect("Failed to add batch");
+                    total_elapsed += start.elapsed();
+
+                    let _ = store.close().await;
+                }
+
+                total_elapsed
+            })
+        });
+    });
+
+    group.finish();
+}
+
+/// Header of the JSON-array records written before the binary format, which
+/// stores still read
+const JSON_RECORD_HEADER: [u8; 4] = [0, 0x50, 0xFF, 0x7F];
+
 fn bench_query_latency(c: &mut Criterion) {
     let rt = tokio::runtime::Runtime::new().unwrap();
 
@@ -150,6 +280,68 @@ fn bench_query_latency(c: &mut Criterion) {
         );
     }
 
+    // Binary records against the JSON-array records they replaced, over
+    // the same batch-loaded vectors
+    let num_vectors = 5000;
+    for json in [false, true] {
+        let name = if json {
+            "json_records"
+        } else {
+            "binary_records"
+        };
+        group.bench_with_input(
+            BenchmarkId::new(name, num_vectors),
+            &num_vectors,
+            |b, &n| {
+                let store = rt.block_on(async {
+                    let store = setup_store().await;
+                    let vectors: Vec<Vec<f32>> =
+                        (0..n).map(|_| generate_random_vector(VECTOR_DIM)).collect();
+                    let items = vectors
+                        .iter()
+                        .enumerate()
+                        .map(|(i, vector)| {
+                            (
+                                format!("doc{}", i),
+                                vector.clone(),
+                                Some(generate_random_metadata()),
+                            )
+                        })
+                        .collect();
+                    store
+                        .batch_add(items, DuplicatePolicy::KeepLast)
+                        .await
+                        .expect("Failed to add batch");
+                    if json {
+                        // Concurrent rewrites share write-ahead log flushes
+                        let rewrites = vectors.iter().enumerate().map(|(i, vector)| {
+                            let store = &store;
+                            let mut bytes = JSON_RECORD_HEADER.to_vec();
+                            bytes.extend(serde_json::to_vec(vector).unwrap());
+                            async move { store.add_raw(&format!("doc{}", i), bytes, true).await }
+                        });
+                        for rewritten in futures::future::join_all(rewrites).await {
+                            rewritten.expect("Failed to write JSON record");
+                        }
+                    }
+                    store
+                });
+                let query_vector = generate_random_vector(VECTOR_DIM);
+
+                b.to_async(&rt).iter(|| async {
+                    store
+                        .query(black_box(&query_vector), black_box(10))
+                        .await
+                        .expect("Failed to query")
+                });
+
+                rt.block_on(async {
+                    let _ = store.close().await;
+                });
+            },
+        );
+    }
+
     group.finish();
 }
 
@@ -213,13 +405,543 @@ fn bench_query_throughput(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_repeated_query(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("repeated_query");
+    group.throughput(Throughput::Elements(1000)); // 1000 identical queries per iteration
+
+    let store = rt.block_on(setup_store_with_vectors(1000));
+    let query_vector = generate_random_vector(VECTOR_DIM);
+    let prepared = PreparedQuery::new(query_vector.clone());
+
+    group.bench_function("query", |b| {
+        b.to_async(&rt).iter(|| async {
+            for _ in 0..1000 {
+                store
+                    .query(black_box(&query_vector), black_box(10))
+                    .await
+                    .expect("Failed to query");
+            }
+        });
+    });
+
+    group.bench_function("query_prepared", |b| {
+        b.to_async(&rt).iter(|| async {
+            for _ in 0..1000 {
+                store
+                    .query_prepared(black_box(&prepared), black_box(10))
+                    .await
+                    .expect("Failed to query");
+            }
+        });
+    });
+
+    rt.block_on(async {
+        let _ = store.close().await;
+    });
+
+    group.finish();
+}
+
+fn bench_query_parallel(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("query_parallel");
+    let num_vectors = 50_000;
+    group.sample_size(10);
+
+    let store = rt.block_on(async {
+        let store = setup_store().await;
+        let items = (0..num_vectors)
+            .map(|i| {
+                (
+                    format!("doc{}", i),
+                    generate_random_vector(VECTOR_DIM),
+                    Some(generate_random_metadata()),
+                )
+            })
+            .collect();
+        store
+            .batch_add(items, DuplicatePolicy::KeepLast)
+            .await
+            .expect("Failed to add batch");
+        store
+    });
+    let query_vector = generate_random_vector(VECTOR_DIM);
+
+    group.bench_function("query", |b| {
+        b.to_async(&rt).iter(|| async {
+            store
+                .query(black_box(&query_vector), black_box(10))
+                .await
+                .expect("Failed to query")
+        });
+    });
+
+    group.bench_function("query_parallel", |b| {
+        b.to_async(&rt).iter(|| async {
+            store
+                .query_parallel(black_box(&query_vector), black_box(10))
+                .await
+                .expect("Failed to query")
+        });
+    });
+
+    rt.block_on(async {
+        let _ = store.close().await;
+    });
+
+    group.finish();
+}
+
+fn bench_query_metric(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("query_metric");
+    let num_vectors = 5000;
+
+    for metric in [
+        DistanceMetric::Cosine,
+        DistanceMetric::DotProduct,
+        DistanceMetric::Euclidean,
+        DistanceMetric::SquaredEuclidean,
+    ] {
+        group.bench_with_input(
+            BenchmarkId::new("knn_query", metric.as_str()),
+            &metric,
+            |b, &metric| {
+                let store = rt.block_on(async {
+                    let object_store: Arc<dyn ObjectStore + 'static> = Arc::new(InMemory::new());
+                    let store =
+                        VectorStore::open_with_metric("/bench/vectors", object_store, metric)
+                            .await
+                            .expect("Failed to open store");
+                    let items = (0..num_vectors)
+                        .map(|i| {
+                            (
+                                format!("doc{}", i),
+                                generate_random_vector(VECTOR_DIM),
+                                Some(generate_random_metadata()),
+                            )
+                        })
+                        .collect();
+                    store
+                        .batch_add(items, DuplicatePolicy::KeepLast)
+                        .await
+                        .expect("Failed to add batch");
+                    store
+                });
+                let query_vector = generate_random_vector(VECTOR_DIM);
+
+                b.to_async(&rt).iter(|| async {
+                    store
+                        .query(black_box(&query_vector), black_box(10))
+                        .await
+                        .expect("Failed to query")
+                });
+
+                rt.block_on(async {
+                    let _ = store.close().await;
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+/// Level-0 tables and sorted runs in the latest manifest at `path`
+async fn manifest_levels(path: &str, object_store: Arc<dyn ObjectStore>) -> (usize, usize) {
+    let admin = slatedb::admin::Admin::builder(path, object_store).build();
+    let manifest = admin
+        .read_manifest(None)
+        .await
+        .expect("Failed to read manifest")
+        .expect("No manifest");
+    // Serialized as [id, manifest]
+    let manifest: serde_json::Value = serde_json::from_str(&manifest).unwrap();
+    let core = &manifest[1]["core"];
+    let len = |field: &str| core[field].as_array().map_or(0, Vec::len);
+    (len("l0"), len("compacted"))
+}
+
+fn bench_query_after_churn(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("query_after_churn");
+    let num_vectors = 1000;
+    // Enough level-0 tables for slatedb's compactor to pick them up
+    let churn_rounds = 6;
+    let path = "/bench/churn";
+
+    // Each round deletes every record and adds it back, then flushes the
+    // memtable, so every round leaves a level-0 table of tombstones and
+    // replaced versions for the scan to merge past
+    let object_store: Arc<dyn ObjectStore + 'static> = Arc::new(InMemory::new());
+    let store = rt.block_on(async {
+        let store = VectorStore::open(path, object_store.clone())
+            .await
+            .expect("Failed to open store");
+        let records = || {
+            (0..num_vectors)
+                .map(|i| {
+                    (
+                        format!("doc{}", i),
+                        generate_random_vector(VECTOR_DIM),
+                        Some(generate_random_metadata()),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        store
+            .batch_add(records(), DuplicatePolicy::Error)
+            .await
+            .expect("Failed to add vectors");
+        for _ in 0..churn_rounds {
+            // Concurrent deletes share write-ahead log flushes
+            let deletes = (0..num_vectors).map(|i| {
+                let store = &store;
+                async move { store.delete(&format!("doc{}", i)).await }
+            });
+            for deleted in futures::future::join_all(deletes).await {
+                deleted.expect("Failed to delete vector");
+            }
+            store
+                .batch_add(records(), DuplicatePolicy::Error)
+                .await
+                .expect("Failed to add vectors");
+            store
+                .flush_memtable()
+                .await
+                .expect("Failed to flush memtable");
+        }
+        store
+    });
+    let query_vector = generate_random_vector(VECTOR_DIM);
+
+    group.bench_function("after_churn", |b| {
+        b.to_async(&rt).iter(|| async {
+            store
+                .query(black_box(&query_vector), black_box(10))
+                .await
+                .expect("Failed to query")
+        });
+    });
+
+    // The compactor merges the level-0 tables into a sorted run, dropping
+    // the deleted and replaced versions
+    rt.block_on(async {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(120);
+        loop {
+            let (l0, compacted) = manifest_levels(path, object_store.clone()).await;
+            if l0 == 0 && compacted > 0 {
+                break;
+            }
+            assert!(
+                std::time::Instant::now() < deadline,
+                "Compaction didn't finish: {l0} level-0 tables left"
+            );
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        }
+    });
+
+    group.bench_function("after_churn_and_compaction", |b| {
+        b.to_async(&rt).iter(|| async {
+            store
+                .query(black_box(&query_vector), black_box(10))
+                .await
+                .expect("Failed to query")
+        });
+    });
+
+    let fresh = rt.block_on(async {
+        let fresh = setup_store().await;
+        let records = (0..num_vectors)
+            .map(|i| {
+                (
+                    format!("doc{}", i),
+                    generate_random_vector(VECTOR_DIM),
+                    Some(generate_random_metadata()),
+                )
+            })
+            .collect();
+        fresh
+            .batch_add(records, DuplicatePolicy::Error)
+            .await
+            .expect("Failed to add vectors");
+        fresh
+    });
+    group.bench_function("fresh", |b| {
+        b.to_async(&rt).iter(|| async {
+            fresh
+                .query(black_box(&query_vector), black_box(10))
+                .await
+                .expect("Failed to query")
+        });
+    });
+
+    rt.block_on(async {
+        let _ = store.close().await;
+        let _ = fresh.close().await;
+    });
+
+    group.finish();
+}
+
+fn bench_query_large_k(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("query_large_k");
+    let store = rt.block_on(setup_store_with_vectors(5000));
+    let query_vector = generate_random_vector(VECTOR_DIM);
+
+    for &k in &[100, 500, 1000] {
+        group.bench_with_input(BenchmarkId::new("top_k", k), &k, |b, &k| {
+            b.to_async(&rt).iter(|| async {
+                store
+                    .query(black_box(&query_vector), black_box(k))
+                    .await
+                    .expect("Failed to query")
+            });
+        });
+    }
+
+    rt.block_on(async {
+        let _ = store.close().await;
+    });
+
+    group.finish();
+}
+
+fn bench_query_large_metadata(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("query_large_metadata");
+    let store = rt.block_on(async {
+        let store = setup_store().await;
+        for i in 0..1000 {
+            let metadata = serde_json::json!({
+                "title": format!("Document {}", i),
+                "body": "lorem ipsum ".repeat(500),
+                "tags": (0..50).map(|t| format!("tag{}", t)).collect::<Vec<_>>(),
+            });
+            store
+                .add(
+                    &format!("doc{}", i),
+                    generate_random_vector(VECTOR_DIM),
+                    Some(metadata),
+                )
+                .await
+                .expect("Failed to add vector");
+        }
+        store
+    });
+    let query_vector = generate_random_vector(VECTOR_DIM);
+
+    group.bench_function("query", |b| {
+        b.to_async(&rt).iter(|| async {
+            store
+                .query(black_box(&query_vector), black_box(100))
+                .await
+                .expect("Failed to query")
+        });
+    });
+
+    group.bench_function("query_lazy", |b| {
+        b.to_async(&rt).iter(|| async {
+            store
+                .query_lazy(black_box(&query_vector), black_box(100))
+                .await
+                .expect("Failed to query")
+        });
+    });
+
+    rt.block_on(async {
+        let _ = store.close().await;
+    });
+
+    group.finish();
+}
+
+fn bench_query_ann(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("query_ann");
+    let num_vectors = 50_000;
+    group.sample_size(10);
+
+    let store = rt.block_on(async {
+        let store = setup_store().await;
+        let items = (0..num_vectors)
+            .map(|i| {
+                (
+                    format!("doc{}", i),
+                    generate_random_vector(VECTOR_DIM),
+                    None,
+                )
+            })
+            .collect();
+        store
+            .batch_add(items, DuplicatePolicy::KeepLast)
+            .await
+            .expect("Failed to add batch");
+        store
+            .build_ann_index()
+            .await
+            .expect("Failed to build index");
+        store
+    });
+    let query_vector = generate_random_vector(VECTOR_DIM);
+
+    group.bench_function("query", |b| {
+        b.to_async(&rt).iter(|| async {
+            store
+                .query(black_box(&query_vector), black_box(10))
+                .await
+                .expect("Failed to query")
+        });
+    });
+
+    for ef in [32, 128] {
+        group.bench_with_input(BenchmarkId::new("query_ann", ef), &ef, |b, &ef| {
+            b.to_async(&rt).iter(|| async {
+                store
+                    .query_ann(black_box(&query_vector), black_box(10), ef)
+                    .await
+                    .expect("Failed to query")
+            });
+        });
+    }
+
+    rt.block_on(async {
+        let _ = store.close().await;
+    });
+    group.finish();
+}
+
+fn bench_query_batch(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("query_batch");
+    let num_queries = 50;
+    group.throughput(Throughput::Elements(num_queries as u64));
+    group.sample_size(10);
+
+    let store = rt.block_on(setup_store_with_vectors(1000));
+    let queries: Vec<Vec<f32>> = (0..num_queries)
+        .map(|_| generate_random_vector(VECTOR_DIM))
+        .collect();
+
+    group.bench_function("serial_query", |b| {
+        b.to_async(&rt).iter(|| async {
+            for query in &queries {
+                store
+                    .query(black_box(query), black_box(10))
+                    .await
+                    .expect("Failed to query");
+            }
+        });
+    });
+
+    group.bench_function("query_batch", |b| {
+        b.to_async(&rt).iter(|| async {
+            store
+                .query_batch(black_box(&queries), black_box(10))
+                .await
+                .expect("Failed to query")
+        });
+    });
+
+    rt.block_on(async {
+        let _ = store.close().await;
+    });
+
+    group.finish();
+}
+
+fn bench_first_query_warmup(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("first_query");
+    let num_vectors = 10_000;
+    group.sample_size(10);
+
+    let object_store: Arc<dyn ObjectStore + 'static> = Arc::new(InMemory::new());
+    rt.block_on(async {
+        let store = VectorStore::open("/bench/warmup", object_store.clone())
+            .await
+            .expect("Failed to open store");
+        let items = (0..num_vectors)
+            .map(|i| {
+                (
+                    format!("doc{}", i),
+                    generate_random_vector(VECTOR_DIM),
+                    Some(generate_random_metadata()),
+                )
+            })
+            .collect();
+        store
+            .batch_add(items, DuplicatePolicy::KeepLast)
+            .await
+            .expect("Failed to add batch");
+        store.close().await.expect("Failed to close store");
+    });
+    let query_vector = generate_random_vector(VECTOR_DIM);
+
+    // Each iteration reopens the store and times only its first query
+    for warm in [false, true] {
+        let name = if warm { "after_warmup" } else { "cold" };
+        group.bench_function(name, |b| {
+            b.to_async(&rt).iter_custom(|iters| {
+                let object_store = object_store.clone();
+                let query_vector = query_vector.clone();
+                async move {
+                    let mut total = std::time::Duration::ZERO;
+                    for _ in 0..iters {
+                        let store = VectorStore::open("/bench/warmup", object_store.clone())
+                            .await
+                            .expect("Failed to open store");
+                        if warm {
+                            store.warmup().await.expect("Failed to warm up");
+                        }
+                        let start = std::time::Instant::now();
+                        black_box(
+                            store
+                                .query(black_box(&query_vector), black_box(10))
+                                .await
+                                .expect("Failed to query"),
+                        );
+                        total += start.elapsed();
+                        store.close().await.expect("Failed to close store");
+                    }
+                    total
+                }
+            });
+        });
+    }
+
+    group.finish();
+}
+
 criterion_group!(
     benches,
     bench_write_latency,
     bench_write_throughput,
+    bench_batch_add,
+    bench_add_batch_vs_add,
     bench_query_latency,
     bench_query_varying_k,
+    bench_query_large_k,
+    bench_query_large_metadata,
     bench_query_throughput,
+    bench_repeated_query,
+    bench_query_batch,
+    bench_query_parallel,
+    bench_query_metric,
+    bench_query_after_churn,
+    bench_query_ann,
+    bench_first_query_warmup,
 );
 
 criterion_main!(benches);
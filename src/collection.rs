@@ -0,0 +1,55 @@
+use crate::errors::VectorStoreError;
+use crate::store::{DEFAULT_DIM, QueryResult, VectorStore};
+
+/// A namespace used through the same record-level calls as the default one,
+/// started with [`VectorStore::collection`]. Records in one collection never
+/// show up in another's queries.
+///
+/// The namespace is created on first use; its dimension is set by the first
+/// vector written, as for the default namespace. Names that contain `:` need
+/// [`crate::KeyEncoding::Escaped`] to stay isolated.
+pub struct Collection<'a> {
+    store: &'a VectorStore,
+    name: String,
+}
+
+impl<'a> Collection<'a> {
+    pub(crate) fn new(store: &'a VectorStore, name: &str) -> Self {
+        Self {
+            store,
+            name: name.to_string(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns `true` if a record with `id` was replaced
+    pub async fn add(
+        &self,
+        id: &str,
+        vector: Vec<f32>,
+        metadata: Option<serde_json::Value>,
+    ) -> Result<bool, VectorStoreError> {
+        self.store.add_to(&self.name, id, vector, metadata).await
+    }
+
+    pub async fn query(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        self.store.ensure_namespace(&self.name, DEFAULT_DIM).await?;
+        let items = self.store.query_ns(&self.name, query_vector, k).await?;
+        Ok(items
+            .into_iter()
+            .map(|item| QueryResult {
+                id: item.id,
+                score: item.score,
+                metadata: item.attributes,
+                vector: None,
+            })
+            .collect())
+    }
+}
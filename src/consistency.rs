@@ -0,0 +1,52 @@
+use crate::errors::VectorStoreError;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::{Instant, sleep};
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(5);
+const MAX_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Re-run `read` until it finds something or `timeout` elapses.
+///
+/// Meant for reads issued right after a write against an eventually
+/// consistent backend, where a missing record may just not be visible yet.
+/// Errors are returned immediately; only `Ok(None)` is retried. The final
+/// attempt happens at or after the deadline, so a record that shows up late
+/// is still seen.
+pub async fn retry_until_visible<T, F, Fut>(
+    timeout: Duration,
+    mut read: F,
+) -> Result<Option<T>, VectorStoreError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Option<T>, VectorStoreError>>,
+{
+    let deadline = Instant::now() + timeout;
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        if let Some(found) = read().await? {
+            return Ok(Some(found));
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(None);
+        }
+        sleep(backoff.min(deadline - now)).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Check that reading `key` back returned exactly the bytes that were written.
+pub fn verify_write(
+    key: &[u8],
+    expected: &[u8],
+    actual: Option<&[u8]>,
+) -> Result<(), VectorStoreError> {
+    if actual == Some(expected) {
+        Ok(())
+    } else {
+        Err(VectorStoreError::WriteVerificationFailed(
+            String::from_utf8_lossy(key).into_owned(),
+        ))
+    }
+}
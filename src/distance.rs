@@ -0,0 +1,193 @@
+//! The scoring functions the store ranks records with, for scoring vectors
+//! outside it (e.g. reranking candidates) exactly as a query would.
+
+pub use crate::models::{DistanceMetric, SparseVector};
+
+/// Independent accumulators per pass. Summing in fixed-width lanes instead of
+/// one running total lets LLVM turn the loops below into SIMD multiplies and
+/// adds on any target that has them, and plain scalar code on those that don't.
+const LANES: usize = 8;
+
+/// A way of scoring one vector against another, for metrics the built-in
+/// [`DistanceMetric`]s don't cover. Set one with
+/// [`VectorStoreOptions::distance`](crate::VectorStoreOptions::distance).
+///
+/// Every [`DistanceMetric`] is itself a `Distance`.
+pub trait Distance: std::fmt::Debug + Send + Sync {
+    /// Score `b` against `a`
+    fn score(&self, a: &[f32], b: &[f32]) -> f32;
+
+    /// Whether a larger score is a closer match. Distance-style metrics
+    /// return `false`, so queries keep their smallest scores.
+    fn higher_is_better(&self) -> bool;
+}
+
+impl Distance for DistanceMetric {
+    fn score(&self, a: &[f32], b: &[f32]) -> f32 {
+        similarity(*self, a, b)
+    }
+
+    fn higher_is_better(&self) -> bool {
+        DistanceMetric::higher_is_better(*self)
+    }
+}
+
+/// Score `b` against `a`. A distance rather than a similarity for
+/// [`DistanceMetric::Euclidean`], [`DistanceMetric::SquaredEuclidean`],
+/// [`DistanceMetric::Manhattan`] and [`DistanceMetric::Hamming`]; see
+/// [`DistanceMetric::higher_is_better`].
+pub fn similarity(metric: DistanceMetric, a: &[f32], b: &[f32]) -> f32 {
+    match metric {
+        DistanceMetric::Cosine => cosine_similarity(a, b),
+        DistanceMetric::DotProduct => dot_product(a, b),
+        DistanceMetric::Euclidean => euclidean_distance(a, b),
+        DistanceMetric::SquaredEuclidean => squared_euclidean_distance(a, b),
+        DistanceMetric::Manhattan => manhattan_distance(a, b),
+        DistanceMetric::Hamming => hamming_distance(a, b),
+    }
+}
+
+/// Each component's term in `metric`'s score of `b` against `a`: products
+/// for [`DistanceMetric::DotProduct`] and [`DistanceMetric::Cosine`] (before
+/// dividing by the norms), squared differences for the Euclidean metrics
+/// (before the square root for [`DistanceMetric::Euclidean`]), absolute
+/// differences for Manhattan and sign mismatches for Hamming
+pub(crate) fn dimension_terms(metric: DistanceMetric, a: &[f32], b: &[f32]) -> Vec<f32> {
+    let term = |x: f32, y: f32| match metric {
+        DistanceMetric::Cosine | DistanceMetric::DotProduct => x * y,
+        DistanceMetric::Euclidean | DistanceMetric::SquaredEuclidean => (x - y) * (x - y),
+        DistanceMetric::Manhattan => (x - y).abs(),
+        DistanceMetric::Hamming => ((x > 0.0) != (y > 0.0)) as u8 as f32,
+    };
+    a.iter().zip(b).map(|(&x, &y)| term(x, y)).collect()
+}
+
+/// Orders two scores best first, where `higher_is_better` is the scoring
+/// [`Distance`]'s. NaN ranks after every other score either way.
+pub(crate) fn compare_scores(higher_is_better: bool, a: f32, b: f32) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        _ if higher_is_better => b.partial_cmp(&a).unwrap_or(Ordering::Equal),
+        _ => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+    }
+}
+
+/// Sum of `term(a[i], b[i])` over equal-length slices, `LANES` at a time
+/// with a scalar loop for the tail
+#[inline(always)]
+fn lane_sum(a: &[f32], b: &[f32], term: impl Fn(f32, f32) -> f32) -> f32 {
+    let a_chunks = a.chunks_exact(LANES);
+    let b_chunks = b.chunks_exact(LANES);
+    let tail: f32 = a_chunks
+        .remainder()
+        .iter()
+        .zip(b_chunks.remainder())
+        .map(|(&x, &y)| term(x, y))
+        .sum();
+
+    let mut acc = [0.0f32; LANES];
+    for (x, y) in a_chunks.zip(b_chunks) {
+        for ((acc, &x), &y) in acc.iter_mut().zip(x).zip(y) {
+            *acc += term(x, y);
+        }
+    }
+    acc.iter().sum::<f32>() + tail
+}
+
+/// Sum of component products. Vectors of different lengths score 0.
+pub fn dot_product(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    lane_sum(a, b, |x, y| x * y)
+}
+
+/// L2 distance. Vectors of different lengths are infinitely far apart.
+pub fn euclidean_distance(a: &[f32], b: &[f32]) -> f32 {
+    squared_euclidean_distance(a, b).sqrt()
+}
+
+/// Sum of squared component differences: [`euclidean_distance`] squared
+pub fn squared_euclidean_distance(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return f32::INFINITY;
+    }
+    lane_sum(a, b, |x, y| (x - y) * (x - y))
+}
+
+/// L1 distance: the sum of absolute component differences
+pub fn manhattan_distance(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return f32::INFINITY;
+    }
+    lane_sum(a, b, |x, y| (x - y).abs())
+}
+
+/// Number of components where one of `a` and `b` is positive and the other
+/// isn't: the Hamming distance between their
+/// [`BinaryVector`](crate::BinaryVector)s
+pub fn hamming_distance(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return f32::INFINITY;
+    }
+    lane_sum(a, b, |x, y| ((x > 0.0) != (y > 0.0)) as u8 as f32)
+}
+
+/// Cosine of the angle between `a` and `b`, from -1.0 to 1.0. Empty
+/// vectors, vectors of different lengths, and any vector with zero
+/// magnitude score 0.
+///
+/// ```
+/// use smolpuff::distance;
+///
+/// let a = [3.0, 4.0];
+/// let b = [6.0, 8.0];
+/// assert!((distance::cosine_similarity(&a, &b) - 1.0).abs() < 1e-6);
+/// assert_eq!(distance::cosine_similarity(&a, &[0.0, 0.0]), 0.0);
+/// ```
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    cosine_similarity_with_norm(a, magnitude(a), b)
+}
+
+/// Cosine similarity where `a`'s magnitude is already known
+pub(crate) fn cosine_similarity_with_norm(a: &[f32], magnitude_a: f32, b: &[f32]) -> f32 {
+    cosine_similarity_with_norms(a, magnitude_a, b, magnitude(b))
+}
+
+/// Cosine similarity where both magnitudes are already known, so only the
+/// dot product is computed
+pub(crate) fn cosine_similarity_with_norms(
+    a: &[f32],
+    magnitude_a: f32,
+    b: &[f32],
+    magnitude_b: f32,
+) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+
+    if magnitude_a == 0.0 || magnitude_b == 0.0 {
+        return 0.0;
+    }
+
+    lane_sum(a, b, |x, y| x * y) / (magnitude_a * magnitude_b)
+}
+
+/// Dot product of two sparse vectors: the sum of weight products over the
+/// indices both have
+pub fn sparse_dot_product(a: &SparseVector, b: &SparseVector) -> f32 {
+    let (small, large) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    small
+        .iter()
+        .filter_map(|(index, x)| large.get(index).map(|y| x * y))
+        .sum()
+}
+
+/// L2 norm of `v`
+pub fn magnitude(v: &[f32]) -> f32 {
+    lane_sum(v, v, |x, _| x * x).sqrt()
+}
@@ -10,17 +10,48 @@ pub enum VectorStoreError {
     #[error("Serialization error: {0}")]
     SerializationError(#[from] serde_json::Error),
 
+    #[error("Metadata encoding error: {0}")]
+    MetadataEncoding(String),
+
     #[error("Namespace not found: {0}")]
     NamespaceNotFound(String),
 
     #[error("Namespace already exists: {0}")]
     NamespaceAlreadyExists(String),
 
+    #[error("Record not found: {0}")]
+    NotFound(String),
+
     #[error("Dimension mismatch: expected {expected}, got {got}")]
     DimensionMismatch { expected: usize, got: usize },
 
+    #[error("Vector dimension {got} exceeds the configured maximum of {max}")]
+    DimensionTooLarge { max: usize, got: usize },
+
     #[error("Invalid request: {0}")]
     InvalidRequest(String),
+
+    #[error("Invalid vector: {0}")]
+    InvalidVector(String),
+
+    #[error("Unsupported record format version {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("Write verification failed for key {0}")]
+    WriteVerificationFailed(String),
+
+    #[error("Object store error: {0}")]
+    ObjectStore(#[from] object_store::Error),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Background task failed: {0}")]
+    TaskFailed(#[from] tokio::task::JoinError),
+
+    #[cfg(feature = "parquet")]
+    #[error("Parquet error: {0}")]
+    ParquetError(#[from] parquet::errors::ParquetError),
 }
 
 impl IntoResponse for VectorStoreError {
@@ -30,12 +61,37 @@ impl IntoResponse for VectorStoreError {
             VectorStoreError::SerializationError(_) => {
                 (StatusCode::INTERNAL_SERVER_ERROR, self.to_string())
             }
+            VectorStoreError::MetadataEncoding(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string())
+            }
             VectorStoreError::NamespaceNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
             VectorStoreError::NamespaceAlreadyExists(_) => (StatusCode::CONFLICT, self.to_string()),
+            VectorStoreError::NotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
             VectorStoreError::DimensionMismatch { .. } => {
                 (StatusCode::BAD_REQUEST, self.to_string())
             }
+            VectorStoreError::DimensionTooLarge { .. } => {
+                (StatusCode::BAD_REQUEST, self.to_string())
+            }
             VectorStoreError::InvalidRequest(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            VectorStoreError::InvalidVector(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            VectorStoreError::UnsupportedVersion(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string())
+            }
+            VectorStoreError::WriteVerificationFailed(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string())
+            }
+            VectorStoreError::ObjectStore(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string())
+            }
+            VectorStoreError::Io(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
+            VectorStoreError::TaskFailed(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string())
+            }
+            #[cfg(feature = "parquet")]
+            VectorStoreError::ParquetError(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string())
+            }
         };
 
         (status, axum::Json(serde_json::json!({ "error": message }))).into_response()
@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A predicate on a record's metadata, for [`VectorStore::query_where`].
+/// Fields are top-level metadata keys. Filters are plain data, so they can
+/// be built by clients and sent as JSON:
+///
+/// ```ignore
+/// {"and": [{"or": [{"eq": ["lang", "en"]}, {"eq": ["lang", "de"]}]},
+///          {"range": ["year", 2020, 2024]}]}
+/// ```
+///
+/// [`VectorStore::query_where`]: crate::VectorStore::query_where
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    /// `field` equals the value. Numbers compare by value, so `1` equals `1.0`.
+    Eq(String, Value),
+    /// `field` is a number in `min..=max`
+    Range(String, f64, f64),
+    /// Every filter matches; true when empty
+    And(Vec<Filter>),
+    /// At least one filter matches; false when empty
+    Or(Vec<Filter>),
+}
+
+impl Filter {
+    /// Whether `metadata` passes the filter. A missing field matches no
+    /// `Eq` or `Range`.
+    pub fn matches(&self, metadata: &Value) -> bool {
+        match self {
+            Filter::Eq(field, expected) => metadata.get(field).is_some_and(|actual| {
+                match (actual.as_f64(), expected.as_f64()) {
+                    (Some(a), Some(b)) => a == b,
+                    _ => actual == expected,
+                }
+            }),
+            Filter::Range(field, min, max) => metadata
+                .get(field)
+                .and_then(Value::as_f64)
+                .is_some_and(|value| (*min..=*max).contains(&value)),
+            Filter::And(filters) => filters.iter().all(|f| f.matches(metadata)),
+            Filter::Or(filters) => filters.iter().any(|f| f.matches(metadata)),
+        }
+    }
+}
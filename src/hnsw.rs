@@ -0,0 +1,261 @@
+use crate::distance::Distance;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::sync::Arc;
+
+/// Tuning for the in-memory HNSW index, set with
+/// [`VectorStoreOptions::hnsw`](crate::VectorStoreOptions::hnsw).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HnswParams {
+    /// Links kept per node on the upper layers; the bottom layer keeps twice
+    /// as many. More links raise recall and memory use.
+    pub m: usize,
+    /// Candidates considered while linking a new node. Higher builds a
+    /// better graph, more slowly.
+    pub ef_construction: usize,
+}
+
+impl Default for HnswParams {
+    fn default() -> Self {
+        Self {
+            m: 16,
+            ef_construction: 200,
+        }
+    }
+}
+
+/// A node's distance from the point being searched for; smaller is closer
+#[derive(Debug, Clone, Copy)]
+struct Near {
+    dist: f32,
+    node: u32,
+}
+
+impl PartialEq for Near {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Near {}
+
+impl PartialOrd for Near {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Near {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.dist
+            .total_cmp(&other.dist)
+            .then(self.node.cmp(&other.node))
+    }
+}
+
+/// Hierarchical navigable small world graph over the default namespace's
+/// vectors (Malkov & Yashunin, 2016), held entirely in memory.
+///
+/// Replacing or removing a record leaves its old node in the graph as a
+/// tombstone, still walked through but never returned, so heavy churn
+/// slowly degrades the graph until it is rebuilt.
+pub(crate) struct Hnsw {
+    params: HnswParams,
+    distance: Arc<dyn Distance>,
+    ids: Vec<String>,
+    vectors: Vec<Vec<f32>>,
+    // links[node][layer], for every layer from 0 up to the node's own
+    links: Vec<Vec<Vec<u32>>>,
+    deleted: Vec<bool>,
+    by_id: HashMap<String, u32>,
+    entry: Option<u32>,
+    level_mult: f64,
+    rng: StdRng,
+}
+
+impl Hnsw {
+    pub(crate) fn new(params: HnswParams, distance: Arc<dyn Distance>) -> Self {
+        let m = params.m.max(2);
+        Self {
+            params: HnswParams { m, ..params },
+            distance,
+            ids: Vec::new(),
+            vectors: Vec::new(),
+            links: Vec::new(),
+            deleted: Vec::new(),
+            by_id: HashMap::new(),
+            entry: None,
+            level_mult: 1.0 / (m as f64).ln(),
+            rng: StdRng::seed_from_u64(0x5eed),
+        }
+    }
+
+    pub(crate) fn params(&self) -> HnswParams {
+        self.params
+    }
+
+    pub(crate) fn distance(&self) -> Arc<dyn Distance> {
+        self.distance.clone()
+    }
+
+    fn dist(&self, query: &[f32], node: u32) -> f32 {
+        let score = self.distance.score(query, &self.vectors[node as usize]);
+        let dist = if self.distance.higher_is_better() {
+            -score
+        } else {
+            score
+        };
+        if dist.is_nan() { f32::INFINITY } else { dist }
+    }
+
+    fn max_links(&self, layer: usize) -> usize {
+        if layer == 0 {
+            self.params.m * 2
+        } else {
+            self.params.m
+        }
+    }
+
+    fn top_layer(&self, node: u32) -> usize {
+        self.links[node as usize].len() - 1
+    }
+
+    /// Add `vector` under `id`, replacing any earlier node for `id`
+    pub(crate) fn insert(&mut self, id: &str, vector: Vec<f32>) {
+        self.remove(id);
+
+        let level =
+            (-self.rng.r#gen::<f64>().max(f64::MIN_POSITIVE).ln() * self.level_mult) as usize;
+        let node = self.vectors.len() as u32;
+        self.ids.push(id.to_string());
+        self.vectors.push(vector);
+        self.links.push(vec![Vec::new(); level + 1]);
+        self.deleted.push(false);
+        self.by_id.insert(id.to_string(), node);
+
+        let Some(entry) = self.entry else {
+            self.entry = Some(node);
+            return;
+        };
+
+        let query = self.vectors[node as usize].clone();
+        let top = self.top_layer(entry);
+        let mut nearest = vec![Near {
+            dist: self.dist(&query, entry),
+            node: entry,
+        }];
+        for layer in (level + 1..=top).rev() {
+            nearest = self.search_layer(&query, &nearest, 1, layer);
+        }
+        for layer in (0..=level.min(top)).rev() {
+            nearest = self.search_layer(&query, &nearest, self.params.ef_construction, layer);
+            let neighbors: Vec<u32> = nearest
+                .iter()
+                .take(self.max_links(layer))
+                .map(|n| n.node)
+                .collect();
+            for &neighbor in &neighbors {
+                self.link(neighbor, node, layer);
+            }
+            self.links[node as usize][layer] = neighbors;
+        }
+
+        if level > top {
+            self.entry = Some(node);
+        }
+    }
+
+    /// Link `from` to `to` on `layer`, dropping `from`'s farthest link if
+    /// that takes it over the limit
+    fn link(&mut self, from: u32, to: u32, layer: usize) {
+        self.links[from as usize][layer].push(to);
+        if self.links[from as usize][layer].len() <= self.max_links(layer) {
+            return;
+        }
+
+        let origin = &self.vectors[from as usize];
+        let mut ranked: Vec<Near> = self.links[from as usize][layer]
+            .iter()
+            .map(|&n| Near {
+                dist: self.dist(origin, n),
+                node: n,
+            })
+            .collect();
+        ranked.sort();
+        ranked.truncate(self.max_links(layer));
+        self.links[from as usize][layer] = ranked.into_iter().map(|n| n.node).collect();
+    }
+
+    /// Tombstone the node for `id`, if any
+    pub(crate) fn remove(&mut self, id: &str) {
+        if let Some(node) = self.by_id.remove(id) {
+            self.deleted[node as usize] = true;
+        }
+    }
+
+    /// The `ef` nodes on `layer` closest to `query` reachable from
+    /// `entries`, closest first. Tombstoned nodes are included.
+    fn search_layer(&self, query: &[f32], entries: &[Near], ef: usize, layer: usize) -> Vec<Near> {
+        let ef = ef.max(1);
+        let mut visited: HashSet<u32> = entries.iter().map(|n| n.node).collect();
+        let mut candidates: BinaryHeap<Reverse<Near>> =
+            entries.iter().copied().map(Reverse).collect();
+        let mut found: BinaryHeap<Near> = entries.iter().copied().collect();
+        while found.len() > ef {
+            found.pop();
+        }
+
+        while let Some(Reverse(closest)) = candidates.pop() {
+            if found.len() >= ef && found.peek().is_some_and(|far| closest.dist > far.dist) {
+                break;
+            }
+            for &neighbor in &self.links[closest.node as usize][layer] {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let near = Near {
+                    dist: self.dist(query, neighbor),
+                    node: neighbor,
+                };
+                if found.len() < ef || found.peek().is_some_and(|far| near.dist < far.dist) {
+                    candidates.push(Reverse(near));
+                    found.push(near);
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+
+        found.into_sorted_vec()
+    }
+
+    /// Up to `k` live records approximately closest to `query`, best first,
+    /// with their scores under the index's distance. `ef` bounds the
+    /// candidate list on the bottom layer; raising it trades speed for
+    /// recall.
+    pub(crate) fn search(&self, query: &[f32], k: usize, ef: usize) -> Vec<(String, f32)> {
+        let Some(entry) = self.entry else {
+            return Vec::new();
+        };
+
+        let mut nearest = vec![Near {
+            dist: self.dist(query, entry),
+            node: entry,
+        }];
+        for layer in (1..=self.top_layer(entry)).rev() {
+            nearest = self.search_layer(query, &nearest, 1, layer);
+        }
+        self.search_layer(query, &nearest, ef.max(k), 0)
+            .into_iter()
+            .filter(|n| !self.deleted[n.node as usize])
+            .take(k)
+            .map(|n| {
+                let score = self.distance.score(query, &self.vectors[n.node as usize]);
+                (self.ids[n.node as usize].clone(), score)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// How namespace names and record ids are written into storage keys.
+///
+/// Keys look like `ns:{ns}:meta` and `ns:{ns}:{kind}:{id}`. With `Raw`, a
+/// namespace name containing `:` would land inside another namespace's scan
+/// range (`ns:a:vec:x:meta` is both namespace `a:vec:x`'s metadata and id
+/// `x:meta` in namespace `a`), so creating one is rejected; ids come last in
+/// the key and may contain anything. `Escaped` percent-encodes `:`, `;`, `%`
+/// and control characters so any string is safe in either place. The two
+/// encodings are not interchangeable, so a store remembers the one it was
+/// created with and refuses to open with the other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyEncoding {
+    /// Names and ids are used verbatim, as in stores written before this option existed
+    #[default]
+    Raw,
+    /// Names and ids are percent-encoded
+    Escaped,
+}
+
+impl KeyEncoding {
+    pub(crate) fn meta_key(self, ns: &str) -> String {
+        format!("ns:{}:meta", self.encode(ns))
+    }
+
+    pub(crate) fn record_key(self, ns: &str, kind: &str, id: &str) -> String {
+        format!("ns:{}:{kind}:{}", self.encode(ns), self.encode(id))
+    }
+
+    /// Scan bounds covering every `ns:{ns}:{kind}:*` key. `;` sorts right after `:`,
+    /// so the end bound is the first key past the prefix.
+    pub(crate) fn range(self, ns: &str, kind: &str) -> (String, String) {
+        let ns = self.encode(ns);
+        (format!("ns:{ns}:{kind}:"), format!("ns:{ns}:{kind};"))
+    }
+
+    /// Recover the id from a key produced by [`KeyEncoding::record_key`], given
+    /// the prefix returned by [`KeyEncoding::range`]
+    pub(crate) fn id_from_key(self, prefix: &str, key: &[u8]) -> String {
+        let key_str = String::from_utf8_lossy(key);
+        let id = key_str.strip_prefix(prefix).unwrap_or("");
+        match self {
+            KeyEncoding::Raw => id.to_string(),
+            KeyEncoding::Escaped => unescape(id),
+        }
+    }
+
+    fn encode(self, part: &str) -> Cow<'_, str> {
+        match self {
+            KeyEncoding::Raw => Cow::Borrowed(part),
+            KeyEncoding::Escaped => escape(part),
+        }
+    }
+}
+
+fn needs_escape(c: char) -> bool {
+    matches!(c, ':' | ';' | '%') || c.is_control()
+}
+
+fn escape(part: &str) -> Cow<'_, str> {
+    if !part.chars().any(needs_escape) {
+        return Cow::Borrowed(part);
+    }
+    let mut out = String::with_capacity(part.len() + 8);
+    for c in part.chars() {
+        if needs_escape(c) {
+            let mut buf = [0; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                out.push_str(&format!("%{byte:02X}"));
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    Cow::Owned(out)
+}
+
+fn unescape(part: &str) -> String {
+    let bytes = part.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = part
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            out.push(byte);
+            i += 3;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
@@ -1,8 +1,35 @@
+pub mod collection;
+pub mod consistency;
+pub mod distance;
 pub mod errors;
+pub mod filter;
 pub mod handlers;
+pub mod hnsw;
+pub mod keys;
+pub mod lsh;
 pub mod metrics;
 pub mod models;
+pub mod options;
+pub mod quantization;
+pub mod query;
+pub mod serialization;
+pub mod session;
 pub mod store;
 
+pub use collection::Collection;
+pub use distance::Distance;
 pub use errors::VectorStoreError;
-pub use store::VectorStore;
+pub use filter::Filter;
+pub use hnsw::HnswParams;
+pub use keys::KeyEncoding;
+pub use lsh::LshParams;
+pub use models::{DistanceMetric, DuplicatePolicy, SparseVector, Standardization, VectorEncoding};
+pub use options::VectorStoreOptions;
+pub use quantization::{BinaryVector, PqParams, ProductQuantizer, QuantizedVector};
+pub use query::{QueryBuilder, ScoreNormalization, SearchResults};
+pub use serialization::SerializationFormat;
+pub use session::SessionQuery;
+pub use store::{
+    ExplainedResult, IvfSuggestion, LazyQueryResult, MetricComparison, PreparedQuery, QueryProfile,
+    QueryResult, QueryStats, RecoveryReport, StoreInfo, VectorRecord, VectorStore, recall_at_k,
+};
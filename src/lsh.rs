@@ -0,0 +1,70 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+/// Tuning for the random-projection LSH index, set with
+/// [`VectorStoreOptions::lsh`](crate::VectorStoreOptions::lsh).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LshParams {
+    /// Independent hash tables. A query scores every record sharing a bucket
+    /// with it in any table, so more tables raise recall and query cost.
+    pub tables: usize,
+    /// Hyperplanes per table, one hash bit each, from 1 to 64. More make
+    /// buckets smaller: fewer candidates, and fewer true neighbors among them.
+    pub hyperplanes: usize,
+    /// Seed the hyperplanes are drawn from
+    pub seed: u64,
+}
+
+impl Default for LshParams {
+    fn default() -> Self {
+        Self {
+            tables: 8,
+            hyperplanes: 8,
+            seed: 0x5eed,
+        }
+    }
+}
+
+/// The random hyperplanes of every table, persisted under `meta:lsh` so a
+/// record's buckets stay the same across reopens
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Lsh {
+    params: LshParams,
+    // Normals of the hyperplanes, table by table
+    planes: Vec<Vec<f32>>,
+}
+
+impl Lsh {
+    pub(crate) fn new(params: LshParams, dim: usize) -> Self {
+        let mut rng = StdRng::seed_from_u64(params.seed);
+        // Gaussian components (Box-Muller) make the normals uniform in direction
+        let mut gaussian = || {
+            let u: f32 = rng.gen_range(f32::EPSILON..1.0);
+            let v: f32 = rng.r#gen();
+            (-2.0 * u.ln()).sqrt() * (std::f32::consts::TAU * v).cos()
+        };
+        let planes = (0..params.tables * params.hyperplanes)
+            .map(|_| (0..dim).map(|_| gaussian()).collect())
+            .collect();
+        Self { params, planes }
+    }
+
+    pub(crate) fn params(&self) -> LshParams {
+        self.params
+    }
+
+    /// The bucket `vector` falls in, in each table: bit `i` is set when it
+    /// lies on the positive side of the table's `i`th hyperplane
+    pub(crate) fn buckets(&self, vector: &[f32]) -> Vec<u64> {
+        self.planes
+            .chunks(self.params.hyperplanes.max(1))
+            .map(|table| {
+                table.iter().enumerate().fold(0, |bucket, (i, normal)| {
+                    let side: f32 = normal.iter().zip(vector).map(|(n, x)| n * x).sum();
+                    if side > 0.0 { bucket | 1 << i } else { bucket }
+                })
+            })
+            .collect()
+    }
+}
@@ -1,5 +1,118 @@
+use crate::errors::VectorStoreError;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How two vectors are compared.
+///
+/// [`Cosine`](Self::Cosine) and [`DotProduct`](Self::DotProduct) are
+/// similarities, where higher scores mean "more similar";
+/// [`Euclidean`](Self::Euclidean),
+/// [`SquaredEuclidean`](Self::SquaredEuclidean),
+/// [`Manhattan`](Self::Manhattan) and [`Hamming`](Self::Hamming) are
+/// distances, where lower scores do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DistanceMetric {
+    #[default]
+    Cosine,
+    DotProduct,
+    Euclidean,
+    /// Euclidean distance without the final square root. Ranks exactly like
+    /// [`Euclidean`](Self::Euclidean) and is cheaper to compute; take the
+    /// square root of the returned scores if true distances are needed.
+    SquaredEuclidean,
+    Manhattan,
+    /// Number of components whose signs differ; pairs with
+    /// [`VectorEncoding::Binary`]
+    Hamming,
+}
+
+impl DistanceMetric {
+    /// The name stored in [`NamespaceMetadata::distance`]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DistanceMetric::Cosine => "cosine",
+            DistanceMetric::DotProduct => "dot_product",
+            DistanceMetric::Euclidean => "euclidean",
+            DistanceMetric::SquaredEuclidean => "squared_euclidean",
+            DistanceMetric::Manhattan => "manhattan",
+            DistanceMetric::Hamming => "hamming",
+        }
+    }
+
+    /// Whether a larger score is a closer match
+    pub fn higher_is_better(self) -> bool {
+        !matches!(
+            self,
+            DistanceMetric::Euclidean
+                | DistanceMetric::SquaredEuclidean
+                | DistanceMetric::Manhattan
+                | DistanceMetric::Hamming
+        )
+    }
+}
+
+impl std::str::FromStr for DistanceMetric {
+    type Err = VectorStoreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "cosine" => Ok(DistanceMetric::Cosine),
+            "dot_product" | "dot" => Ok(DistanceMetric::DotProduct),
+            "euclidean" | "l2" => Ok(DistanceMetric::Euclidean),
+            "squared_euclidean" | "l2sq" => Ok(DistanceMetric::SquaredEuclidean),
+            "manhattan" | "l1" => Ok(DistanceMetric::Manhattan),
+            "hamming" => Ok(DistanceMetric::Hamming),
+            _ => Err(VectorStoreError::InvalidRequest(format!(
+                "Unknown distance metric: {s}"
+            ))),
+        }
+    }
+}
+
+/// How vectors are laid out on disk. Every record says which layout it
+/// uses, so changing this only affects vectors written afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VectorEncoding {
+    /// Full-precision `f32`s
+    #[default]
+    F32,
+    /// One `i8` per component plus a per-vector scale (see
+    /// [`crate::QuantizedVector`]): about 4x smaller, at some cost in recall.
+    /// Cosine and dot-product queries quantize the query once and score
+    /// these records in integer space.
+    Int8,
+    /// Half-precision floats: 2 bytes per component, about 3 significant
+    /// decimal digits. Components beyond the `f16` range (±65504) are
+    /// clamped to it.
+    F16,
+    /// One bit per component, its sign (see [`crate::BinaryVector`]): up to
+    /// 32x smaller. Magnitudes are lost, so only the direction of a vector
+    /// survives, and coarsely: with [`DistanceMetric::Hamming`] it makes a
+    /// fast first-stage filter whose top results land in the right
+    /// neighborhood, but whose order within it is rough and full of ties.
+    /// Rescore the candidates against full vectors when the order matters.
+    Binary,
+}
+
+/// Non-zero weights of a sparse vector (e.g. SPLADE or BM25 term weights)
+/// by dimension index, as stored with [`crate::VectorStore::add_sparse`].
+/// Missing indices are zero.
+pub type SparseVector = HashMap<u32, f32>;
+
+/// What [`crate::VectorStore::batch_add`] does when an id appears more than
+/// once in the same batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Reject the whole batch before anything is written
+    Error,
+    /// Keep the first record with each id
+    KeepFirst,
+    /// Keep the last record with each id
+    KeepLast,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NamespaceMetadata {
@@ -8,6 +121,27 @@ pub struct NamespaceMetadata {
     pub distance: String,
     pub approx_row_count: u64,
     pub created_at: DateTime<Utc>,
+    /// Set by [`crate::VectorStore::standardize`]; when present, vectors are
+    /// standardized on write and queries are standardized before scoring
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub standardization: Option<Standardization>,
+}
+
+/// Per-dimension statistics used to map `x` to `(x - mean) / std`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Standardization {
+    pub mean: Vec<f32>,
+    pub std: Vec<f32>,
+}
+
+impl Standardization {
+    pub fn apply(&self, vector: &[f32]) -> Vec<f32> {
+        vector
+            .iter()
+            .zip(self.mean.iter().zip(&self.std))
+            .map(|(x, (mean, std))| (x - mean) / std)
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
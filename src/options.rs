@@ -0,0 +1,171 @@
+use crate::distance::Distance;
+use crate::errors::VectorStoreError;
+use crate::hnsw::HnswParams;
+use crate::keys::KeyEncoding;
+use crate::lsh::LshParams;
+use crate::models::{DistanceMetric, VectorEncoding};
+use crate::serialization::SerializationFormat;
+use crate::store::VectorStore;
+use object_store::ObjectStore;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Builder for opening a [`VectorStore`] with non-default settings.
+///
+/// `VectorStoreOptions::default()` matches the behavior of [`VectorStore::open`].
+#[derive(Debug, Clone, Default)]
+pub struct VectorStoreOptions {
+    pub(crate) max_dimension: Option<usize>,
+    pub(crate) expected_dim: Option<usize>,
+    pub(crate) batch_size: Option<usize>,
+    pub(crate) read_after_write_timeout: Option<Duration>,
+    pub(crate) key_encoding: Option<KeyEncoding>,
+    pub(crate) verify_writes: bool,
+    pub(crate) block_cache_bytes: Option<usize>,
+    pub(crate) metric: Option<DistanceMetric>,
+    pub(crate) distance: Option<Arc<dyn Distance>>,
+    pub(crate) query_concurrency: Option<usize>,
+    pub(crate) vector_encoding: VectorEncoding,
+    pub(crate) hnsw: Option<HnswParams>,
+    pub(crate) lsh: Option<LshParams>,
+    pub(crate) serialization: Option<SerializationFormat>,
+}
+
+impl VectorStoreOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject any vector longer than `max` on writes and queries, before it is
+    /// serialized or anything is written.
+    pub fn max_dimension(mut self, max: usize) -> Self {
+        self.max_dimension = Some(max);
+        self
+    }
+
+    /// Require every vector written or queried to have exactly `dim`
+    /// components, failing with [`VectorStoreError::DimensionMismatch`]
+    /// otherwise. When unset, each namespace takes the dimension of its
+    /// first vector.
+    pub fn expected_dim(mut self, dim: usize) -> Self {
+        self.expected_dim = Some(dim);
+        self
+    }
+
+    /// Number of records [`VectorStore::batch_add`] puts in each write batch.
+    /// Smaller batches bound peak memory on huge ingests; larger ones amortize
+    /// more writes per flush.
+    pub fn batch_size(mut self, size: usize) -> Self {
+        self.batch_size = Some(size);
+        self
+    }
+
+    /// Retry point lookups that come back empty for up to `timeout`, for
+    /// object stores that don't guarantee read-after-write consistency.
+    /// Off by default; strongly consistent backends never need it.
+    pub fn read_after_write_timeout(mut self, timeout: Duration) -> Self {
+        self.read_after_write_timeout = Some(timeout);
+        self
+    }
+
+    /// How namespace names and ids are encoded into storage keys. See
+    /// [`KeyEncoding`]. Like [`VectorStoreOptions::metric`] it is persisted
+    /// when the store is created, so reopening needs no setting and a
+    /// different encoding is an error. Defaults to [`KeyEncoding::Raw`].
+    pub fn key_encoding(mut self, encoding: KeyEncoding) -> Self {
+        self.key_encoding = Some(encoding);
+        self
+    }
+
+    /// Read every record back right after [`VectorStore::upsert`] writes it
+    /// and fail with [`VectorStoreError::WriteVerificationFailed`] if it
+    /// differs. Doubles the cost of each write, so it is off by default.
+    pub fn verify_writes(mut self, verify: bool) -> Self {
+        self.verify_writes = verify;
+        self
+    }
+
+    /// Cap slatedb's in-memory block cache at `bytes`. When unset, slatedb's
+    /// default cache settings apply. A smaller cache bounds memory when many
+    /// stores share a process, at the cost of colder queries: blocks that
+    /// don't fit are fetched from the object store again on every scan.
+    pub fn block_cache_bytes(mut self, bytes: usize) -> Self {
+        self.block_cache_bytes = Some(bytes);
+        self
+    }
+
+    /// Metric used to score queries. It is persisted on first open, so this
+    /// can be left unset when reopening; setting it to a different metric
+    /// than the store was created with is an error. Defaults to
+    /// [`DistanceMetric::Cosine`].
+    pub fn metric(mut self, metric: DistanceMetric) -> Self {
+        self.metric = Some(metric);
+        self
+    }
+
+    /// Score queries with a custom [`Distance`] instead of the namespace's
+    /// metric. Every query method uses it, except where a metric is chosen
+    /// per query ([`crate::QueryBuilder::metric`],
+    /// [`VectorStore::query_with_metric`]) or the method is tied to one
+    /// ([`VectorStore::query_masked`]'s cosine, [`VectorStore::query_sparse`]'s
+    /// dot product). Unlike
+    /// [`VectorStoreOptions::metric`] it is not persisted, so it has to be
+    /// set again on every open.
+    pub fn distance(mut self, distance: impl Distance + 'static) -> Self {
+        self.distance = Some(Arc::new(distance));
+        self
+    }
+
+    /// Maximum number of chunks [`VectorStore::query_parallel`] scores at
+    /// once. Defaults to the number of available CPUs.
+    pub fn query_concurrency(mut self, tasks: usize) -> Self {
+        self.query_concurrency = Some(tasks);
+        self
+    }
+
+    /// How vectors are stored. The lossy encodings ([`VectorEncoding::Int8`],
+    /// [`VectorEncoding::F16`] and [`VectorEncoding::Binary`]) convert each
+    /// vector on write and queries score the stored approximation. Defaults
+    /// to [`VectorEncoding::F32`].
+    pub fn vector_encoding(mut self, encoding: VectorEncoding) -> Self {
+        self.vector_encoding = encoding;
+        self
+    }
+
+    /// Build an in-memory HNSW index over the default namespace when the
+    /// store opens, for [`VectorStore::query_ann`]. Opening then scans every
+    /// record once; afterwards writes keep the index up to date. Off by
+    /// default.
+    pub fn hnsw(mut self, params: HnswParams) -> Self {
+        self.hnsw = Some(params);
+        self
+    }
+
+    /// Hash default-namespace vectors into random-projection LSH buckets as
+    /// they are written, for [`VectorStore::query_lsh`]. Records already in
+    /// the store are hashed when it opens. The parameters and hyperplanes are
+    /// persisted, so like [`VectorStoreOptions::metric`] this can be left
+    /// unset when reopening, and different parameters are an error. Off by
+    /// default.
+    pub fn lsh(mut self, params: LshParams) -> Self {
+        self.lsh = Some(params);
+        self
+    }
+
+    /// How record metadata is encoded. Like [`VectorStoreOptions::metric`] it
+    /// is persisted when the store is created, so reopening needs no setting
+    /// and a different one is an error. Defaults to
+    /// [`SerializationFormat::Json`].
+    pub fn serialization(mut self, format: SerializationFormat) -> Self {
+        self.serialization = Some(format);
+        self
+    }
+
+    pub async fn build_and_open<P: AsRef<str>>(
+        self,
+        path: P,
+        object_store: Arc<dyn ObjectStore>,
+    ) -> Result<VectorStore, VectorStoreError> {
+        VectorStore::open_with_options(path, object_store, self).await
+    }
+}
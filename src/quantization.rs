@@ -0,0 +1,372 @@
+use crate::distance::{
+    DistanceMetric, dot_product, hamming_distance, manhattan_distance, squared_euclidean_distance,
+};
+use crate::errors::VectorStoreError;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::index::sample;
+use serde::{Deserialize, Serialize};
+
+/// A vector quantized to `i8` with one symmetric scale: `x ≈ value as f32 * scale`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantizedVector {
+    pub values: Vec<i8>,
+    pub scale: f32,
+}
+
+impl QuantizedVector {
+    /// Map `vector` onto `-127..=127`, scaled by its largest magnitude
+    pub fn quantize(vector: &[f32]) -> Self {
+        let max_abs = vector.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+        if max_abs == 0.0 {
+            return Self {
+                values: vec![0; vector.len()],
+                scale: 1.0,
+            };
+        }
+
+        let scale = max_abs / 127.0;
+        let values = vector
+            .iter()
+            .map(|x| (x / scale).round().clamp(-127.0, 127.0) as i8)
+            .collect();
+        Self { values, scale }
+    }
+
+    pub fn dequantize(&self) -> Vec<f32> {
+        self.values.iter().map(|&v| v as f32 * self.scale).collect()
+    }
+
+    /// Approximate dot product, accumulated in integers and rescaled once
+    pub fn dot(&self, other: &QuantizedVector) -> f32 {
+        dot_i64(&self.values, &other.values) as f32 * self.scale * other.scale
+    }
+
+    /// Approximate cosine similarity. The scales cancel out, so this never
+    /// leaves integer space until the final division.
+    pub fn cosine(&self, other: &QuantizedVector) -> f32 {
+        if self.values.len() != other.values.len() || self.values.is_empty() {
+            return 0.0;
+        }
+
+        let dot = dot_i64(&self.values, &other.values) as f32;
+        let norm_a = dot_i64(&self.values, &self.values) as f32;
+        let norm_b = dot_i64(&other.values, &other.values) as f32;
+        if norm_a == 0.0 || norm_b == 0.0 {
+            return 0.0;
+        }
+
+        dot / (norm_a.sqrt() * norm_b.sqrt())
+    }
+}
+
+/// A vector reduced to the signs of its components, one bit each, packed
+/// into `u64` words: component `i` is bit `i % 64` of word `i / 64`, set when
+/// the component is positive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinaryVector {
+    pub bits: Vec<u64>,
+    pub dim: usize,
+}
+
+impl BinaryVector {
+    pub fn quantize(vector: &[f32]) -> Self {
+        let mut bits = vec![0u64; vector.len().div_ceil(64)];
+        for (i, &x) in vector.iter().enumerate() {
+            if x > 0.0 {
+                bits[i / 64] |= 1 << (i % 64);
+            }
+        }
+        Self {
+            bits,
+            dim: vector.len(),
+        }
+    }
+
+    /// `1.0` for every set bit and `-1.0` for every clear one
+    pub fn dequantize(&self) -> Vec<f32> {
+        (0..self.dim)
+            .map(|i| {
+                if self.bits[i / 64] >> (i % 64) & 1 == 1 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            })
+            .collect()
+    }
+
+    /// Number of components whose signs differ, by popcount over the packed
+    /// words. Vectors of different dimensions are `u32::MAX` apart.
+    pub fn hamming_distance(&self, other: &BinaryVector) -> u32 {
+        if self.dim != other.dim {
+            return u32::MAX;
+        }
+        self.bits
+            .iter()
+            .zip(&other.bits)
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum()
+    }
+}
+
+/// Tuning for [`ProductQuantizer::train`] and
+/// [`VectorStore::train_pq`](crate::VectorStore::train_pq).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PqParams {
+    /// Subvectors each vector is split into, each stored as one byte. Must
+    /// divide the dimension; more subspaces cost more bytes and lose less.
+    pub subspaces: usize,
+    /// Centroids in each subspace's codebook, from 1 to 256
+    pub centroids: usize,
+    /// k-means iterations per codebook
+    pub iterations: usize,
+    /// Seed the initial centroids are drawn with
+    pub seed: u64,
+}
+
+impl Default for PqParams {
+    fn default() -> Self {
+        Self {
+            subspaces: 8,
+            centroids: 256,
+            iterations: 20,
+            seed: 0x5eed,
+        }
+    }
+}
+
+/// Product quantizer: splits a vector into equal subvectors and replaces
+/// each with the index of its nearest centroid in that subspace's codebook,
+/// so a vector of any dimension is stored in one byte per subspace.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProductQuantizer {
+    dim: usize,
+    // codebooks[subspace][code] is a centroid of dim / codebooks.len() components
+    codebooks: Vec<Vec<Vec<f32>>>,
+}
+
+impl ProductQuantizer {
+    /// Fit a codebook per subspace to `vectors` with k-means
+    pub fn train(vectors: &[Vec<f32>], params: PqParams) -> Result<Self, VectorStoreError> {
+        let Some(dim) = vectors.first().map(Vec::len) else {
+            return Err(VectorStoreError::InvalidRequest(
+                "Product quantization needs training vectors".to_string(),
+            ));
+        };
+        if vectors.iter().any(|v| v.len() != dim) {
+            return Err(VectorStoreError::InvalidRequest(
+                "Training vectors differ in dimension".to_string(),
+            ));
+        }
+        check_pq_shape(dim, params.subspaces, params.centroids)?;
+
+        let sub_dim = dim / params.subspaces;
+        let mut rng = StdRng::seed_from_u64(params.seed);
+        let codebooks = (0..params.subspaces)
+            .map(|s| {
+                let points: Vec<&[f32]> = vectors
+                    .iter()
+                    .map(|v| &v[s * sub_dim..(s + 1) * sub_dim])
+                    .collect();
+                kmeans(&points, params.centroids, params.iterations, &mut rng)
+            })
+            .collect();
+        Ok(Self { dim, codebooks })
+    }
+
+    /// Use precomputed codebooks: `codebooks[s][c]` is centroid `c` of
+    /// subspace `s`. Every centroid must have the same length.
+    pub fn from_codebooks(codebooks: Vec<Vec<Vec<f32>>>) -> Result<Self, VectorStoreError> {
+        let sub_dim = codebooks
+            .first()
+            .and_then(|codebook| codebook.first())
+            .map_or(0, Vec::len);
+        let centroids = codebooks.first().map_or(0, Vec::len);
+        if sub_dim == 0
+            || codebooks
+                .iter()
+                .flatten()
+                .any(|centroid| centroid.len() != sub_dim)
+        {
+            return Err(VectorStoreError::InvalidRequest(
+                "Codebook centroids must be non-empty and of equal length".to_string(),
+            ));
+        }
+        let dim = sub_dim * codebooks.len();
+        check_pq_shape(dim, codebooks.len(), centroids)?;
+        if codebooks.iter().any(|codebook| codebook.is_empty()) {
+            return Err(VectorStoreError::InvalidRequest(
+                "Every codebook needs a centroid".to_string(),
+            ));
+        }
+        Ok(Self { dim, codebooks })
+    }
+
+    /// Dimension of the vectors this quantizer encodes
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Number of subspaces, which is also the size of a code in bytes
+    pub fn subspaces(&self) -> usize {
+        self.codebooks.len()
+    }
+
+    /// One centroid index per subspace, nearest by Euclidean distance
+    pub fn encode(&self, vector: &[f32]) -> Vec<u8> {
+        self.subvectors(vector)
+            .zip(&self.codebooks)
+            .map(|(sub, codebook)| nearest(codebook, sub) as u8)
+            .collect()
+    }
+
+    /// Whether `codes` could have come from [`ProductQuantizer::encode`]: a
+    /// code per subspace, each naming one of its centroids. Decoding or
+    /// scoring codes that don't fit panics.
+    pub fn fits(&self, codes: &[u8]) -> bool {
+        codes.len() == self.subspaces()
+            && codes
+                .iter()
+                .zip(&self.codebooks)
+                .all(|(&code, codebook)| (code as usize) < codebook.len())
+    }
+
+    /// The vector `codes` stands for: its centroids, concatenated
+    pub fn decode(&self, codes: &[u8]) -> Vec<f32> {
+        codes
+            .iter()
+            .zip(&self.codebooks)
+            .flat_map(|(&code, codebook)| codebook[code as usize].iter().copied())
+            .collect()
+    }
+
+    /// Precompute how `query` scores against every centroid under `metric`,
+    /// so scoring a code is a lookup per subspace
+    pub(crate) fn score_table(&self, metric: DistanceMetric, query: &[f32]) -> PqScoreTable {
+        let partial: fn(&[f32], &[f32]) -> f32 = match metric {
+            DistanceMetric::Cosine | DistanceMetric::DotProduct => dot_product,
+            DistanceMetric::Euclidean | DistanceMetric::SquaredEuclidean => {
+                squared_euclidean_distance
+            }
+            DistanceMetric::Manhattan => manhattan_distance,
+            DistanceMetric::Hamming => hamming_distance,
+        };
+        let tables = self
+            .subvectors(query)
+            .zip(&self.codebooks)
+            .map(|(sub, codebook)| codebook.iter().map(|c| partial(sub, c)).collect())
+            .collect();
+        // Cosine also needs each decoded vector's norm, summed from its centroids'
+        let squared_norms = (metric == DistanceMetric::Cosine).then(|| {
+            self.codebooks
+                .iter()
+                .map(|codebook| codebook.iter().map(|c| dot_product(c, c)).collect())
+                .collect()
+        });
+        PqScoreTable {
+            metric,
+            tables,
+            squared_norms,
+            query_norm: dot_product(query, query).sqrt(),
+        }
+    }
+
+    fn subvectors<'v>(&self, vector: &'v [f32]) -> std::slice::Chunks<'v, f32> {
+        vector.chunks(self.dim / self.subspaces())
+    }
+}
+
+/// A query's scores against every centroid of a [`ProductQuantizer`], for
+/// asymmetric distance computation: the query stays exact and only the
+/// stored vectors are quantized
+pub(crate) struct PqScoreTable {
+    metric: DistanceMetric,
+    // tables[subspace][code]: the query's partial score against that centroid
+    tables: Vec<Vec<f32>>,
+    squared_norms: Option<Vec<Vec<f32>>>,
+    query_norm: f32,
+}
+
+impl PqScoreTable {
+    /// The query's score against the vector `codes` decodes to, under the
+    /// table's metric
+    pub(crate) fn score(&self, codes: &[u8]) -> f32 {
+        let lookup = |tables: &[Vec<f32>]| -> f32 {
+            codes
+                .iter()
+                .zip(tables)
+                .map(|(&code, table)| table[code as usize])
+                .sum()
+        };
+        let sum = lookup(&self.tables);
+        match (self.metric, &self.squared_norms) {
+            (DistanceMetric::Cosine, Some(squared_norms)) => {
+                let norm = lookup(squared_norms).sqrt();
+                if norm == 0.0 || self.query_norm == 0.0 {
+                    0.0
+                } else {
+                    sum / (norm * self.query_norm)
+                }
+            }
+            (DistanceMetric::Euclidean, _) => sum.sqrt(),
+            _ => sum,
+        }
+    }
+}
+
+fn check_pq_shape(dim: usize, subspaces: usize, centroids: usize) -> Result<(), VectorStoreError> {
+    if subspaces == 0 || !dim.is_multiple_of(subspaces) {
+        return Err(VectorStoreError::InvalidRequest(format!(
+            "{subspaces} subspaces don't divide dimension {dim}"
+        )));
+    }
+    if !(1..=256).contains(&centroids) {
+        return Err(VectorStoreError::InvalidRequest(format!(
+            "A codebook holds 1 to 256 centroids, not {centroids}"
+        )));
+    }
+    Ok(())
+}
+
+/// Index of the centroid nearest `point`
+fn nearest(centroids: &[Vec<f32>], point: &[f32]) -> usize {
+    centroids
+        .iter()
+        .map(|c| squared_euclidean_distance(c, point))
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map_or(0, |(i, _)| i)
+}
+
+/// Lloyd's k-means, starting from `k` distinct points (fewer if there aren't
+/// that many). A centroid that loses all its points stays where it was.
+fn kmeans(points: &[&[f32]], k: usize, iterations: usize, rng: &mut StdRng) -> Vec<Vec<f32>> {
+    let k = k.min(points.len());
+    let mut centroids: Vec<Vec<f32>> = sample(rng, points.len(), k)
+        .into_iter()
+        .map(|i| points[i].to_vec())
+        .collect();
+    let sub_dim = points[0].len();
+    for _ in 0..iterations {
+        let mut sums = vec![vec![0.0f32; sub_dim]; k];
+        let mut counts = vec![0usize; k];
+        for point in points {
+            let c = nearest(&centroids, point);
+            counts[c] += 1;
+            for (sum, x) in sums[c].iter_mut().zip(*point) {
+                *sum += x;
+            }
+        }
+        for ((centroid, sum), count) in centroids.iter_mut().zip(sums).zip(counts) {
+            if count > 0 {
+                *centroid = sum.into_iter().map(|x| x / count as f32).collect();
+            }
+        }
+    }
+    centroids
+}
+
+fn dot_i64(a: &[i8], b: &[i8]) -> i64 {
+    a.iter().zip(b).map(|(&x, &y)| x as i64 * y as i64).sum()
+}
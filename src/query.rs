@@ -0,0 +1,196 @@
+use crate::errors::VectorStoreError;
+use crate::models::DistanceMetric;
+use crate::store::{QueryProfile, QueryResult, VectorStore};
+use std::collections::HashSet;
+
+type MetadataFilter<'a> = Box<dyn Fn(Option<&serde_json::Value>) -> bool + Send + Sync + 'a>;
+
+const DEFAULT_K: usize = 10;
+
+/// Composable query against the default namespace, started with
+/// [`VectorStore::search`] and executed with [`QueryBuilder::run`].
+///
+/// ```ignore
+/// let found = store
+///     .search(vector)
+///     .k(5)
+///     .min_score(0.5)
+///     .exclude(["seen"])
+///     .filter(|m| m.is_some_and(|m| m["lang"] == "en"))
+///     .run()
+///     .await?;
+/// ```
+pub struct QueryBuilder<'a> {
+    store: &'a VectorStore,
+    pub(crate) vector: Vec<f32>,
+    pub(crate) k: usize,
+    // None scores with the namespace's metric, or the store's custom distance if it has one
+    pub(crate) metric: Option<DistanceMetric>,
+    pub(crate) filter: Option<MetadataFilter<'a>>,
+    pub(crate) min_score: Option<f32>,
+    pub(crate) exclude: HashSet<String>,
+    pub(crate) only: Option<HashSet<String>>,
+    pub(crate) offset: usize,
+    pub(crate) profile: bool,
+    pub(crate) with_vectors: bool,
+    pub(crate) normalization: Option<ScoreNormalization>,
+}
+
+impl<'a> QueryBuilder<'a> {
+    pub(crate) fn new(store: &'a VectorStore, vector: Vec<f32>) -> Self {
+        Self {
+            store,
+            vector,
+            k: DEFAULT_K,
+            metric: None,
+            filter: None,
+            min_score: None,
+            exclude: HashSet::new(),
+            only: None,
+            offset: 0,
+            profile: false,
+            with_vectors: false,
+            normalization: None,
+        }
+    }
+
+    /// Number of results to return (default 10)
+    pub fn k(mut self, k: usize) -> Self {
+        self.k = k;
+        self
+    }
+
+    /// Score with `metric` instead of the namespace's metric, or the store's custom
+    /// [`Distance`](crate::Distance) if it was opened with one
+    pub fn metric(mut self, metric: DistanceMetric) -> Self {
+        self.metric = Some(metric);
+        self
+    }
+
+    /// Keep only records whose metadata (`None` if they have none) passes `filter`
+    pub fn filter(
+        mut self,
+        filter: impl Fn(Option<&serde_json::Value>) -> bool + Send + Sync + 'a,
+    ) -> Self {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Drop results scoring worse than `min_score`: below it for similarities,
+    /// above it for distances (see [`DistanceMetric::higher_is_better`])
+    pub fn min_score(mut self, min_score: f32) -> Self {
+        self.min_score = Some(min_score);
+        self
+    }
+
+    /// Never return these ids
+    pub fn exclude<I, S>(mut self, ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.exclude.extend(ids.into_iter().map(Into::into));
+        self
+    }
+
+    /// Consider only these ids; repeated calls widen the set. Ids passed to
+    /// [`QueryBuilder::exclude`] are still dropped.
+    pub fn only<I, S>(mut self, ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.only
+            .get_or_insert_with(HashSet::new)
+            .extend(ids.into_iter().map(Into::into));
+        self
+    }
+
+    /// Skip this many of the best matches before taking `k`, for paging.
+    /// The skipped matches are still ranked, so deep offsets cost as much as
+    /// asking for `offset + k` results.
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Also return a [`QueryProfile`] for the query
+    pub fn profile(mut self) -> Self {
+        self.profile = true;
+        self
+    }
+
+    /// Also return each match's stored vector in [`QueryResult::vector`]
+    pub fn with_vectors(mut self) -> Self {
+        self.with_vectors = true;
+        self
+    }
+
+    /// Rescale the returned scores with `normalization` once the final
+    /// results are picked. Off by default, so scores are the metric's own.
+    pub fn normalize(mut self, normalization: ScoreNormalization) -> Self {
+        self.normalization = Some(normalization);
+        self
+    }
+
+    pub async fn run(self) -> Result<SearchResults, VectorStoreError> {
+        self.store.run_search(self).await
+    }
+}
+
+/// How [`QueryBuilder::normalize`] rescales the scores of a result list.
+/// Both treat the best result as the highest, so under distance metrics
+/// (see [`DistanceMetric::higher_is_better`]) the nearest match ends up
+/// with the largest normalized score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreNormalization {
+    /// Map the best score to 1 and the worst to 0, linearly. A list whose
+    /// scores are all equal maps to 1.
+    MinMax,
+    /// Turn the scores into probabilities summing to 1 with a softmax
+    Softmax,
+}
+
+impl ScoreNormalization {
+    pub(crate) fn apply(self, results: &mut [QueryResult], higher_is_better: bool) {
+        let oriented = |score: f32| if higher_is_better { score } else { -score };
+        let (min, max) = results
+            .iter()
+            .map(|r| oriented(r.score))
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), s| {
+                (min.min(s), max.max(s))
+            });
+        match self {
+            ScoreNormalization::MinMax => {
+                let range = max - min;
+                for result in results {
+                    result.score = if range > 0.0 {
+                        (oriented(result.score) - min) / range
+                    } else {
+                        1.0
+                    };
+                }
+            }
+            ScoreNormalization::Softmax => {
+                // Shifting by the max keeps exp from overflowing
+                let exps: Vec<f32> = results
+                    .iter()
+                    .map(|r| (oriented(r.score) - max).exp())
+                    .collect();
+                let sum: f32 = exps.iter().sum();
+                for (result, exp) in results.iter_mut().zip(exps) {
+                    result.score = exp / sum;
+                }
+            }
+        }
+    }
+}
+
+/// Output of [`QueryBuilder::run`]
+#[derive(Debug, Clone)]
+pub struct SearchResults {
+    /// Best first
+    pub results: Vec<QueryResult>,
+    /// Present when [`QueryBuilder::profile`] was requested
+    pub profile: Option<QueryProfile>,
+}
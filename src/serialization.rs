@@ -0,0 +1,97 @@
+use crate::errors::VectorStoreError;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
+
+/// How record metadata is encoded on disk, set with
+/// [`VectorStoreOptions::serialization`](crate::VectorStoreOptions::serialization).
+///
+/// A store remembers the format it was created with, so it is only chosen
+/// once: reopening picks it up without being told, and asking for a
+/// different one fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SerializationFormat {
+    /// Readable with any tool; what stores created before this option used
+    #[default]
+    Json,
+    /// bincode: compact and the fastest to encode and decode, but Rust-only
+    Bincode,
+    /// MessagePack: compact and readable from most languages
+    MessagePack,
+}
+
+/// bincode can't decode self-describing data such as [`Value`], so metadata
+/// goes through this explicitly tagged mirror of it
+#[derive(Serialize, Deserialize)]
+enum TaggedValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    String(String),
+    Array(Vec<TaggedValue>),
+    Object(Vec<(String, TaggedValue)>),
+}
+
+impl From<&Value> for TaggedValue {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Null => TaggedValue::Null,
+            Value::Bool(b) => TaggedValue::Bool(*b),
+            Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+                (Some(i), _) => TaggedValue::Int(i),
+                (None, Some(u)) => TaggedValue::UInt(u),
+                _ => TaggedValue::Float(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Value::String(s) => TaggedValue::String(s.clone()),
+            Value::Array(items) => TaggedValue::Array(items.iter().map(Into::into).collect()),
+            Value::Object(fields) => {
+                TaggedValue::Object(fields.iter().map(|(k, v)| (k.clone(), v.into())).collect())
+            }
+        }
+    }
+}
+
+impl From<TaggedValue> for Value {
+    fn from(value: TaggedValue) -> Self {
+        match value {
+            TaggedValue::Null => Value::Null,
+            TaggedValue::Bool(b) => Value::Bool(b),
+            TaggedValue::Int(i) => Value::Number(i.into()),
+            TaggedValue::UInt(u) => Value::Number(u.into()),
+            TaggedValue::Float(f) => Number::from_f64(f).map_or(Value::Null, Value::Number),
+            TaggedValue::String(s) => Value::String(s),
+            TaggedValue::Array(items) => Value::Array(items.into_iter().map(Into::into).collect()),
+            TaggedValue::Object(fields) => Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(k, v)| (k, v.into()))
+                    .collect::<Map<_, _>>(),
+            ),
+        }
+    }
+}
+
+impl SerializationFormat {
+    pub(crate) fn encode(self, metadata: &Value) -> Result<Vec<u8>, VectorStoreError> {
+        match self {
+            SerializationFormat::Json => Ok(serde_json::to_vec(metadata)?),
+            SerializationFormat::Bincode => bincode::serialize(&TaggedValue::from(metadata))
+                .map_err(|e| VectorStoreError::MetadataEncoding(e.to_string())),
+            SerializationFormat::MessagePack => rmp_serde::to_vec_named(metadata)
+                .map_err(|e| VectorStoreError::MetadataEncoding(e.to_string())),
+        }
+    }
+
+    pub(crate) fn decode(self, bytes: &[u8]) -> Result<Value, VectorStoreError> {
+        match self {
+            SerializationFormat::Json => Ok(serde_json::from_slice(bytes)?),
+            SerializationFormat::Bincode => bincode::deserialize::<TaggedValue>(bytes)
+                .map(Into::into)
+                .map_err(|e| VectorStoreError::MetadataEncoding(e.to_string())),
+            SerializationFormat::MessagePack => rmp_serde::from_slice(bytes)
+                .map_err(|e| VectorStoreError::MetadataEncoding(e.to_string())),
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use crate::errors::VectorStoreError;
+use crate::store::{QueryResult, VectorStore};
+
+/// Queries against an exponential moving average of the vectors a session has
+/// submitted, so results drift toward what was asked recently.
+///
+/// Each [`SessionQuery::submit`] updates the average as
+/// `ema = decay * ema + (1 - decay) * vector`; the first vector seeds it. A
+/// `decay` near 1.0 remembers long histories, near 0.0 follows the latest query.
+pub struct SessionQuery<'a> {
+    store: &'a VectorStore,
+    decay: f32,
+    ema: Option<Vec<f32>>,
+}
+
+impl<'a> SessionQuery<'a> {
+    /// `decay` is clamped to `0.0..=1.0`.
+    pub fn new(store: &'a VectorStore, decay: f32) -> Self {
+        Self {
+            store,
+            decay: decay.clamp(0.0, 1.0),
+            ema: None,
+        }
+    }
+
+    pub fn submit(&mut self, vector: &[f32]) -> Result<(), VectorStoreError> {
+        match &mut self.ema {
+            None => self.ema = Some(vector.to_vec()),
+            Some(ema) if ema.len() != vector.len() => {
+                return Err(VectorStoreError::DimensionMismatch {
+                    expected: ema.len(),
+                    got: vector.len(),
+                });
+            }
+            Some(ema) => {
+                for (avg, x) in ema.iter_mut().zip(vector) {
+                    *avg = self.decay * *avg + (1.0 - self.decay) * x;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The current average, or `None` before anything was submitted
+    pub fn current(&self) -> Option<&[f32]> {
+        self.ema.as_deref()
+    }
+
+    /// Top `k` matches for the current average. Empty until a vector has been
+    /// submitted.
+    pub async fn query(&self, k: usize) -> Result<Vec<QueryResult>, VectorStoreError> {
+        match &self.ema {
+            Some(ema) => self.store.query(ema, k).await,
+            None => Ok(Vec::new()),
+        }
+    }
+}
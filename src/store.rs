@@ -1,15 +1,90 @@
+use crate::collection::Collection;
+use crate::consistency::{retry_until_visible, verify_write};
+use crate::distance::{
+    Distance, compare_scores, cosine_similarity_with_norm, cosine_similarity_with_norms,
+    dimension_terms, magnitude, similarity, sparse_dot_product,
+};
 use crate::errors::VectorStoreError;
-use crate::models::NamespaceMetadata;
+use crate::filter::Filter;
+use crate::hnsw::Hnsw;
+use crate::keys::KeyEncoding;
+use crate::lsh::Lsh;
+use crate::models::{
+    DistanceMetric, DuplicatePolicy, NamespaceMetadata, SparseVector, Standardization,
+    VectorEncoding,
+};
+use crate::options::VectorStoreOptions;
+use crate::quantization::{BinaryVector, PqParams, ProductQuantizer, QuantizedVector};
+use crate::query::{QueryBuilder, SearchResults};
+use crate::serialization::SerializationFormat;
+use bytes::Bytes;
 use chrono::Utc;
+use futures::{Stream, StreamExt};
+use half::f16;
 use metrics::{counter, histogram};
 use object_store::ObjectStore;
-use slatedb::Db;
-use std::collections::BinaryHeap;
-use std::sync::Arc;
-use std::time::Instant;
+use object_store::aws::AmazonS3Builder;
+use object_store::memory::InMemory;
+use serde::{Deserialize, Serialize};
+use slatedb::config::{FlushOptions, FlushType};
+use slatedb::db_cache::moka::{MokaCache, MokaCacheOptions};
+use slatedb::{Db, DbIterator, WriteBatch};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::Mutex;
+use tokio::task::JoinSet;
 
 const DEFAULT_NS: &str = "_default";
-const DEFAULT_DIM: usize = 0; // 0 means "any dimension" for backward compat
+pub(crate) const DEFAULT_DIM: usize = 0; // 0 means "any dimension" for backward compat
+const DEFAULT_BATCH_SIZE: usize = 1000;
+const IN_MEMORY_PATH: &str = "/smolpuff";
+const NORM_EPSILON: f32 = 1e-6;
+const APPEND_SEQ_KEY: &str = "seq:_default"; // outside the ns: keyspace
+const METRIC_KEY: &str = "meta:distance"; // outside the ns: keyspace
+const FORMAT_VERSION_KEY: &str = "meta:format_version"; // outside the ns: keyspace
+const SERIALIZATION_KEY: &str = "meta:serialization"; // outside the ns: keyspace
+const KEY_ENCODING_KEY: &str = "meta:key_encoding"; // outside the ns: keyspace
+const LSH_KEY: &str = "meta:lsh"; // outside the ns: keyspace
+const PQ_KEY: &str = "meta:pq"; // outside the ns: keyspace
+/// Every kind of `ns:{ns}:{kind}:{id}` key, i.e. everything in a namespace
+/// but its metadata
+const RECORD_KINDS: [&str; 9] = [
+    "vec",
+    "norm",
+    "doc",
+    "sparse",
+    "sparse_doc",
+    "chunk",
+    "chunk_doc",
+    "lsh",
+    "pq",
+];
+/// Most vectors [`VectorStore::train_pq`] fits codebooks to; k-means over
+/// more costs time without improving them much
+const PQ_TRAINING_SAMPLE: usize = 10_000;
+/// On-disk layout version written by this build, recorded when a store is
+/// first opened
+pub const FORMAT_VERSION: u32 = 2;
+/// Every vector record starts with its format version followed by these
+/// bytes. Read as an `f32` the header is a NaN, which no accepted vector
+/// holds, so it can't be mistaken for the first component of a record
+/// written before records were versioned.
+const RECORD_MAGIC: [u8; 3] = [0x50, 0xFF, 0x7F];
+const RECORD_HEADER_LEN: usize = 1 + RECORD_MAGIC.len();
+const RECORD_V0_JSON: u8 = 0; // JSON array; read, but no longer written
+const RECORD_V1_BINARY: u8 = 1; // little-endian f32s
+const RECORD_V2_INT8: u8 = 2; // little-endian f32 scale, then one i8 per component
+const RECORD_V3_SIGN_BITS: u8 = 3; // little-endian u32 dimension, then u64 words
+const RECORD_V4_F16: u8 = 4; // little-endian f16s
+const VERIFY_SAMPLE_SIZE: usize = 100; // records checked per namespace by open_and_verify
+const PARALLEL_CHUNK_SIZE: usize = 1024; // records per query_parallel scoring task
+const MMR_POOL_FACTOR: usize = 4; // query_mmr picks k results from k * this candidates
+#[cfg(feature = "parquet")]
+const EXPORT_BATCH_SIZE: usize = 1024;
 
 #[derive(Debug, Clone, PartialEq)]
 struct ScoredItem {
@@ -25,18 +100,204 @@ impl PartialOrd for ScoredItem {
     }
 }
 
+/// Worse items compare greater, so a `BinaryHeap` keeps the worst on top
+/// (a min-heap over scores) and sorting ascending puts the best first.
+/// Higher scores are better; [`TopK`] negates distances before they get here.
+/// Equal scores are ordered by id, smallest first, and NaN scores rank below
+/// every real score.
 impl Ord for ScoredItem {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        // Reverse ordering for min-heap (we want to keep the highest scores)
-        other
-            .score
-            .partial_cmp(&self.score)
-            .unwrap_or(std::cmp::Ordering::Equal)
+        compare_scores(true, self.score, other.score).then_with(|| self.id.cmp(&other.id))
     }
 }
 
+/// Bounded min-heap keeping the `k` best-scoring items, at most one per id.
+///
+/// Two keys can decode to the same id (e.g. a raw `a:b` and the escaped
+/// `a%3Ab` in a store written with both encodings before the encoding was
+/// persisted); only the best-scoring of them is kept, so results never
+/// repeat an id.
+///
+/// For metrics where lower is better, scores are negated on the way in and
+/// back on the way out, so the heap itself always keeps the largest.
+struct TopK {
+    k: usize,
+    higher_is_better: bool,
+    heap: BinaryHeap<ScoredItem>,
+    // Score of every id currently in `heap`
+    members: HashMap<String, f32>,
+}
+
+impl TopK {
+    fn with_capacity(k: usize, capacity: usize, higher_is_better: bool) -> Self {
+        Self {
+            k,
+            higher_is_better,
+            heap: BinaryHeap::with_capacity(capacity),
+            members: HashMap::with_capacity(capacity),
+        }
+    }
+
+    fn oriented(&self, score: f32) -> f32 {
+        if self.higher_is_better { score } else { -score }
+    }
+
+    /// Whether an item with `score` could enter the heap right now. A score
+    /// tied with the current minimum could, depending on its id.
+    fn would_accept(&self, score: f32) -> bool {
+        let score = self.oriented(score);
+        self.heap.len() < self.k || self.heap.peek().is_some_and(|min| score >= min.score)
+    }
+
+    fn offer(&mut self, mut item: ScoredItem) {
+        let full = self.heap.len() >= self.k;
+        item.score = self.oriented(item.score);
+        // Anything that can't beat the current minimum can't matter, so the
+        // id lookup only happens for items that would enter the heap
+        if full && self.heap.peek().is_some_and(|min| item >= *min) {
+            return;
+        }
+
+        if let Some(&existing) = self.members.get(&item.id) {
+            if item.score > existing {
+                self.heap.retain(|si| si.id != item.id);
+                self.members.insert(item.id.clone(), item.score);
+                self.heap.push(item);
+            }
+            return;
+        }
+
+        if full && let Some(evicted) = self.heap.pop() {
+            self.members.remove(&evicted.id);
+        }
+        self.members.insert(item.id.clone(), item.score);
+        self.heap.push(item);
+    }
+
+    /// Best first. The heap's ordering is reversed, so ascending order is best-first
+    fn into_sorted_vec(self) -> Vec<ScoredItem> {
+        let higher_is_better = self.higher_is_better;
+        let mut items = self.heap.into_sorted_vec();
+        if !higher_is_better {
+            for item in &mut items {
+                item.score = -item.score;
+            }
+        }
+        items
+    }
+}
+
+/// The `k` best-scoring items plus every item tied with the `k`-th, for
+/// rankings that break score ties by something other than id. Like [`TopK`]
+/// it negates distances on the way in and back on the way out.
+struct TopKWithTies {
+    k: usize,
+    higher_is_better: bool,
+    heap: BinaryHeap<ScoredItem>,
+    // Items scoring exactly the heap's minimum that didn't fit in it
+    ties: Vec<ScoredItem>,
+}
+
+impl TopKWithTies {
+    fn new(k: usize, higher_is_better: bool) -> Self {
+        Self {
+            k,
+            higher_is_better,
+            heap: BinaryHeap::with_capacity(k.min(1024)),
+            ties: Vec::new(),
+        }
+    }
+
+    fn offer(&mut self, mut item: ScoredItem) {
+        if self.k == 0 {
+            return;
+        }
+        if !self.higher_is_better {
+            item.score = -item.score;
+        }
+        if self.heap.len() < self.k {
+            self.heap.push(item);
+            return;
+        }
+        let Some(min) = self.heap.peek().map(|min| min.score) else {
+            return;
+        };
+        match compare_scores(true, item.score, min) {
+            std::cmp::Ordering::Greater => {}
+            std::cmp::Ordering::Equal => self.ties.push(item),
+            std::cmp::Ordering::Less => {
+                self.heap.push(item);
+                let Some(evicted) = self.heap.pop() else {
+                    return;
+                };
+                let new_min = self.heap.peek().map_or(evicted.score, |min| min.score);
+                if compare_scores(true, evicted.score, new_min).is_eq() {
+                    self.ties.push(evicted);
+                } else {
+                    // The cutoff rose past every earlier tie
+                    self.ties.clear();
+                }
+            }
+        }
+    }
+
+    /// The kept items, in no particular order
+    fn into_vec(self) -> Vec<ScoredItem> {
+        let higher_is_better = self.higher_is_better;
+        let mut items = self.heap.into_vec();
+        items.extend(self.ties);
+        if !higher_is_better {
+            for item in &mut items {
+                item.score = -item.score;
+            }
+        }
+        items
+    }
+}
+
+/// A vector store over one slatedb database.
+///
+/// Cloning is cheap and every clone is a handle on the same database,
+/// append counter, ANN index and query cache, so one store can be shared across tasks
+/// and request handlers. Handles are `Send + Sync` and every method takes
+/// `&self`, so `add`s and `query`s may run concurrently. A query sees every
+/// `add` that returned before it started; one still in flight may be missed,
+/// or found before its metadata is written.
+#[derive(Clone)]
 pub struct VectorStore {
-    db: Db,
+    db: Arc<Db>,
+    options: VectorStoreOptions,
+    // Resolved from the options and the persisted `meta:distance` key on open
+    metric: DistanceMetric,
+    // Resolved from the options and the persisted `meta:serialization` key on open
+    serialization: SerializationFormat,
+    // Serializes read-increment-write of the append counter
+    append_lock: Arc<Mutex<()>>,
+    // Approximate-search index over the default namespace, once built
+    ann: Arc<RwLock<Option<Hnsw>>>,
+    // Decoded default-namespace vectors by vec key, once warmed up
+    cache: Arc<RwLock<Option<BTreeMap<String, CachedVector>>>>,
+    // LSH hyperplanes, once LSH is on and the dimension is known
+    lsh: Arc<RwLock<Option<Arc<Lsh>>>>,
+    // Product quantizer codebooks, once trained or set
+    pq: Arc<RwLock<Option<Arc<ProductQuantizer>>>>,
+}
+
+/// A default-namespace vector held in memory by [`VectorStore::warmup`]
+struct CachedVector {
+    id: String,
+    vector: Vec<f32>,
+    norm: f32,
+}
+
+impl CachedVector {
+    fn new(id: &str, vector: Vec<f32>) -> Self {
+        Self {
+            id: id.to_string(),
+            norm: magnitude(&vector),
+            vector,
+        }
+    }
 }
 
 fn record_op(operation: &str, namespace: &str, start: Instant, succeeded: bool) {
@@ -62,302 +323,4738 @@ impl VectorStore {
         path: P,
         object_store: Arc<dyn ObjectStore>,
     ) -> Result<Self, VectorStoreError> {
-        let db = Db::open(path.as_ref(), object_store).await?;
-        Ok(Self { db })
+        Self::open_with_options(path, object_store, VectorStoreOptions::default()).await
     }
 
-    // --- Namespace operations ---
+    pub async fn open_with_options<P: AsRef<str>>(
+        path: P,
+        object_store: Arc<dyn ObjectStore>,
+        mut options: VectorStoreOptions,
+    ) -> Result<Self, VectorStoreError> {
+        let db = match options.block_cache_bytes {
+            Some(bytes) => {
+                let cache = MokaCache::new_with_opts(MokaCacheOptions {
+                    max_capacity: bytes as u64,
+                    ..MokaCacheOptions::default()
+                });
+                Db::builder(path.as_ref(), object_store)
+                    .with_memory_cache(Arc::new(cache))
+                    .build()
+                    .await?
+            }
+            None => Db::open(path.as_ref(), object_store).await?,
+        };
 
-    pub async fn create_namespace(
-        &self,
-        name: &str,
-        vector_dim: usize,
-        distance: &str,
-    ) -> Result<NamespaceMetadata, VectorStoreError> {
-        let start = Instant::now();
-        let result = async {
-            let meta_key = format!("ns:{name}:meta");
+        let persisted = match db.get(METRIC_KEY.as_bytes()).await? {
+            Some(bytes) => Some(serde_json::from_slice::<DistanceMetric>(&bytes)?),
+            None => None,
+        };
+        // The metric is persisted on first open, so a store without one is new
+        let is_new = persisted.is_none();
+        let metric = match (persisted, options.metric) {
+            (Some(stored), Some(requested)) if stored != requested => {
+                return Err(VectorStoreError::InvalidRequest(format!(
+                    "Store uses {} distance, not {}",
+                    stored.as_str(),
+                    requested.as_str()
+                )));
+            }
+            (Some(stored), _) => stored,
+            (None, requested) => {
+                let metric = requested.unwrap_or_default();
+                db.put(METRIC_KEY.as_bytes(), &serde_json::to_vec(&metric)?)
+                    .await?;
+                metric
+            }
+        };
 
-            // Check if namespace already exists
-            if self.db.get(meta_key.as_bytes()).await?.is_some() {
-                return Err(VectorStoreError::NamespaceAlreadyExists(name.to_string()));
+        let persisted = match db.get(SERIALIZATION_KEY.as_bytes()).await? {
+            Some(bytes) => Some(serde_json::from_slice::<SerializationFormat>(&bytes)?),
+            // Existing stores from before the format was configurable hold JSON
+            None if !is_new => Some(SerializationFormat::Json),
+            None => None,
+        };
+        let serialization = match (persisted, options.serialization) {
+            (Some(stored), Some(requested)) if stored != requested => {
+                return Err(VectorStoreError::InvalidRequest(format!(
+                    "Store serializes metadata as {stored:?}, not {requested:?}"
+                )));
             }
+            (Some(stored), _) => stored,
+            (None, requested) => requested.unwrap_or_default(),
+        };
+        if db.get(SERIALIZATION_KEY.as_bytes()).await?.is_none() {
+            db.put(
+                SERIALIZATION_KEY.as_bytes(),
+                &serde_json::to_vec(&serialization)?,
+            )
+            .await?;
+        }
 
-            let metadata = NamespaceMetadata {
-                name: name.to_string(),
-                vector_dim,
-                distance: distance.to_string(),
-                approx_row_count: 0,
-                created_at: Utc::now(),
-            };
+        // Stores from before the encoding was persisted are trusted to be
+        // opened with the one they were written with
+        let persisted = match db.get(KEY_ENCODING_KEY.as_bytes()).await? {
+            Some(bytes) => Some(serde_json::from_slice::<KeyEncoding>(&bytes)?),
+            None => None,
+        };
+        match (persisted, options.key_encoding) {
+            (Some(stored), Some(requested)) if stored != requested => {
+                return Err(VectorStoreError::InvalidRequest(format!(
+                    "Store keys are {stored:?}-encoded, not {requested:?}"
+                )));
+            }
+            (Some(stored), _) => options.key_encoding = Some(stored),
+            (None, requested) => {
+                let encoding = requested.unwrap_or_default();
+                db.put(KEY_ENCODING_KEY.as_bytes(), &serde_json::to_vec(&encoding)?)
+                    .await?;
+                options.key_encoding = Some(encoding);
+            }
+        }
 
-            let value = serde_json::to_vec(&metadata)?;
-            self.db.put(meta_key.as_bytes(), &value).await?;
+        if db.get(FORMAT_VERSION_KEY.as_bytes()).await?.is_none() {
+            db.put(
+                FORMAT_VERSION_KEY.as_bytes(),
+                &serde_json::to_vec(&FORMAT_VERSION)?,
+            )
+            .await?;
+        }
 
-            Ok(metadata)
+        if let Some(params) = options.lsh
+            && (params.tables == 0 || !(1..=64).contains(&params.hyperplanes))
+        {
+            return Err(VectorStoreError::InvalidRequest(format!(
+                "LSH needs at least one table and 1 to 64 hyperplanes, not {params:?}"
+            )));
         }
-        .await;
-        record_op("create_namespace", name, start, result.is_ok());
-        result
+        let lsh = match db.get(LSH_KEY.as_bytes()).await? {
+            Some(bytes) => Some(serde_json::from_slice::<Lsh>(&bytes)?),
+            None => None,
+        };
+        if let Some(stored) = &lsh {
+            match options.lsh {
+                Some(requested) if requested != stored.params() => {
+                    return Err(VectorStoreError::InvalidRequest(format!(
+                        "Store uses LSH parameters {:?}, not {requested:?}",
+                        stored.params()
+                    )));
+                }
+                _ => options.lsh = Some(stored.params()),
+            }
+        }
+
+        let pq = match db.get(PQ_KEY.as_bytes()).await? {
+            Some(bytes) => Some(Arc::new(serde_json::from_slice::<ProductQuantizer>(
+                &bytes,
+            )?)),
+            None => None,
+        };
+
+        let store = Self {
+            db: Arc::new(db),
+            options,
+            metric,
+            serialization,
+            append_lock: Arc::new(Mutex::new(())),
+            ann: Arc::new(RwLock::new(None)),
+            cache: Arc::new(RwLock::new(None)),
+            lsh: Arc::new(RwLock::new(lsh.map(Arc::new))),
+            pq: Arc::new(RwLock::new(pq)),
+        };
+        if store.options.hnsw.is_some() {
+            store.build_ann_index().await?;
+        }
+        if store.options.lsh.is_some() {
+            store.init_lsh().await?;
+        }
+        Ok(store)
     }
 
-    pub async fn get_namespace(&self, name: &str) -> Result<NamespaceMetadata, VectorStoreError> {
-        let start = Instant::now();
-        let result = async {
-            let meta_key = format!("ns:{name}:meta");
-            match self.db.get(meta_key.as_bytes()).await? {
-                Some(value) => Ok(serde_json::from_slice(&value)?),
-                None => Err(VectorStoreError::NamespaceNotFound(name.to_string())),
-            }
+    /// Open a store that scores queries with `metric`. Shorthand for
+    /// [`VectorStoreOptions::metric`].
+    pub async fn open_with_metric<P: AsRef<str>>(
+        path: P,
+        object_store: Arc<dyn ObjectStore>,
+        metric: DistanceMetric,
+    ) -> Result<Self, VectorStoreError> {
+        Self::open_with_options(path, object_store, VectorStoreOptions::new().metric(metric)).await
+    }
+
+    /// Open a store backed by a fresh in-memory object store, for tests,
+    /// examples and experiments. Nothing outlives the returned store.
+    pub async fn open_in_memory() -> Result<Self, VectorStoreError> {
+        Self::open(IN_MEMORY_PATH, Arc::new(InMemory::new())).await
+    }
+
+    /// Open a store in an S3 (or S3-compatible) bucket. Credentials come
+    /// from the standard `AWS_*` environment variables; `endpoint` points at
+    /// a non-AWS service such as LocalStack or MinIO, and plain `http://`
+    /// endpoints are allowed.
+    pub async fn open_s3<P: AsRef<str>>(
+        path: P,
+        bucket: &str,
+        region: &str,
+        endpoint: Option<String>,
+    ) -> Result<Self, VectorStoreError> {
+        let mut builder = AmazonS3Builder::from_env()
+            .with_bucket_name(bucket)
+            .with_region(region);
+        if let Some(endpoint) = endpoint {
+            builder = builder
+                .with_allow_http(endpoint.starts_with("http://"))
+                .with_endpoint(endpoint);
         }
-        .await;
-        record_op("get_namespace", name, start, result.is_ok());
-        result
+        Self::open(path, Arc::new(builder.build()?)).await
     }
 
-    pub async fn delete_namespace(&self, name: &str) -> Result<(), VectorStoreError> {
+    /// Build the in-memory HNSW index over the default namespace from its
+    /// stored records, with [`VectorStoreOptions::hnsw`]'s parameters (or
+    /// the defaults) and the namespace's metric, replacing any earlier index. From then on writes keep
+    /// it up to date and [`VectorStore::query_ann`] searches it. Writes made
+    /// while it builds may be missing from it until the next rebuild.
+    pub async fn build_ann_index(&self) -> Result<(), VectorStoreError> {
         let start = Instant::now();
         let result = async {
-            let meta_key = format!("ns:{name}:meta");
-
-            // Verify namespace exists
-            if self.db.get(meta_key.as_bytes()).await?.is_none() {
-                return Err(VectorStoreError::NamespaceNotFound(name.to_string()));
-            }
-
-            // Delete all vec keys for this namespace
-            let vec_prefix = format!("ns:{name}:vec:");
-            let vec_end = format!("ns:{name}:vec;");
+            let params = self.options.hnsw.unwrap_or_default();
+            let mut index = Hnsw::new(params, self.default_distance().await?);
+            let (vec_prefix, vec_end) = self.keys().range(DEFAULT_NS, "vec");
             let mut iter = self
                 .db
                 .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
                 .await?;
             while let Ok(Some(item)) = iter.next().await {
-                self.db.delete(&item.key).await?;
-            }
-
-            // Delete all doc keys for this namespace
-            let doc_prefix = format!("ns:{name}:doc:");
-            let doc_end = format!("ns:{name}:doc;");
-            let mut iter = self
-                .db
-                .scan(doc_prefix.as_bytes()..doc_end.as_bytes())
-                .await?;
-            while let Ok(Some(item)) = iter.next().await {
-                self.db.delete(&item.key).await?;
+                let id = self.keys().id_from_key(&vec_prefix, &item.key);
+                index.insert(&id, decode_record(&item.value)?);
             }
-
-            // Delete the metadata key
-            self.db.delete(meta_key.as_bytes()).await?;
-
+            *self.ann.write().expect("ANN index lock poisoned") = Some(index);
             Ok(())
         }
         .await;
-        record_op("delete_namespace", name, start, result.is_ok());
+        record_op("build_ann_index", DEFAULT_NS, start, result.is_ok());
         result
     }
 
-    // --- Data operations ---
+    /// Rebuild the ANN index if one is built, after a bulk rewrite
+    async fn rebuild_ann_index(&self) -> Result<(), VectorStoreError> {
+        let built = self.ann.read().expect("ANN index lock poisoned").is_some();
+        if built {
+            self.build_ann_index().await?;
+        }
+        Ok(())
+    }
 
-    pub async fn upsert(
-        &self,
-        ns: &str,
-        id: &str,
-        vector: Vec<f32>,
-        attributes: Option<serde_json::Value>,
-    ) -> Result<(), VectorStoreError> {
-        let start = Instant::now();
-        let result = async {
-            // Get namespace metadata to validate dimensions
-            let meta = self.get_namespace(ns).await?;
+    /// Empty the ANN index, if one is built, keeping its parameters and
+    /// distance
+    fn reset_ann_index(&self) {
+        if let Some(index) = self.ann.write().expect("ANN index lock poisoned").as_mut() {
+            *index = Hnsw::new(index.params(), index.distance());
+        }
+    }
 
-            if meta.vector_dim > 0 && vector.len() != meta.vector_dim {
-                return Err(VectorStoreError::DimensionMismatch {
-                    expected: meta.vector_dim,
-                    got: vector.len(),
-                });
+    /// Add a default-namespace record, as stored, to the ANN index and the
+    /// query cache, whichever are built
+    fn index_record(&self, id: &str, vec_bytes: &[u8]) -> Result<(), VectorStoreError> {
+        let mut ann = self.ann.write().expect("ANN index lock poisoned");
+        let mut cache = self.cache.write().expect("query cache lock poisoned");
+        if ann.is_none() && cache.is_none() {
+            return Ok(());
+        }
+        // An unreadable record drops the cache, so queries scan and report it
+        let vector = match decode_record(vec_bytes) {
+            Ok(vector) => vector,
+            Err(_) if ann.is_none() => {
+                *cache = None;
+                return Ok(());
             }
-
-            // Store vector as raw f32 le_bytes
-            let vec_key = format!("ns:{ns}:vec:{id}");
-            let vec_bytes: Vec<u8> = vector.iter().flat_map(|f| f.to_le_bytes()).collect();
-            self.db.put(vec_key.as_bytes(), &vec_bytes).await?;
-
-            // Store attributes separately as JSON
-            let doc_key = format!("ns:{ns}:doc:{id}");
-            if let Some(attrs) = &attributes {
-                let doc_bytes = serde_json::to_vec(attrs)?;
-                self.db.put(doc_key.as_bytes(), &doc_bytes).await?;
+            Err(e) => {
+                *cache = None;
+                return Err(e);
             }
-
-            // Update approx row count (best effort — not atomic)
-            let meta_key = format!("ns:{ns}:meta");
-            let updated_meta = NamespaceMetadata {
-                approx_row_count: meta.approx_row_count + 1,
-                ..meta
-            };
-            let meta_bytes = serde_json::to_vec(&updated_meta)?;
-            self.db.put(meta_key.as_bytes(), &meta_bytes).await?;
-
-            Ok(())
+        };
+        if let Some(cache) = cache.as_mut() {
+            let vec_key = self.keys().record_key(DEFAULT_NS, "vec", id);
+            cache.insert(vec_key, CachedVector::new(id, vector.clone()));
         }
-        .await;
-        record_op("upsert", ns, start, result.is_ok());
-        result
+        if let Some(index) = ann.as_mut() {
+            index.insert(id, vector);
+        }
+        Ok(())
     }
 
-    pub async fn query_ns(
-        &self,
-        ns: &str,
-        query_vector: &[f32],
-        top_k: usize,
-    ) -> Result<Vec<crate::models::QueryResultItem>, VectorStoreError> {
+    /// Load every vector in the default namespace into memory, so
+    /// [`VectorStore::query`] scores them without reading them from slatedb;
+    /// only the top-k results' metadata is still fetched. Worth calling right
+    /// after opening, before the first query pays for a cold scan. From then
+    /// on writes and deletes keep the cache up to date, and calling this
+    /// again reloads it. Writes made while it loads may be missing from it
+    /// until the next reload.
+    ///
+    /// Vectors are cached decoded, at 4 bytes per component whatever
+    /// [`VectorEncoding`] they are stored in.
+    pub async fn warmup(&self) -> Result<(), VectorStoreError> {
         let start = Instant::now();
         let result = async {
-            // Verify namespace exists
-            let meta = self.get_namespace(ns).await?;
-
-            if meta.vector_dim > 0 && query_vector.len() != meta.vector_dim {
-                return Err(VectorStoreError::DimensionMismatch {
-                    expected: meta.vector_dim,
-                    got: query_vector.len(),
-                });
-            }
-
-            let mut heap: BinaryHeap<ScoredItem> = BinaryHeap::new();
-
-            // Scan all vectors in this namespace
-            let vec_prefix = format!("ns:{ns}:vec:");
-            let vec_end = format!("ns:{ns}:vec;");
+            let mut cache = BTreeMap::new();
+            let (vec_prefix, vec_end) = self.keys().range(DEFAULT_NS, "vec");
             let mut iter = self
                 .db
                 .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
                 .await?;
-
             while let Ok(Some(item)) = iter.next().await {
-                // Extract id from key: "ns:{ns}:vec:{id}"
-                let key_str = String::from_utf8_lossy(&item.key);
-                let id = key_str.strip_prefix(&vec_prefix).unwrap_or("").to_string();
-
-                // Decode vector from le_bytes
-                let vec_data = decode_f32_vec(&item.value);
-                let score = cosine_similarity(query_vector, &vec_data);
-
-                let scored = ScoredItem { score, id };
-
-                if heap.len() < top_k {
-                    heap.push(scored);
-                } else if let Some(min_item) = heap.peek()
-                    && score > min_item.score
-                {
-                    heap.pop();
-                    heap.push(scored);
-                }
-            }
-
-            // Collect top-k IDs, sorted by score descending
-            let mut scored_ids: Vec<ScoredItem> = heap.into_iter().collect();
-            scored_ids.sort_by(|a, b| {
-                b.score
-                    .partial_cmp(&a.score)
-                    .unwrap_or(std::cmp::Ordering::Equal)
-            });
-
-            // Fetch attributes only for top-k results
-            let mut results = Vec::with_capacity(scored_ids.len());
-            for si in scored_ids {
-                let doc_key = format!("ns:{ns}:doc:{}", si.id);
-                let attributes = match self.db.get(doc_key.as_bytes()).await? {
-                    Some(val) => Some(serde_json::from_slice(&val)?),
-                    None => None,
-                };
-                results.push(crate::models::QueryResultItem {
-                    id: si.id,
-                    score: si.score,
-                    attributes,
-                });
+                let id = self.keys().id_from_key(&vec_prefix, &item.key);
+                let vector = decode_record(&item.value)?;
+                let vec_key = String::from_utf8_lossy(&item.key).into_owned();
+                cache.insert(vec_key, CachedVector::new(&id, vector));
             }
-
-            Ok(results)
+            *self.cache.write().expect("query cache lock poisoned") = Some(cache);
+            Ok(())
         }
         .await;
-        record_op("query", ns, start, result.is_ok());
+        record_op("warmup", DEFAULT_NS, start, result.is_ok());
         result
     }
 
-    // --- Backward-compatible methods for benchmarks ---
+    /// Reload the query cache if it is warm, after a bulk rewrite
+    async fn reload_cache(&self) -> Result<(), VectorStoreError> {
+        let warm = self
+            .cache
+            .read()
+            .expect("query cache lock poisoned")
+            .is_some();
+        if warm {
+            self.warmup().await?;
+        }
+        Ok(())
+    }
 
-    pub async fn add(
-        &self,
-        id: &str,
-        vector: Vec<f32>,
-        metadata: Option<serde_json::Value>,
-    ) -> Result<(), VectorStoreError> {
-        // Ensure _default namespace exists
-        self.ensure_default_namespace(vector.len()).await?;
-        self.upsert(DEFAULT_NS, id, vector, metadata).await
+    /// Empty the query cache, if it is warm
+    fn reset_cache(&self) {
+        if let Some(cache) = self
+            .cache
+            .write()
+            .expect("query cache lock poisoned")
+            .as_mut()
+        {
+            cache.clear();
+        }
     }
 
-    pub async fn query(
+    /// Score `query_vector` against every cached vector into `heap`. Returns
+    /// false, having done nothing, if the cache isn't warm.
+    fn scan_cache(
         &self,
         query_vector: &[f32],
-        k: usize,
-    ) -> Result<Vec<QueryResult>, VectorStoreError> {
-        // Ensure _default namespace exists
-        self.ensure_default_namespace(DEFAULT_DIM).await?;
-
-        let items = self.query_ns(DEFAULT_NS, query_vector, k).await?;
-        Ok(items
-            .into_iter()
-            .map(|item| QueryResult {
-                id: item.id,
-                score: item.score,
-                metadata: item.attributes,
-            })
-            .collect())
+        query_norm: f32,
+        metric: DistanceMetric,
+        heap: &mut TopK,
+        profile: &mut QueryProfile,
+    ) -> bool {
+        let cache = self.cache.read().expect("query cache lock poisoned");
+        let Some(cache) = cache.as_ref() else {
+            return false;
+        };
+        let custom = self.options.distance.as_deref();
+        for cached in cache.values() {
+            profile.records_scanned += 1;
+            let score_start = Instant::now();
+            let score = match (custom, metric) {
+                (Some(distance), _) => distance.score(query_vector, &cached.vector),
+                (None, DistanceMetric::Cosine) => cosine_similarity_with_norms(
+                    query_vector,
+                    query_norm,
+                    &cached.vector,
+                    cached.norm,
+                ),
+                (None, other) => similarity(other, query_vector, &cached.vector),
+            };
+            heap.offer(ScoredItem {
+                score,
+                id: cached.id.clone(),
+            });
+            profile.score_time += score_start.elapsed();
+        }
+        true
     }
 
-    async fn ensure_default_namespace(&self, dim: usize) -> Result<(), VectorStoreError> {
-        let meta_key = format!("ns:{DEFAULT_NS}:meta");
-        if self.db.get(meta_key.as_bytes()).await?.is_none() {
-            self.create_namespace(DEFAULT_NS, dim, "cosine").await?;
+    /// Draw LSH hyperplanes for a store that has records but none yet, e.g.
+    /// because LSH was just turned on, and hash its records. An empty store
+    /// draws them on its first write, once the dimension is known.
+    async fn init_lsh(&self) -> Result<(), VectorStoreError> {
+        if self.lsh.read().expect("LSH lock poisoned").is_some() {
+            return Ok(());
+        }
+        let meta_key = self.keys().meta_key(DEFAULT_NS);
+        let Some(value) = self.db.get(meta_key.as_bytes()).await? else {
+            return Ok(());
+        };
+        let meta: NamespaceMetadata = serde_json::from_slice(&value)?;
+        if meta.vector_dim != DEFAULT_DIM {
+            self.lsh_for(meta.vector_dim).await?;
+            self.rebuild_lsh().await?;
         }
         Ok(())
     }
 
-    pub async fn close(self) -> Result<(), VectorStoreError> {
-        self.db.close().await?;
-        Ok(())
+    /// The LSH hyperplanes, drawn for `dim` and persisted if LSH is on but
+    /// has none yet
+    async fn lsh_for(&self, dim: usize) -> Result<Option<Arc<Lsh>>, VectorStoreError> {
+        let Some(params) = self.options.lsh else {
+            return Ok(None);
+        };
+        if let Some(lsh) = self.lsh.read().expect("LSH lock poisoned").clone() {
+            return Ok(Some(lsh));
+        }
+        // Drawn from the seed, so concurrent first writes agree on them
+        let lsh = Arc::new(Lsh::new(params, dim));
+        self.db
+            .put(LSH_KEY.as_bytes(), &serde_json::to_vec(&*lsh)?)
+            .await?;
+        *self.lsh.write().expect("LSH lock poisoned") = Some(lsh.clone());
+        Ok(Some(lsh))
     }
-}
-
-/// Backward-compatible QueryResult for benchmarks
-#[derive(Debug, Clone)]
-pub struct QueryResult {
-    pub id: String,
-    pub score: f32,
-    pub metadata: Option<serde_json::Value>,
-}
 
-fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
-    if a.len() != b.len() || a.is_empty() {
-        return 0.0;
+    /// Keys of the buckets a default-namespace record falls in, one per table
+    fn lsh_keys(&self, lsh: &Lsh, id: &str, vector: &[f32]) -> Vec<String> {
+        lsh.buckets(vector)
+            .into_iter()
+            .enumerate()
+            .map(|(table, bucket)| {
+                self.keys()
+                    .record_key(DEFAULT_NS, "lsh", &format!("{table}:{bucket:016x}:{id}"))
+            })
+            .collect()
     }
 
-    let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
-    let magnitude_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
-    let magnitude_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    /// Bucket keys to write for a default-namespace record, hashing its
+    /// vector as stored; none when LSH is off
+    async fn lsh_keys_for(
+        &self,
+        id: &str,
+        vec_bytes: &[u8],
+    ) -> Result<Vec<String>, VectorStoreError> {
+        if self.options.lsh.is_none() {
+            return Ok(Vec::new());
+        }
+        let vector = decode_record(vec_bytes)?;
+        Ok(match self.lsh_for(vector.len()).await? {
+            Some(lsh) => self.lsh_keys(&lsh, id, &vector),
+            None => Vec::new(),
+        })
+    }
+
+    /// Buckets a replaced record's old vector fell in that its new one,
+    /// hashed into `new_keys`, doesn't
+    fn stale_lsh_keys(&self, id: &str, old_bytes: &[u8], new_keys: &[String]) -> Vec<String> {
+        let lsh = self.lsh.read().expect("LSH lock poisoned").clone();
+        match (lsh, decode_record(old_bytes)) {
+            (Some(lsh), Ok(old)) => self
+                .lsh_keys(&lsh, id, &old)
+                .into_iter()
+                .filter(|key| !new_keys.contains(key))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Replace every LSH bucket with ones hashed from the stored records, if
+    /// there are hyperplanes, after records were rewritten or written before
+    /// LSH was on
+    async fn rebuild_lsh(&self) -> Result<(), VectorStoreError> {
+        let Some(lsh) = self.lsh.read().expect("LSH lock poisoned").clone() else {
+            return Ok(());
+        };
+        let batch_size = self.options.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1);
+        let mut batch = WriteBatch::new();
+        let mut pending = 0;
+
+        let (lsh_prefix, lsh_end) = self.keys().range(DEFAULT_NS, "lsh");
+        let mut iter = self
+            .db
+            .scan(lsh_prefix.as_bytes()..lsh_end.as_bytes())
+            .await?;
+        while let Ok(Some(item)) = iter.next().await {
+            batch.delete(&item.key);
+            pending += 1;
+            if pending >= batch_size {
+                self.db
+                    .write(std::mem::replace(&mut batch, WriteBatch::new()))
+                    .await?;
+                pending = 0;
+            }
+        }
+
+        let (vec_prefix, vec_end) = self.keys().range(DEFAULT_NS, "vec");
+        let mut iter = self
+            .db
+            .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
+            .await?;
+        while let Ok(Some(item)) = iter.next().await {
+            let id = self.keys().id_from_key(&vec_prefix, &item.key);
+            for key in self.lsh_keys(&lsh, &id, &decode_record(&item.value)?) {
+                batch.put(key.as_bytes(), []);
+            }
+            pending += 1;
+            if pending >= batch_size {
+                self.db
+                    .write(std::mem::replace(&mut batch, WriteBatch::new()))
+                    .await?;
+                pending = 0;
+            }
+        }
+        self.db.write(batch).await?;
+        Ok(())
+    }
+
+    /// Fit product quantization codebooks (see [`ProductQuantizer`]) to the
+    /// default namespace's vectors, sampling evenly across them when there
+    /// are many, and encode every record with them for
+    /// [`VectorStore::query_pq`]. Replaces any earlier codebooks.
+    pub async fn train_pq(&self, params: PqParams) -> Result<(), VectorStoreError> {
+        self.ensure_default_namespace(DEFAULT_DIM).await?;
+        let meta = self.get_namespace(DEFAULT_NS).await?;
+        let stride = (meta.approx_row_count as usize / PQ_TRAINING_SAMPLE).max(1);
+        let mut sample = Vec::new();
+        let (vec_prefix, vec_end) = self.keys().range(DEFAULT_NS, "vec");
+        let mut iter = self
+            .db
+            .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
+            .await?;
+        let mut seen = 0usize;
+        while let Ok(Some(item)) = iter.next().await {
+            if seen.is_multiple_of(stride) && sample.len() < PQ_TRAINING_SAMPLE {
+                sample.push(decode_record(&item.value)?);
+            }
+            seen += 1;
+        }
+        self.set_pq(ProductQuantizer::train(&sample, params)?).await
+    }
+
+    /// Encode the default namespace with precomputed `quantizer` codebooks,
+    /// persisting them under `meta:pq` in place of any earlier ones. From
+    /// then on writes encode each record as they store it.
+    ///
+    /// The codes are an index alongside the records: full vectors are still
+    /// stored, so every other query reads them as before and
+    /// [`VectorStore::query_pq`] can be checked against them.
+    pub async fn set_pq(&self, quantizer: ProductQuantizer) -> Result<(), VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            self.ensure_default_namespace(DEFAULT_DIM).await?;
+            let meta = self.get_namespace(DEFAULT_NS).await?;
+            if meta.vector_dim > 0 && quantizer.dim() != meta.vector_dim {
+                return Err(VectorStoreError::DimensionMismatch {
+                    expected: meta.vector_dim,
+                    got: quantizer.dim(),
+                });
+            }
+            // The codebooks are only persisted once every code is encoded
+            // with them, so a crash in between leaves none rather than
+            // codebooks that old codes don't fit
+            let codebooks = serde_json::to_vec(&quantizer)?;
+            self.db.delete(PQ_KEY.as_bytes()).await?;
+            *self.pq.write().expect("PQ lock poisoned") = Some(Arc::new(quantizer));
+            self.rebuild_pq().await?;
+            self.db.put(PQ_KEY.as_bytes(), &codebooks).await?;
+            Ok(())
+        }
+        .await;
+        record_op("set_pq", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// The product quantization codes key and value to write for a
+    /// default-namespace record, encoding its vector as stored; none without
+    /// codebooks for its dimension
+    fn pq_entry(
+        &self,
+        id: &str,
+        vec_bytes: &[u8],
+    ) -> Result<Option<(String, Vec<u8>)>, VectorStoreError> {
+        let Some(pq) = self.pq.read().expect("PQ lock poisoned").clone() else {
+            return Ok(None);
+        };
+        let vector = decode_record(vec_bytes)?;
+        if vector.len() != pq.dim() {
+            return Ok(None);
+        }
+        let key = self.keys().record_key(DEFAULT_NS, "pq", id);
+        Ok(Some((key, pq.encode(&vector))))
+    }
+
+    /// Replace every product quantization code with ones encoded from the
+    /// stored records, if there are codebooks, after records were rewritten
+    /// or the codebooks changed
+    async fn rebuild_pq(&self) -> Result<(), VectorStoreError> {
+        if self.pq.read().expect("PQ lock poisoned").is_none() {
+            return Ok(());
+        }
+        let batch_size = self.options.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1);
+        let mut batch = WriteBatch::new();
+        let mut pending = 0;
+
+        let (pq_prefix, pq_end) = self.keys().range(DEFAULT_NS, "pq");
+        let mut iter = self
+            .db
+            .scan(pq_prefix.as_bytes()..pq_end.as_bytes())
+            .await?;
+        while let Ok(Some(item)) = iter.next().await {
+            batch.delete(&item.key);
+            pending += 1;
+            if pending >= batch_size {
+                self.db
+                    .write(std::mem::replace(&mut batch, WriteBatch::new()))
+                    .await?;
+                pending = 0;
+            }
+        }
+
+        let (vec_prefix, vec_end) = self.keys().range(DEFAULT_NS, "vec");
+        let mut iter = self
+            .db
+            .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
+            .await?;
+        while let Ok(Some(item)) = iter.next().await {
+            let id = self.keys().id_from_key(&vec_prefix, &item.key);
+            if let Some((key, codes)) = self.pq_entry(&id, &item.value)? {
+                batch.put(key.as_bytes(), &codes);
+            }
+            pending += 1;
+            if pending >= batch_size {
+                self.db
+                    .write(std::mem::replace(&mut batch, WriteBatch::new()))
+                    .await?;
+                pending = 0;
+            }
+        }
+        self.db.write(batch).await?;
+        Ok(())
+    }
+
+    /// What the default namespace's queries are scored with, falling back to
+    /// the store's distance while the namespace doesn't exist yet
+    async fn default_distance(&self) -> Result<Arc<dyn Distance>, VectorStoreError> {
+        let meta_key = self.keys().meta_key(DEFAULT_NS);
+        Ok(
+            match (
+                self.db.get(meta_key.as_bytes()).await?,
+                &self.options.distance,
+            ) {
+                (Some(value), _) => self.namespace_distance(&serde_json::from_slice(&value)?),
+                (None, Some(distance)) => distance.clone(),
+                (None, None) => Arc::new(self.metric),
+            },
+        )
+    }
+
+    /// The metric queries are scored with
+    pub fn metric(&self) -> DistanceMetric {
+        self.metric
+    }
+
+    /// The metric a namespace's queries are scored with. Namespaces created
+    /// before their `distance` was checked may hold any string; one that
+    /// isn't a known metric falls back to the store's, which is what scored
+    /// them then.
+    fn namespace_metric(&self, meta: &NamespaceMetadata) -> DistanceMetric {
+        meta.distance.parse().unwrap_or(self.metric)
+    }
+
+    /// What a namespace's queries are scored with: the custom
+    /// [`VectorStoreOptions::distance`] if there is one, else its metric
+    fn namespace_distance(&self, meta: &NamespaceMetadata) -> Arc<dyn Distance> {
+        match &self.options.distance {
+            Some(distance) => distance.clone(),
+            None => Arc::new(self.namespace_metric(meta)),
+        }
+    }
+
+    /// Open the store after an unclean shutdown and check it is usable.
+    ///
+    /// Opening replays slatedb's WAL, which is the recovery step; afterwards
+    /// every namespace's metadata is decoded and up to [`VERIFY_SAMPLE_SIZE`]
+    /// records of each kind (vectors, sparse vectors, document chunks and
+    /// their metadata) per namespace are decoded, vectors checked against it.
+    /// Problems are collected in the report rather than returned as errors.
+    pub async fn open_and_verify<P: AsRef<str>>(
+        path: P,
+        object_store: Arc<dyn ObjectStore>,
+    ) -> Result<(Self, RecoveryReport), VectorStoreError> {
+        let store = Self::open(path, object_store).await?;
+        let report = store.verify().await?;
+        Ok((store, report))
+    }
+
+    async fn verify(&self) -> Result<RecoveryReport, VectorStoreError> {
+        let mut report = RecoveryReport::default();
+        let mut namespaces: HashMap<String, NamespaceMetadata> = HashMap::new();
+        let mut sampled: HashMap<(String, String), usize> = HashMap::new();
+
+        // Kinds sort as chunk < chunk_doc < doc < lsh < meta < norm < pq <
+        // sparse < sparse_doc < vec within a namespace, so only vectors come
+        // after the metadata and are checked against its dimension
+        let mut iter = self.db.scan("ns:".as_bytes().."ns;".as_bytes()).await?;
+        while let Ok(Some(item)) = iter.next().await {
+            let key_str = String::from_utf8_lossy(&item.key);
+            let mut parts = key_str.strip_prefix("ns:").unwrap_or("").splitn(3, ':');
+            let (Some(ns), Some(kind)) = (parts.next(), parts.next()) else {
+                report.problems.push(format!("Unrecognized key: {key_str}"));
+                continue;
+            };
+            let id = parts.next().unwrap_or("");
+
+            if kind == "meta" {
+                match serde_json::from_slice::<NamespaceMetadata>(&item.value) {
+                    Ok(meta) => {
+                        namespaces.insert(ns.to_string(), meta);
+                    }
+                    Err(e) => report
+                        .problems
+                        .push(format!("Namespace {ns}: unreadable metadata: {e}")),
+                }
+                report.namespaces += 1;
+                continue;
+            }
+
+            // Cached norms and index entries are derived from the records,
+            // so they are checked but not counted as records
+            match kind {
+                "norm" => {
+                    if item.value.len() != 4 {
+                        report.problems.push(format!(
+                            "Namespace {ns}: norm for {id} is {} bytes, not one f32",
+                            item.value.len()
+                        ));
+                    }
+                    continue;
+                }
+                "pq" => {
+                    let pq = self.pq.read().expect("PQ lock poisoned").clone();
+                    if pq.is_some_and(|pq| !pq.fits(&item.value)) {
+                        report.problems.push(format!(
+                            "Namespace {ns}: PQ codes for {id} don't match the codebooks"
+                        ));
+                    }
+                    continue;
+                }
+                "lsh" => continue,
+                _ => {}
+            }
+
+            let count = sampled
+                .entry((ns.to_string(), kind.to_string()))
+                .or_insert(0);
+            if *count >= VERIFY_SAMPLE_SIZE {
+                continue;
+            }
+            *count += 1;
+            report.records_sampled += 1;
+
+            match kind {
+                "doc" | "sparse_doc" | "chunk_doc" => {
+                    if let Err(e) = self.serialization.decode(&item.value) {
+                        report
+                            .problems
+                            .push(format!("Namespace {ns}: unreadable document {id}: {e}"));
+                    }
+                }
+                "sparse" => {
+                    if let Err(e) = decode_sparse_vec(&item.value) {
+                        report.problems.push(format!(
+                            "Namespace {ns}: unreadable sparse vector {id}: {e}"
+                        ));
+                    }
+                }
+                "chunk" => {
+                    if let Err(e) = decode_record(&item.value) {
+                        report
+                            .problems
+                            .push(format!("Namespace {ns}: unreadable chunk {id}: {e}"));
+                    }
+                }
+                "vec" => {
+                    let Some(meta) = namespaces.get(ns) else {
+                        report
+                            .problems
+                            .push(format!("Namespace {ns}: vector {id} has no namespace"));
+                        continue;
+                    };
+                    match decode_record(&item.value) {
+                        Err(e) => report
+                            .problems
+                            .push(format!("Namespace {ns}: unreadable vector {id}: {e}")),
+                        Ok(vector) if meta.vector_dim > 0 && vector.len() != meta.vector_dim => {
+                            report.problems.push(format!(
+                                "Namespace {ns}: vector {id} has dimension {}, expected {}",
+                                vector.len(),
+                                meta.vector_dim
+                            ))
+                        }
+                        Ok(_) => {}
+                    }
+                }
+                _ => report.problems.push(format!("Unrecognized key: {key_str}")),
+            }
+        }
+
+        Ok(report)
+    }
+
+    fn keys(&self) -> KeyEncoding {
+        self.options.key_encoding.unwrap_or_default()
+    }
+
+    /// The `norm` key caching the magnitude of the vector at `vec_key`. Both
+    /// share the same encoded id, so the two ranges sort in lockstep.
+    fn norm_key(&self, ns: &str, vec_key: &[u8]) -> Vec<u8> {
+        let (vec_prefix, _) = self.keys().range(ns, "vec");
+        let (norm_prefix, _) = self.keys().range(ns, "norm");
+        [norm_prefix.as_bytes(), &vec_key[vec_prefix.len()..]].concat()
+    }
+
+    /// Encode `vector` with the configured [`VectorEncoding`], along with the
+    /// norm to cache for it: that of the vector as it will read back, which
+    /// for lossy encodings is not quite `vector`'s own
+    fn encode_vector(&self, vector: &[f32]) -> (Vec<u8>, [u8; 4]) {
+        match self.options.vector_encoding {
+            VectorEncoding::F32 => (encode_f32_vec(vector), encode_norm(vector)),
+            VectorEncoding::Int8 => {
+                let quantized = QuantizedVector::quantize(vector);
+                (
+                    encode_int8_vec(&quantized),
+                    encode_norm(&quantized.dequantize()),
+                )
+            }
+            VectorEncoding::F16 => {
+                let halves = to_f16(vector);
+                let rounded: Vec<f32> = halves.iter().map(|h| h.to_f32()).collect();
+                (encode_f16_vec(&halves), encode_norm(&rounded))
+            }
+            VectorEncoding::Binary => {
+                let binary = BinaryVector::quantize(vector);
+                (
+                    encode_binary_vec(&binary),
+                    encode_norm(&binary.dequantize()),
+                )
+            }
+        }
+    }
+
+    /// Enforce [`VectorStoreOptions::expected_dim`] and
+    /// [`VectorStoreOptions::max_dimension`] on a vector of length `len`
+    fn check_dimension(&self, len: usize) -> Result<(), VectorStoreError> {
+        if let Some(expected) = self.options.expected_dim
+            && len != expected
+        {
+            return Err(VectorStoreError::DimensionMismatch { expected, got: len });
+        }
+        match self.options.max_dimension {
+            Some(max) if len > max => Err(VectorStoreError::DimensionTooLarge { max, got: len }),
+            _ => Ok(()),
+        }
+    }
+
+    // --- Namespace operations ---
+
+    pub async fn create_namespace(
+        &self,
+        name: &str,
+        vector_dim: usize,
+        distance: &str,
+    ) -> Result<NamespaceMetadata, VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            let metric: DistanceMetric = distance.parse()?;
+            if self.keys() == KeyEncoding::Raw && name.contains(':') {
+                return Err(VectorStoreError::InvalidRequest(format!(
+                    "Namespace name {name:?} contains ':', which needs KeyEncoding::Escaped"
+                )));
+            }
+            let meta_key = self.keys().meta_key(name);
+
+            // Check if namespace already exists
+            if self.db.get(meta_key.as_bytes()).await?.is_some() {
+                return Err(VectorStoreError::NamespaceAlreadyExists(name.to_string()));
+            }
+
+            let metadata = NamespaceMetadata {
+                name: name.to_string(),
+                vector_dim,
+                distance: metric.as_str().to_string(),
+                approx_row_count: 0,
+                created_at: Utc::now(),
+                standardization: None,
+            };
+
+            let value = serde_json::to_vec(&metadata)?;
+            self.db.put(meta_key.as_bytes(), &value).await?;
+            if name == DEFAULT_NS {
+                // A built index may have been scored with another metric
+                self.rebuild_ann_index().await?;
+            }
+
+            Ok(metadata)
+        }
+        .await;
+        record_op("create_namespace", name, start, result.is_ok());
+        result
+    }
+
+    pub async fn get_namespace(&self, name: &str) -> Result<NamespaceMetadata, VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            let meta_key = self.keys().meta_key(name);
+            match self.db.get(meta_key.as_bytes()).await? {
+                Some(value) => Ok(serde_json::from_slice(&value)?),
+                None => Err(VectorStoreError::NamespaceNotFound(name.to_string())),
+            }
+        }
+        .await;
+        record_op("get_namespace", name, start, result.is_ok());
+        result
+    }
+
+    pub async fn delete_namespace(&self, name: &str) -> Result<(), VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            let meta_key = self.keys().meta_key(name);
+
+            // Verify namespace exists
+            if self.db.get(meta_key.as_bytes()).await?.is_none() {
+                return Err(VectorStoreError::NamespaceNotFound(name.to_string()));
+            }
+
+            // Delete every record, cached norm and index entry in this namespace
+            for kind in RECORD_KINDS {
+                let (prefix, end) = self.keys().range(name, kind);
+                let mut iter = self.db.scan(prefix.as_bytes()..end.as_bytes()).await?;
+                while let Ok(Some(item)) = iter.next().await {
+                    self.db.delete(&item.key).await?;
+                }
+            }
+
+            // Delete the metadata key
+            self.db.delete(meta_key.as_bytes()).await?;
+            if name == DEFAULT_NS {
+                // Hyperplanes and codebooks were drawn for the old dimension;
+                // LSH draws new ones on the next write, PQ needs retraining
+                self.db.delete(LSH_KEY.as_bytes()).await?;
+                self.db.delete(PQ_KEY.as_bytes()).await?;
+                *self.lsh.write().expect("LSH lock poisoned") = None;
+                *self.pq.write().expect("PQ lock poisoned") = None;
+                self.reset_ann_index();
+                self.reset_cache();
+            }
+
+            Ok(())
+        }
+        .await;
+        record_op("delete_namespace", name, start, result.is_ok());
+        result
+    }
+
+    // --- Data operations ---
+
+    /// Write a record to `ns`, replacing any record with the same id.
+    /// Returns `true` if one was replaced and `false` for a fresh insert.
+    pub async fn upsert(
+        &self,
+        ns: &str,
+        id: &str,
+        vector: Vec<f32>,
+        attributes: Option<serde_json::Value>,
+    ) -> Result<bool, VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            check_vector(id, &vector)?;
+            self.check_dimension(vector.len())?;
+
+            // Get namespace metadata to validate dimensions
+            let meta = self.get_namespace(ns).await?;
+
+            if meta.vector_dim > 0 && vector.len() != meta.vector_dim {
+                return Err(VectorStoreError::DimensionMismatch {
+                    expected: meta.vector_dim,
+                    got: vector.len(),
+                });
+            }
+
+            let vector = standardized(&meta, &vector);
+
+            // Store the vector in the configured encoding
+            let vec_key = self.keys().record_key(ns, "vec", id);
+            let previous = self.db.get(vec_key.as_bytes()).await?;
+            let replaced = previous.is_some();
+            let (vec_bytes, norm) = self.encode_vector(&vector);
+            self.put_verified(vec_key.as_bytes(), &vec_bytes).await?;
+            self.db
+                .put(&self.norm_key(ns, vec_key.as_bytes()), &norm)
+                .await?;
+            if ns == DEFAULT_NS {
+                self.index_record(id, &vec_bytes)?;
+                let lsh_keys = self.lsh_keys_for(id, &vec_bytes).await?;
+                let stale_keys = match &previous {
+                    Some(old_bytes) => self.stale_lsh_keys(id, old_bytes, &lsh_keys),
+                    None => Vec::new(),
+                };
+                let pq_entry = self.pq_entry(id, &vec_bytes)?;
+                if !lsh_keys.is_empty() || !stale_keys.is_empty() || pq_entry.is_some() {
+                    let mut batch = WriteBatch::new();
+                    for key in stale_keys {
+                        batch.delete(key.as_bytes());
+                    }
+                    for key in lsh_keys {
+                        batch.put(key.as_bytes(), []);
+                    }
+                    if let Some((key, codes)) = pq_entry {
+                        batch.put(key.as_bytes(), &codes);
+                    }
+                    self.db.write(batch).await?;
+                }
+            }
+
+            // Store attributes separately as JSON; a replaced record's are
+            // dropped along with it
+            let doc_key = self.keys().record_key(ns, "doc", id);
+            match &attributes {
+                Some(attrs) => {
+                    let doc_bytes = self.serialization.encode(attrs)?;
+                    self.put_verified(doc_key.as_bytes(), &doc_bytes).await?;
+                }
+                None if replaced => self.db.delete(doc_key.as_bytes()).await?,
+                None => {}
+            }
+
+            // Update approx row count (best effort — not atomic)
+            if !replaced {
+                let meta_key = self.keys().meta_key(ns);
+                let updated_meta = NamespaceMetadata {
+                    approx_row_count: meta.approx_row_count + 1,
+                    ..meta
+                };
+                let meta_bytes = serde_json::to_vec(&updated_meta)?;
+                self.db.put(meta_key.as_bytes(), &meta_bytes).await?;
+            }
+
+            Ok(replaced)
+        }
+        .await;
+        record_op("upsert", ns, start, result.is_ok());
+        result
+    }
+
+    pub async fn query_ns(
+        &self,
+        ns: &str,
+        query_vector: &[f32],
+        top_k: usize,
+    ) -> Result<Vec<crate::models::QueryResultItem>, VectorStoreError> {
+        let (results, _) = self.query_ns_profiled(ns, query_vector, top_k).await?;
+        Ok(results)
+    }
+
+    pub async fn query_ns_profiled(
+        &self,
+        ns: &str,
+        query_vector: &[f32],
+        top_k: usize,
+    ) -> Result<(Vec<crate::models::QueryResultItem>, QueryProfile), VectorStoreError> {
+        self.query_ns_with_norm(ns, query_vector, magnitude(query_vector), top_k)
+            .await
+    }
+
+    /// Scan `ns` scoring against a query whose magnitude was computed up front,
+    /// so it is never recomputed per record.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "query", skip_all, fields(ns = ns, k = top_k))
+    )]
+    async fn query_ns_with_norm(
+        &self,
+        ns: &str,
+        query_vector: &[f32],
+        query_norm: f32,
+        top_k: usize,
+    ) -> Result<(Vec<crate::models::QueryResultItem>, QueryProfile), VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            self.check_dimension(query_vector.len())?;
+
+            // Verify namespace exists
+            let meta = self.get_namespace(ns).await?;
+
+            if meta.vector_dim > 0 && query_vector.len() != meta.vector_dim {
+                return Err(VectorStoreError::DimensionMismatch {
+                    expected: meta.vector_dim,
+                    got: query_vector.len(),
+                });
+            }
+
+            // Stored vectors were standardized on write, so the query must be too
+            let (query_vector, query_norm) = match standardized(&meta, query_vector) {
+                Cow::Owned(v) => {
+                    let norm = magnitude(&v);
+                    (Cow::Owned(v), norm)
+                }
+                borrowed => (borrowed, query_norm),
+            };
+            let query_vector = &query_vector[..];
+
+            let mut profile = QueryProfile::default();
+            // The heap never holds more than top_k items, nor more than the
+            // namespace has; the row count bounds the allocation for huge top_k
+            let capacity = top_k.min(meta.approx_row_count as usize);
+            let metric = self.namespace_metric(&meta);
+            let custom = self.options.distance.as_deref();
+            let higher_is_better = match custom {
+                Some(distance) => distance.higher_is_better(),
+                None => metric.higher_is_better(),
+            };
+            let mut heap = TopK::with_capacity(top_k, capacity, higher_is_better);
+
+            // Scan all vectors in this namespace
+            let scan_start = Instant::now();
+            #[cfg(feature = "tracing")]
+            let scan_span = tracing::info_span!(
+                "scan",
+                record_count = tracing::field::Empty,
+                scan_duration_us = tracing::field::Empty,
+            );
+            let scan = async {
+                if ns == DEFAULT_NS
+                    && self.scan_cache(query_vector, query_norm, metric, &mut heap, &mut profile)
+                {
+                    return Ok(());
+                }
+
+                let (vec_prefix, vec_end) = self.keys().range(ns, "vec");
+                let mut iter = self
+                    .db
+                    .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
+                    .await?;
+
+                // Cached norms are read in lockstep with the vectors they belong
+                // to; vectors written before norms were cached have none
+                let (norm_prefix, norm_end) = self.keys().range(ns, "norm");
+                let mut norms = self
+                    .db
+                    .scan(norm_prefix.as_bytes()..norm_end.as_bytes())
+                    .await?;
+                let mut next_norm = match (custom, metric) {
+                    (None, DistanceMetric::Cosine) => norms.next().await.ok().flatten(),
+                    _ => None,
+                };
+                // Sign-bit records are compared word by word, without unpacking
+                let query_bits = match (custom, metric) {
+                    (None, DistanceMetric::Hamming) => Some(BinaryVector::quantize(query_vector)),
+                    _ => None,
+                };
+                // Int8 records are scored in integer space against a query
+                // quantized once, without dequantizing each of them
+                let query_int8 = match (custom, metric) {
+                    (None, DistanceMetric::Cosine | DistanceMetric::DotProduct) => {
+                        Some(QuantizedVector::quantize(query_vector))
+                    }
+                    _ => None,
+                };
+
+                while let Ok(Some(item)) = iter.next().await {
+                    profile.records_scanned += 1;
+                    profile.bytes_read += item.key.len() + item.value.len();
+
+                    let id = self.keys().id_from_key(&vec_prefix, &item.key);
+
+                    if let Some(query_bits) = &query_bits
+                        && let Some(stored) = decode_binary_record(&item.value)
+                    {
+                        let score_start = Instant::now();
+                        let score = query_bits.hamming_distance(&stored) as f32;
+                        heap.offer(ScoredItem { score, id });
+                        profile.score_time += score_start.elapsed();
+                        continue;
+                    }
+                    if let Some(query_int8) = &query_int8
+                        && let Some(stored) = decode_int8_record(&item.value)
+                        && stored.values.len() == query_int8.values.len()
+                    {
+                        let score_start = Instant::now();
+                        let score = match metric {
+                            DistanceMetric::DotProduct => query_int8.dot(&stored),
+                            _ => query_int8.cosine(&stored),
+                        };
+                        heap.offer(ScoredItem { score, id });
+                        profile.score_time += score_start.elapsed();
+                        continue;
+                    }
+
+                    // Decode the vector from whichever format it was written in
+                    let vec_data = decode_record(&item.value)?;
+
+                    let encoded_id = &item.key[vec_prefix.len()..];
+                    let mut cached_norm = None;
+                    while let Some(norm) = &next_norm {
+                        match norm.key[norm_prefix.len()..].cmp(encoded_id) {
+                            std::cmp::Ordering::Less => {
+                                next_norm = norms.next().await.ok().flatten();
+                            }
+                            std::cmp::Ordering::Equal => {
+                                cached_norm = <[u8; 4]>::try_from(&norm.value[..])
+                                    .ok()
+                                    .map(f32::from_le_bytes);
+                                break;
+                            }
+                            std::cmp::Ordering::Greater => break,
+                        }
+                    }
+
+                    let score_start = Instant::now();
+                    let score = match (custom, metric, cached_norm) {
+                        (Some(distance), _, _) => distance.score(query_vector, &vec_data),
+                        (None, DistanceMetric::Cosine, Some(norm)) => {
+                            cosine_similarity_with_norms(query_vector, query_norm, &vec_data, norm)
+                        }
+                        (None, DistanceMetric::Cosine, None) => {
+                            cosine_similarity_with_norm(query_vector, query_norm, &vec_data)
+                        }
+                        (None, other, _) => similarity(other, query_vector, &vec_data),
+                    };
+
+                    heap.offer(ScoredItem { score, id });
+                    profile.score_time += score_start.elapsed();
+                }
+                Ok::<_, VectorStoreError>(())
+            };
+            #[cfg(feature = "tracing")]
+            let scan = tracing::Instrument::instrument(scan, scan_span.clone());
+            scan.await?;
+            #[cfg(feature = "tracing")]
+            {
+                scan_span.record("record_count", profile.records_scanned);
+                scan_span.record("scan_duration_us", scan_start.elapsed().as_micros() as u64);
+            }
+            // Scoring happens inline with the scan, so only the remainder is scan time
+            profile.scan_time = scan_start.elapsed().saturating_sub(profile.score_time);
+
+            // Collect top-k IDs, sorted by score descending
+            let sort_start = Instant::now();
+            let scored_ids = heap.into_sorted_vec();
+            profile.sort_time = sort_start.elapsed();
+
+            // Fetch attributes only for top-k results
+            let fetch_start = Instant::now();
+            let mut results = Vec::with_capacity(scored_ids.len());
+            for si in scored_ids {
+                let doc_key = self.keys().record_key(ns, "doc", &si.id);
+                let attributes = match self.db.get(doc_key.as_bytes()).await? {
+                    Some(val) => {
+                        profile.bytes_read += val.len();
+                        Some(self.serialization.decode(&val)?)
+                    }
+                    None => None,
+                };
+                results.push(crate::models::QueryResultItem {
+                    id: si.id,
+                    score: si.score,
+                    attributes,
+                });
+            }
+            profile.fetch_time = fetch_start.elapsed();
+
+            Ok((results, profile))
+        }
+        .await;
+        record_op("query", ns, start, result.is_ok());
+        result
+    }
+
+    /// Standardize every vector in `ns` to zero mean and unit variance per
+    /// dimension, and persist the statistics used in the namespace metadata.
+    ///
+    /// From then on the store applies the same transform itself: vectors
+    /// (and, in the default namespace, document chunks) written to `ns` are
+    /// standardized before they are stored, and query vectors are
+    /// standardized before scoring, so callers keep passing raw embeddings.
+    /// Dimensions with zero variance are only centered. A
+    /// namespace can be standardized once; refitting would transform the
+    /// stored vectors twice.
+    pub async fn standardize(&self, ns: &str) -> Result<Standardization, VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            let meta = self.get_namespace(ns).await?;
+            if meta.standardization.is_some() {
+                return Err(VectorStoreError::InvalidRequest(format!(
+                    "Namespace {ns} is already standardized"
+                )));
+            }
+
+            // First pass: per-dimension sums, accumulated in f64 for stability
+            let (vec_prefix, vec_end) = self.keys().range(ns, "vec");
+            let mut sum: Vec<f64> = Vec::new();
+            let mut sum_sq: Vec<f64> = Vec::new();
+            let mut count = 0usize;
+            let mut iter = self
+                .db
+                .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
+                .await?;
+            while let Ok(Some(item)) = iter.next().await {
+                let vector = decode_record(&item.value)?;
+                if count == 0 {
+                    sum = vec![0.0; vector.len()];
+                    sum_sq = vec![0.0; vector.len()];
+                } else if vector.len() != sum.len() {
+                    return Err(VectorStoreError::DimensionMismatch {
+                        expected: sum.len(),
+                        got: vector.len(),
+                    });
+                }
+                for ((s, sq), x) in sum.iter_mut().zip(sum_sq.iter_mut()).zip(&vector) {
+                    *s += *x as f64;
+                    *sq += (*x as f64).powi(2);
+                }
+                count += 1;
+            }
+
+            let n = count.max(1) as f64;
+            let mean: Vec<f32> = sum.iter().map(|s| (s / n) as f32).collect();
+            let std: Vec<f32> = sum
+                .iter()
+                .zip(&sum_sq)
+                .map(|(s, sq)| {
+                    let variance = (sq / n - (s / n).powi(2)).max(0.0);
+                    if variance > 0.0 {
+                        variance.sqrt() as f32
+                    } else {
+                        1.0
+                    }
+                })
+                .collect();
+            let stats = Standardization { mean, std };
+
+            // Second pass: rewrite every vector, then record the statistics
+            let batch_size = self.options.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1);
+            let mut batch = WriteBatch::new();
+            let mut pending = 0;
+            let mut iter = self
+                .db
+                .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
+                .await?;
+            while let Ok(Some(item)) = iter.next().await {
+                let vector = stats.apply(&decode_record(&item.value)?);
+                let (vec_bytes, norm) = self.encode_vector(&vector);
+                batch.put(&item.key, vec_bytes);
+                batch.put(self.norm_key(ns, &item.key), norm);
+                pending += 1;
+
+                if pending == batch_size {
+                    self.db
+                        .write(std::mem::replace(&mut batch, WriteBatch::new()))
+                        .await?;
+                    pending = 0;
+                }
+            }
+
+            // Document chunks live only in the default namespace and are
+            // scored against the same standardized queries
+            if ns == DEFAULT_NS {
+                let (chunk_prefix, chunk_end) = self.keys().range(ns, "chunk");
+                let mut iter = self
+                    .db
+                    .scan(chunk_prefix.as_bytes()..chunk_end.as_bytes())
+                    .await?;
+                while let Ok(Some(item)) = iter.next().await {
+                    let chunk = stats.apply(&decode_record(&item.value)?);
+                    batch.put(&item.key, self.encode_vector(&chunk).0);
+                    pending += 1;
+
+                    if pending == batch_size {
+                        self.db
+                            .write(std::mem::replace(&mut batch, WriteBatch::new()))
+                            .await?;
+                        pending = 0;
+                    }
+                }
+            }
+
+            let updated_meta = NamespaceMetadata {
+                standardization: Some(stats.clone()),
+                ..meta
+            };
+            batch.put(
+                self.keys().meta_key(ns).as_bytes(),
+                &serde_json::to_vec(&updated_meta)?,
+            );
+            self.db.write(batch).await?;
+            if ns == DEFAULT_NS {
+                self.rebuild_ann_index().await?;
+                self.reload_cache().await?;
+                self.rebuild_lsh().await?;
+                self.rebuild_pq().await?;
+            }
+
+            Ok(stats)
+        }
+        .await;
+        record_op("standardize", ns, start, result.is_ok());
+        result
+    }
+
+    // --- Backward-compatible methods for benchmarks ---
+
+    /// [`VectorStore::upsert`] into the default namespace, creating it on
+    /// first use. Returns `true` if a record with `id` was replaced.
+    pub async fn add(
+        &self,
+        id: &str,
+        vector: Vec<f32>,
+        metadata: Option<serde_json::Value>,
+    ) -> Result<bool, VectorStoreError> {
+        self.add_to(DEFAULT_NS, id, vector, metadata).await
+    }
+
+    /// [`VectorStore::add`] for any namespace
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "add", skip_all, fields(ns = ns, id = id, dim = vector.len()))
+    )]
+    pub(crate) async fn add_to(
+        &self,
+        ns: &str,
+        id: &str,
+        vector: Vec<f32>,
+        metadata: Option<serde_json::Value>,
+    ) -> Result<bool, VectorStoreError> {
+        // Validate before the namespace gets created with this dimension
+        check_vector(id, &vector)?;
+        self.check_dimension(vector.len())?;
+
+        self.ensure_namespace(ns, vector.len()).await?;
+        self.upsert(ns, id, vector, metadata).await
+    }
+
+    /// A handle on the namespace `name`, created with the store's metric on
+    /// its first write
+    pub fn collection(&self, name: &str) -> Collection<'_> {
+        Collection::new(self, name)
+    }
+
+    /// Add a record under the next id from a persisted counter and return
+    /// that id. Ids start at 1 and are stored zero-padded to 20 digits (so
+    /// `7` is stored as `"00000000000000000007"`), which keeps key order equal
+    /// to append order.
+    ///
+    /// The counter is bumped before the record is written, so a failed append
+    /// leaves a gap but an id is never handed out twice.
+    pub async fn append(
+        &self,
+        vector: Vec<f32>,
+        metadata: Option<serde_json::Value>,
+    ) -> Result<u64, VectorStoreError> {
+        let seq = self.next_append_seq().await?;
+        self.add(&append_id(seq), vector, metadata).await?;
+        Ok(seq)
+    }
+
+    /// [`VectorStore::append`], returning the generated id as stored, ready
+    /// to pass to [`VectorStore::get`] or [`VectorStore::delete`]
+    pub async fn add_auto(
+        &self,
+        vector: Vec<f32>,
+        metadata: Option<serde_json::Value>,
+    ) -> Result<String, VectorStoreError> {
+        let id = append_id(self.next_append_seq().await?);
+        self.add(&id, vector, metadata).await?;
+        Ok(id)
+    }
+
+    /// Bump the persisted append counter and return its new value, skipping
+    /// values whose ids were already taken by explicit [`VectorStore::add`]s.
+    /// Serialized by `append_lock`, so concurrent callers never get the same
+    /// value.
+    async fn next_append_seq(&self) -> Result<u64, VectorStoreError> {
+        let _guard = self.append_lock.lock().await;
+        let mut seq = match self.db.get(APPEND_SEQ_KEY.as_bytes()).await? {
+            Some(value) => serde_json::from_slice::<u64>(&value)?,
+            None => 0,
+        };
+        loop {
+            seq += 1;
+            let vec_key = self.keys().record_key(DEFAULT_NS, "vec", &append_id(seq));
+            if self.db.get(vec_key.as_bytes()).await?.is_none() {
+                break;
+            }
+        }
+        self.db
+            .put(APPEND_SEQ_KEY.as_bytes(), &serde_json::to_vec(&seq)?)
+            .await?;
+        Ok(seq)
+    }
+
+    /// The stored vector for `id` in the default namespace exactly as written
+    /// (a format header and little-endian `f32`s), for copying into
+    /// [`VectorStore::add_raw`]
+    pub async fn get_raw(&self, id: &str) -> Result<Option<Vec<u8>>, VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            let vec_key = self.keys().record_key(DEFAULT_NS, "vec", id);
+            Ok(self
+                .get_visible(vec_key.as_bytes())
+                .await?
+                .map(|b| b.to_vec()))
+        }
+        .await;
+        record_op("get_raw", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Write already-encoded vector bytes (as returned by
+    /// [`VectorStore::get_raw`]) under `id` without decoding them. With
+    /// `validate` set, the bytes must decode to a vector of the namespace's
+    /// dimension. Metadata and the approximate row count are not touched.
+    pub async fn add_raw(
+        &self,
+        id: &str,
+        bytes: Vec<u8>,
+        validate: bool,
+    ) -> Result<(), VectorStoreError> {
+        let decoded = decode_record(&bytes);
+        let dim = decoded.as_ref().map_or(DEFAULT_DIM, Vec::len);
+
+        let start = Instant::now();
+        let result = async {
+            // Validate before the namespace gets created with this dimension
+            if validate {
+                if let Err(e) = &decoded {
+                    return Err(VectorStoreError::InvalidRequest(format!(
+                        "Raw vector for {id} does not decode: {e}"
+                    )));
+                }
+                self.check_dimension(dim)?;
+            }
+            self.ensure_default_namespace(dim).await?;
+
+            if validate {
+                let meta = self.get_namespace(DEFAULT_NS).await?;
+                if meta.vector_dim > 0 && dim != meta.vector_dim {
+                    return Err(VectorStoreError::DimensionMismatch {
+                        expected: meta.vector_dim,
+                        got: dim,
+                    });
+                }
+            }
+
+            let vec_key = self.keys().record_key(DEFAULT_NS, "vec", id);
+            let mut batch = WriteBatch::new();
+            batch.put(vec_key.as_bytes(), &bytes);
+            let norm_key = self.norm_key(DEFAULT_NS, vec_key.as_bytes());
+            let lsh_keys = match &decoded {
+                Ok(_) => self.lsh_keys_for(id, &bytes).await?,
+                Err(_) => Vec::new(),
+            };
+            if self.options.lsh.is_some()
+                && let Some(old_bytes) = self.db.get(vec_key.as_bytes()).await?
+            {
+                for key in self.stale_lsh_keys(id, &old_bytes, &lsh_keys) {
+                    batch.delete(key.as_bytes());
+                }
+            }
+            match &decoded {
+                Ok(vector) => {
+                    batch.put(norm_key, encode_norm(vector));
+                    for key in lsh_keys {
+                        batch.put(key.as_bytes(), []);
+                    }
+                    if let Some((key, codes)) = self.pq_entry(id, &bytes)? {
+                        batch.put(key.as_bytes(), &codes);
+                    }
+                }
+                Err(_) => batch.delete(norm_key),
+            }
+            self.db.write(batch).await?;
+            self.index_record(id, &bytes)?;
+            Ok(())
+        }
+        .await;
+        record_op("add_raw", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Add many records to the default namespace, split into write batches of
+    /// [`VectorStoreOptions::batch_size`] records. Every vector is validated
+    /// before the first batch is written, and each batch is durable before the
+    /// next one is built.
+    ///
+    /// Repeated ids are resolved by `on_duplicate` before anything is written.
+    pub async fn batch_add(
+        &self,
+        items: Vec<(String, Vec<f32>, Option<serde_json::Value>)>,
+        on_duplicate: DuplicatePolicy,
+    ) -> Result<(), VectorStoreError> {
+        let items = dedup_by_id(items, on_duplicate)?;
+        let batch_size = self.options.batch_size.unwrap_or(DEFAULT_BATCH_SIZE);
+        self.write_records(items, batch_size, "batch_add").await
+    }
+
+    /// [`VectorStore::batch_add`] for [`VectorRecord`]s. A repeated id keeps
+    /// its last record, as a loop of [`VectorStore::add`] would.
+    ///
+    /// The batch is all or nothing: every record is validated before anything
+    /// is written, and the records go into a single slatedb write batch that
+    /// commits atomically, whatever [`VectorStoreOptions::batch_size`] is. A
+    /// document and its chunks added together either all land or none do.
+    pub async fn add_batch(&self, records: Vec<VectorRecord>) -> Result<(), VectorStoreError> {
+        let items: Vec<_> = records
+            .into_iter()
+            .map(|r| (r.id, r.vector, r.metadata))
+            .collect();
+        let items = dedup_by_id(items, DuplicatePolicy::KeepLast)?;
+        let batch_size = items.len();
+        self.write_records(items, batch_size, "add_batch").await
+    }
+
+    /// Validate `items`, then write them to the default namespace in write
+    /// batches of `batch_size` records
+    async fn write_records(
+        &self,
+        items: Vec<(String, Vec<f32>, Option<serde_json::Value>)>,
+        batch_size: usize,
+        op: &'static str,
+    ) -> Result<(), VectorStoreError> {
+        let Some((_, first, _)) = items.first() else {
+            return Ok(());
+        };
+        for (id, vector, _) in &items {
+            check_vector(id, vector)?;
+            self.check_dimension(vector.len())?;
+            // Before the namespace exists, so a bad batch can't set its dimension
+            if vector.len() != first.len() {
+                return Err(VectorStoreError::DimensionMismatch {
+                    expected: first.len(),
+                    got: vector.len(),
+                });
+            }
+        }
+        self.ensure_default_namespace(first.len()).await?;
+
+        let start = Instant::now();
+        let result = async {
+            let meta = self.get_namespace(DEFAULT_NS).await?;
+            let expected = if meta.vector_dim > 0 {
+                meta.vector_dim
+            } else {
+                first.len()
+            };
+            if let Some((_, vector, _)) = items.iter().find(|(_, v, _)| v.len() != expected) {
+                return Err(VectorStoreError::DimensionMismatch {
+                    expected,
+                    got: vector.len(),
+                });
+            }
+
+            let batch_size = batch_size.max(1);
+            let meta_key = self.keys().meta_key(DEFAULT_NS);
+            let mut row_count = meta.approx_row_count;
+
+            for chunk in items.chunks(batch_size) {
+                let mut batch = WriteBatch::new();
+                let mut written = Vec::with_capacity(chunk.len());
+                let mut new_rows = 0;
+                for (id, vector, attributes) in chunk {
+                    let vec_key = self.keys().record_key(DEFAULT_NS, "vec", id);
+                    let vector = standardized(&meta, vector);
+                    let (vec_bytes, norm) = self.encode_vector(&vector);
+                    // Ids are unique within the items, so only ids without a
+                    // record yet add a row
+                    let previous = self.db.get(vec_key.as_bytes()).await?;
+                    if previous.is_none() {
+                        new_rows += 1;
+                    }
+                    batch.put(vec_key.as_bytes(), &vec_bytes);
+                    batch.put(self.norm_key(DEFAULT_NS, vec_key.as_bytes()), norm);
+                    let lsh_keys = self.lsh_keys_for(id, &vec_bytes).await?;
+                    if let Some(old_bytes) = &previous {
+                        for key in self.stale_lsh_keys(id, old_bytes, &lsh_keys) {
+                            batch.delete(key.as_bytes());
+                        }
+                    }
+                    for key in lsh_keys {
+                        batch.put(key.as_bytes(), []);
+                    }
+                    if let Some((key, codes)) = self.pq_entry(id, &vec_bytes)? {
+                        batch.put(key.as_bytes(), &codes);
+                    }
+                    written.push((id, vec_bytes));
+
+                    let doc_key = self.keys().record_key(DEFAULT_NS, "doc", id);
+                    match attributes {
+                        Some(attrs) => {
+                            batch.put(doc_key.as_bytes(), &self.serialization.encode(attrs)?)
+                        }
+                        None if previous.is_some() => batch.delete(doc_key.as_bytes()),
+                        None => {}
+                    }
+                }
+
+                // Row count rides along in the same batch (best effort, like upsert)
+                row_count += new_rows;
+                let updated_meta = NamespaceMetadata {
+                    approx_row_count: row_count,
+                    ..meta.clone()
+                };
+                batch.put(meta_key.as_bytes(), &serde_json::to_vec(&updated_meta)?);
+
+                self.db.write(batch).await?;
+                for (id, vec_bytes) in written {
+                    self.index_record(id, &vec_bytes)?;
+                }
+            }
+
+            Ok(())
+        }
+        .await;
+        record_op(op, DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    pub async fn query(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        self.check_dimension(query_vector.len())?;
+
+        // Ensure _default namespace exists
+        self.ensure_default_namespace(DEFAULT_DIM).await?;
+
+        let items = self.query_ns(DEFAULT_NS, query_vector, k).await?;
+        Ok(items
+            .into_iter()
+            .map(|item| QueryResult {
+                id: item.id,
+                score: item.score,
+                metadata: item.attributes,
+                vector: None,
+            })
+            .collect())
+    }
+
+    /// The exact top-`k` for `query_vector`, found by scoring every record
+    /// whatever indexes are built: the ground truth to measure
+    /// [`VectorStore::query_ann`] against with [`recall_at_k`].
+    pub async fn query_exact(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        // The plain query path never consults an index
+        self.query(query_vector, k).await
+    }
+
+    /// Approximate top-`k` for `query_vector` from the HNSW index (see
+    /// [`VectorStore::build_ann_index`]), considering `ef` candidates (never
+    /// fewer than `k`): a larger `ef` finds more of the true nearest
+    /// neighbors, more slowly. Without a built index this is
+    /// [`VectorStore::query`].
+    pub async fn query_ann(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        ef: usize,
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        if self.ann.read().expect("ANN index lock poisoned").is_none() {
+            return self.query(query_vector, k).await;
+        }
+        self.check_dimension(query_vector.len())?;
+        self.ensure_default_namespace(DEFAULT_DIM).await?;
+
+        let start = Instant::now();
+        let result = async {
+            let meta = self.get_namespace(DEFAULT_NS).await?;
+            if meta.vector_dim > 0 && query_vector.len() != meta.vector_dim {
+                return Err(VectorStoreError::DimensionMismatch {
+                    expected: meta.vector_dim,
+                    got: query_vector.len(),
+                });
+            }
+            let query_vector = standardized(&meta, query_vector);
+
+            let found = self
+                .ann
+                .read()
+                .expect("ANN index lock poisoned")
+                .as_ref()
+                .map(|index| index.search(&query_vector, k, ef))
+                .unwrap_or_default();
+            let scored = found
+                .into_iter()
+                .map(|(id, score)| ScoredItem { score, id })
+                .collect();
+            self.with_metadata(scored).await
+        }
+        .await;
+        record_op("query_ann", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Approximate top-`k` for `query_vector` from the LSH buckets (see
+    /// [`VectorStoreOptions::lsh`]): only records sharing a bucket with the
+    /// query in at least one table are read and scored. When none do, or
+    /// LSH is off, this is [`VectorStore::query`].
+    pub async fn query_lsh(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        let lsh = self.lsh.read().expect("LSH lock poisoned").clone();
+        let Some(lsh) = lsh else {
+            return self.query(query_vector, k).await;
+        };
+        self.check_dimension(query_vector.len())?;
+        self.ensure_default_namespace(DEFAULT_DIM).await?;
+
+        let start = Instant::now();
+        let result = async {
+            let meta = self.get_namespace(DEFAULT_NS).await?;
+            if meta.vector_dim > 0 && query_vector.len() != meta.vector_dim {
+                return Err(VectorStoreError::DimensionMismatch {
+                    expected: meta.vector_dim,
+                    got: query_vector.len(),
+                });
+            }
+            let standardized_query = standardized(&meta, query_vector);
+
+            let (lsh_prefix, _) = self.keys().range(DEFAULT_NS, "lsh");
+            let mut candidates = BTreeSet::new();
+            for (table, bucket) in lsh.buckets(&standardized_query).into_iter().enumerate() {
+                let start =
+                    self.keys()
+                        .record_key(DEFAULT_NS, "lsh", &format!("{table}:{bucket:016x}:"));
+                let mut end = start.clone().into_bytes();
+                end.push(0xFF);
+                let mut iter = self.db.scan(start.into_bytes()..end).await?;
+                while let Ok(Some(item)) = iter.next().await {
+                    let entry = self.keys().id_from_key(&lsh_prefix, &item.key);
+                    if let Some(id) = entry.splitn(3, ':').nth(2) {
+                        candidates.insert(id.to_string());
+                    }
+                }
+            }
+            if candidates.is_empty() {
+                return self.query(query_vector, k).await;
+            }
+
+            let distance = self.namespace_distance(&meta);
+            let capacity = k.min(candidates.len());
+            let mut heap = TopK::with_capacity(k, capacity, distance.higher_is_better());
+            for id in candidates {
+                // Overwritten records can linger in their old buckets, and
+                // are scored by what they hold now
+                let vec_key = self.keys().record_key(DEFAULT_NS, "vec", &id);
+                let Some(bytes) = self.db.get(vec_key.as_bytes()).await? else {
+                    continue;
+                };
+                let score = distance.score(&standardized_query, &decode_record(&bytes)?);
+                heap.offer(ScoredItem { score, id });
+            }
+            self.with_metadata(heap.into_sorted_vec()).await
+        }
+        .await;
+        record_op("query_lsh", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Approximate top-`k` for `query_vector` from the product quantization
+    /// codes (see [`VectorStore::train_pq`]): the query is scored against
+    /// every centroid once, then each record by summing a table lookup per
+    /// subspace, reading a byte per subspace instead of its full vector.
+    /// Without codebooks for the namespace's dimension this is
+    /// [`VectorStore::query`].
+    pub async fn query_pq(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        let pq = self.pq.read().expect("PQ lock poisoned").clone();
+        let Some(pq) = pq else {
+            return self.query(query_vector, k).await;
+        };
+        self.check_dimension(query_vector.len())?;
+        self.ensure_default_namespace(DEFAULT_DIM).await?;
+
+        let start = Instant::now();
+        let result = async {
+            let meta = self.get_namespace(DEFAULT_NS).await?;
+            if meta.vector_dim > 0 && query_vector.len() != meta.vector_dim {
+                return Err(VectorStoreError::DimensionMismatch {
+                    expected: meta.vector_dim,
+                    got: query_vector.len(),
+                });
+            }
+            if query_vector.len() != pq.dim() {
+                return self.query(query_vector, k).await;
+            }
+            let standardized_query = standardized(&meta, query_vector);
+
+            // A custom distance can't be split by subspace, so it scores the
+            // decoded vectors instead of a table
+            let custom = self.options.distance.clone();
+            let table = pq.score_table(self.namespace_metric(&meta), &standardized_query);
+            let higher_is_better = self.namespace_distance(&meta).higher_is_better();
+            let capacity = k.min(meta.approx_row_count as usize);
+            let mut heap = TopK::with_capacity(k, capacity, higher_is_better);
+
+            let (pq_prefix, pq_end) = self.keys().range(DEFAULT_NS, "pq");
+            let mut iter = self
+                .db
+                .scan(pq_prefix.as_bytes()..pq_end.as_bytes())
+                .await?;
+            while let Ok(Some(item)) = iter.next().await {
+                let id = self.keys().id_from_key(&pq_prefix, &item.key);
+                // Codes written with other codebooks, e.g. by a set_pq still
+                // running, would index past these ones
+                if !pq.fits(&item.value) {
+                    return Err(VectorStoreError::InvalidVector(format!(
+                        "PQ codes for {id} don't match the codebooks"
+                    )));
+                }
+                let score = match &custom {
+                    Some(distance) => distance.score(&standardized_query, &pq.decode(&item.value)),
+                    None => table.score(&item.value),
+                };
+                heap.offer(ScoredItem { score, id });
+            }
+            self.with_metadata(heap.into_sorted_vec()).await
+        }
+        .await;
+        record_op("query_pq", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Like [`VectorStore::query`], but also returns [`QueryStats`]: how
+    /// many records were examined and how long scanning and scoring them
+    /// took, e.g. for reporting "searched 12,304 vectors in 3ms".
+    pub async fn query_with_stats(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+    ) -> Result<(Vec<QueryResult>, QueryStats), VectorStoreError> {
+        let (results, profile) = self.query_profiled(query_vector, k).await?;
+        let stats = QueryStats {
+            scanned: profile.records_scanned,
+            elapsed: profile.scan_time + profile.score_time,
+        };
+        Ok((results, stats))
+    }
+
+    /// Like [`VectorStore::query`], but also returns a [`QueryProfile`] describing
+    /// where the time went.
+    pub async fn query_profiled(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+    ) -> Result<(Vec<QueryResult>, QueryProfile), VectorStoreError> {
+        self.check_dimension(query_vector.len())?;
+
+        // Ensure _default namespace exists
+        self.ensure_default_namespace(DEFAULT_DIM).await?;
+
+        let (items, profile) = self.query_ns_profiled(DEFAULT_NS, query_vector, k).await?;
+        let results = items
+            .into_iter()
+            .map(|item| QueryResult {
+                id: item.id,
+                score: item.score,
+                metadata: item.attributes,
+                vector: None,
+            })
+            .collect();
+        Ok((results, profile))
+    }
+
+    /// Pairwise similarity among the vectors stored under `ids`, as an
+    /// `n x n` matrix in the order given. Fails if any id is missing.
+    pub async fn similarity_matrix(
+        &self,
+        ids: &[String],
+        metric: DistanceMetric,
+    ) -> Result<Vec<Vec<f32>>, VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            let mut vectors = Vec::with_capacity(ids.len());
+            for id in ids {
+                let vec_key = self.keys().record_key(DEFAULT_NS, "vec", id);
+                match self.db.get(vec_key.as_bytes()).await? {
+                    Some(value) => vectors.push(decode_record(&value)?),
+                    None => {
+                        return Err(VectorStoreError::InvalidRequest(format!(
+                            "Vector not found: {id}"
+                        )));
+                    }
+                }
+            }
+
+            // Similarity is symmetric, so only the upper triangle is computed
+            let n = vectors.len();
+            let mut matrix = vec![vec![0.0; n]; n];
+            for (i, a) in vectors.iter().enumerate() {
+                for (j, b) in vectors.iter().enumerate().skip(i) {
+                    let score = similarity(metric, a, b);
+                    matrix[i][j] = score;
+                    matrix[j][i] = score;
+                }
+            }
+
+            Ok(matrix)
+        }
+        .await;
+        record_op("similarity_matrix", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Like [`VectorStore::query`], but reuses the norm cached in `prepared`
+    /// instead of recomputing it on every call.
+    pub async fn query_prepared(
+        &self,
+        prepared: &PreparedQuery,
+        k: usize,
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        self.check_dimension(prepared.vector.len())?;
+
+        // Ensure _default namespace exists
+        self.ensure_default_namespace(DEFAULT_DIM).await?;
+
+        let (items, _) = self
+            .query_ns_with_norm(DEFAULT_NS, &prepared.vector, prepared.norm, k)
+            .await?;
+        Ok(items
+            .into_iter()
+            .map(|item| QueryResult {
+                id: item.id,
+                score: item.score,
+                metadata: item.attributes,
+                vector: None,
+            })
+            .collect())
+    }
+
+    /// Whether a record with `id` exists in the default namespace. With
+    /// [`VectorStoreOptions::read_after_write_timeout`] set, a miss is retried
+    /// until the record becomes visible or the timeout elapses.
+    pub async fn contains(&self, id: &str) -> Result<bool, VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            let vec_key = self.keys().record_key(DEFAULT_NS, "vec", id);
+            Ok(self.get_visible(vec_key.as_bytes()).await?.is_some())
+        }
+        .await;
+        record_op("contains", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Fetch the record with `id` from the default namespace, or `None` if it
+    /// doesn't exist. The vector is returned as stored, i.e. after any
+    /// standardization applied on write.
+    pub async fn get(&self, id: &str) -> Result<Option<VectorRecord>, VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            let vec_key = self.keys().record_key(DEFAULT_NS, "vec", id);
+            let Some(vec_bytes) = self.get_visible(vec_key.as_bytes()).await? else {
+                return Ok(None);
+            };
+
+            let doc_key = self.keys().record_key(DEFAULT_NS, "doc", id);
+            let metadata = match self.db.get(doc_key.as_bytes()).await? {
+                Some(val) => Some(self.serialization.decode(&val)?),
+                None => None,
+            };
+
+            Ok(Some(VectorRecord {
+                id: id.to_string(),
+                vector: decode_record(&vec_bytes)?,
+                metadata,
+            }))
+        }
+        .await;
+        record_op("get", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Like [`VectorStore::get`], but fails with
+    /// [`VectorStoreError::NotFound`] if there is no record with `id`
+    pub async fn get_strict(&self, id: &str) -> Result<VectorRecord, VectorStoreError> {
+        self.get(id)
+            .await?
+            .ok_or_else(|| VectorStoreError::NotFound(id.to_string()))
+    }
+
+    /// [`VectorStore::get`] for each of `ids`, looked up concurrently. The
+    /// results line up with `ids`, with `None` for ids that don't exist.
+    pub async fn get_many(
+        &self,
+        ids: &[&str],
+    ) -> Result<Vec<Option<VectorRecord>>, VectorStoreError> {
+        futures::future::try_join_all(ids.iter().map(|id| self.get(id))).await
+    }
+
+    /// Store a sparse vector and optional metadata under `id` in the default
+    /// namespace, replacing any earlier sparse record with that id. Sparse
+    /// records live under their own keys, apart from dense ones: the same id
+    /// can hold one of each, and neither kind shows up in the other's
+    /// queries.
+    pub async fn add_sparse(
+        &self,
+        id: &str,
+        vector: SparseVector,
+        metadata: Option<serde_json::Value>,
+    ) -> Result<(), VectorStoreError> {
+        if vector.is_empty() {
+            return Err(VectorStoreError::InvalidVector(format!(
+                "Record {id} has an empty sparse vector"
+            )));
+        }
+        if let Some((i, x)) = vector.iter().find(|(_, x)| !x.is_finite()) {
+            return Err(VectorStoreError::InvalidVector(format!(
+                "Record {id} has non-finite weight {x} at index {i}"
+            )));
+        }
+
+        let start = Instant::now();
+        let result = async {
+            let mut batch = WriteBatch::new();
+            let sparse_key = self.keys().record_key(DEFAULT_NS, "sparse", id);
+            batch.put(sparse_key.as_bytes(), encode_sparse_vec(&vector));
+            let doc_key = self.keys().record_key(DEFAULT_NS, "sparse_doc", id);
+            match metadata {
+                Some(metadata) => {
+                    batch.put(doc_key.as_bytes(), &self.serialization.encode(&metadata)?)
+                }
+                None => batch.delete(doc_key.as_bytes()),
+            }
+            self.db.write(batch).await?;
+            Ok(())
+        }
+        .await;
+        record_op("add_sparse", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// The `k` sparse records in the default namespace with the highest
+    /// [`sparse_dot_product`] against `query_vector`, best first
+    pub async fn query_sparse(
+        &self,
+        query_vector: &SparseVector,
+        k: usize,
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            let mut heap = TopK::with_capacity(k, k.min(1024), true);
+            let (sparse_prefix, sparse_end) = self.keys().range(DEFAULT_NS, "sparse");
+            let mut iter = self
+                .db
+                .scan(sparse_prefix.as_bytes()..sparse_end.as_bytes())
+                .await?;
+            while let Ok(Some(item)) = iter.next().await {
+                let score = sparse_dot_product(query_vector, &decode_sparse_vec(&item.value)?);
+                if !heap.would_accept(score) {
+                    continue;
+                }
+                let id = self.keys().id_from_key(&sparse_prefix, &item.key);
+                heap.offer(ScoredItem { score, id });
+            }
+
+            let mut results = Vec::new();
+            for si in heap.into_sorted_vec() {
+                let doc_key = self.keys().record_key(DEFAULT_NS, "sparse_doc", &si.id);
+                let metadata = match self.db.get(doc_key.as_bytes()).await? {
+                    Some(val) => Some(self.serialization.decode(&val)?),
+                    None => None,
+                };
+                results.push(QueryResult {
+                    id: si.id,
+                    score: si.score,
+                    metadata,
+                    vector: None,
+                });
+            }
+            Ok(results)
+        }
+        .await;
+        record_op("query_sparse", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Store a document as several chunk vectors plus optional metadata in
+    /// the default namespace, replacing any earlier chunks of `doc_id`. Chunk
+    /// `i` is kept under `ns:{ns}:chunk:{doc_id}:{i}`, apart from plain
+    /// records, so chunks only show up in [`VectorStore::query_documents`].
+    pub async fn add_document(
+        &self,
+        doc_id: &str,
+        chunks: Vec<Vec<f32>>,
+        metadata: Option<serde_json::Value>,
+    ) -> Result<(), VectorStoreError> {
+        let Some(dim) = chunks.first().map(Vec::len) else {
+            return Err(VectorStoreError::InvalidVector(format!(
+                "Document {doc_id} has no chunks"
+            )));
+        };
+        for chunk in &chunks {
+            check_vector(doc_id, chunk)?;
+            if chunk.len() != dim {
+                return Err(VectorStoreError::DimensionMismatch {
+                    expected: dim,
+                    got: chunk.len(),
+                });
+            }
+        }
+        self.check_dimension(dim)?;
+        self.ensure_default_namespace(dim).await?;
+
+        let start = Instant::now();
+        let result = async {
+            let meta = self.get_namespace(DEFAULT_NS).await?;
+            if meta.vector_dim > 0 && dim != meta.vector_dim {
+                return Err(VectorStoreError::DimensionMismatch {
+                    expected: meta.vector_dim,
+                    got: dim,
+                });
+            }
+
+            let mut batch = WriteBatch::new();
+            for key in self.chunk_keys(doc_id).await? {
+                batch.delete(&key);
+            }
+            for (i, chunk) in chunks.iter().enumerate() {
+                let chunk_key = self.chunk_key(doc_id, i);
+                let chunk = standardized(&meta, chunk);
+                batch.put(chunk_key.as_bytes(), self.encode_vector(&chunk).0);
+            }
+            let doc_key = self.keys().record_key(DEFAULT_NS, "chunk_doc", doc_id);
+            match metadata {
+                Some(metadata) => {
+                    batch.put(doc_key.as_bytes(), &self.serialization.encode(&metadata)?)
+                }
+                None => batch.delete(doc_key.as_bytes()),
+            }
+            self.db.write(batch).await?;
+            Ok(())
+        }
+        .await;
+        record_op("add_document", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// The `k` documents added with [`VectorStore::add_document`] whose best
+    /// chunk matches `query_vector` best, best first. Every chunk is scored,
+    /// and each document appears once, with its best chunk's score. Chunks
+    /// of another dimension than `query_vector` are an error.
+    pub async fn query_documents(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        self.check_dimension(query_vector.len())?;
+        self.ensure_default_namespace(DEFAULT_DIM).await?;
+
+        let start = Instant::now();
+        let result = async {
+            let meta = self.get_namespace(DEFAULT_NS).await?;
+            if meta.vector_dim > 0 && query_vector.len() != meta.vector_dim {
+                return Err(VectorStoreError::DimensionMismatch {
+                    expected: meta.vector_dim,
+                    got: query_vector.len(),
+                });
+            }
+            let query_vector = standardized(&meta, query_vector);
+
+            let distance = self.namespace_distance(&meta);
+            let higher_is_better = distance.higher_is_better();
+            let mut best: HashMap<String, f32> = HashMap::new();
+
+            let (chunk_prefix, chunk_end) = self.keys().range(DEFAULT_NS, "chunk");
+            let mut iter = self
+                .db
+                .scan(chunk_prefix.as_bytes()..chunk_end.as_bytes())
+                .await?;
+            while let Ok(Some(item)) = iter.next().await {
+                let chunk_id = self.keys().id_from_key(&chunk_prefix, &item.key);
+                let Some((doc_id, _)) = chunk_id.rsplit_once(':') else {
+                    continue;
+                };
+                let chunk = decode_record(&item.value)?;
+                if chunk.len() != query_vector.len() {
+                    return Err(VectorStoreError::DimensionMismatch {
+                        expected: chunk.len(),
+                        got: query_vector.len(),
+                    });
+                }
+                let score = distance.score(&query_vector, &chunk);
+                if score.is_nan() {
+                    continue;
+                }
+                best.entry(doc_id.to_string())
+                    .and_modify(|best| {
+                        let better = if higher_is_better {
+                            score > *best
+                        } else {
+                            score < *best
+                        };
+                        if better {
+                            *best = score;
+                        }
+                    })
+                    .or_insert(score);
+            }
+
+            let mut heap = TopK::with_capacity(k, k.min(best.len()), higher_is_better);
+            for (id, score) in best {
+                heap.offer(ScoredItem { score, id });
+            }
+
+            let mut results = Vec::new();
+            for si in heap.into_sorted_vec() {
+                let doc_key = self.keys().record_key(DEFAULT_NS, "chunk_doc", &si.id);
+                let metadata = match self.db.get(doc_key.as_bytes()).await? {
+                    Some(val) => Some(self.serialization.decode(&val)?),
+                    None => None,
+                };
+                results.push(QueryResult {
+                    id: si.id,
+                    score: si.score,
+                    metadata,
+                    vector: None,
+                });
+            }
+            Ok(results)
+        }
+        .await;
+        record_op("query_documents", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Key of chunk `index` of `doc_id`. The index is zero-padded so a
+    /// document's chunks sort in order, and never contains `:`, so the
+    /// document id is everything before the last `:`.
+    fn chunk_key(&self, doc_id: &str, index: usize) -> String {
+        self.keys()
+            .record_key(DEFAULT_NS, "chunk", &format!("{doc_id}:{index:08}"))
+    }
+
+    /// Keys of every stored chunk of `doc_id`
+    async fn chunk_keys(&self, doc_id: &str) -> Result<Vec<Bytes>, VectorStoreError> {
+        let (chunk_prefix, _) = self.keys().range(DEFAULT_NS, "chunk");
+        // Also covers documents whose ids extend this one, e.g. `{doc_id}:x`,
+        // which are filtered out below
+        let start = self
+            .keys()
+            .record_key(DEFAULT_NS, "chunk", &format!("{doc_id}:"));
+        let mut end = start.clone().into_bytes();
+        end.push(0xFF);
+
+        let mut keys = Vec::new();
+        let mut iter = self.db.scan(start.into_bytes()..end).await?;
+        while let Ok(Some(item)) = iter.next().await {
+            let chunk_id = self.keys().id_from_key(&chunk_prefix, &item.key);
+            if chunk_id
+                .rsplit_once(':')
+                .is_some_and(|(doc, _)| doc == doc_id)
+            {
+                keys.push(item.key);
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Exact number of records in the default namespace. This scans every
+    /// vector key; the namespace's `approx_row_count` is free to read but
+    /// only best effort, since concurrent writes can race on it.
+    pub async fn count(&self) -> Result<usize, VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            let (vec_prefix, vec_end) = self.keys().range(DEFAULT_NS, "vec");
+            let mut iter = self
+                .db
+                .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
+                .await?;
+            let mut count = 0;
+            while iter.next().await?.is_some() {
+                count += 1;
+            }
+            Ok(count)
+        }
+        .await;
+        record_op("count", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// How the store is configured and roughly how much it holds, read from
+    /// the bookkeeping kept on every write rather than by scanning.
+    pub async fn info(&self) -> Result<StoreInfo, VectorStoreError> {
+        let format_version = match self.db.get(FORMAT_VERSION_KEY.as_bytes()).await? {
+            Some(bytes) => serde_json::from_slice(&bytes)?,
+            None => FORMAT_VERSION,
+        };
+        let meta_key = self.keys().meta_key(DEFAULT_NS);
+        let (dimension, record_count) = match self.db.get(meta_key.as_bytes()).await? {
+            Some(value) => {
+                let meta: NamespaceMetadata = serde_json::from_slice(&value)?;
+                let dimension = (meta.vector_dim != DEFAULT_DIM).then_some(meta.vector_dim);
+                (dimension, meta.approx_row_count)
+            }
+            None => (None, 0),
+        };
+        Ok(StoreInfo {
+            dimension,
+            metric: self.metric,
+            record_count,
+            format_version,
+        })
+    }
+
+    /// Ids of every record in the default namespace, in key order. Scans
+    /// only the vector keys, so no vector or metadata is decoded.
+    pub async fn list_ids(&self) -> Result<Vec<String>, VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            let (vec_prefix, vec_end) = self.keys().range(DEFAULT_NS, "vec");
+            let mut iter = self
+                .db
+                .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
+                .await?;
+            let mut ids = Vec::new();
+            while let Ok(Some(item)) = iter.next().await {
+                ids.push(self.keys().id_from_key(&vec_prefix, &item.key));
+            }
+            Ok(ids)
+        }
+        .await;
+        record_op("list_ids", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Whether the default namespace has no records. Stops at the first one,
+    /// so unlike [`VectorStore::count`] it never scans the whole namespace.
+    pub async fn is_empty(&self) -> Result<bool, VectorStoreError> {
+        let (vec_prefix, vec_end) = self.keys().range(DEFAULT_NS, "vec");
+        let mut iter = self
+            .db
+            .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
+            .await?;
+        Ok(iter.next().await?.is_none())
+    }
+
+    /// Remove the record with `id` (vector and metadata) from the default
+    /// namespace. Deleting an id that doesn't exist is not an error.
+    pub async fn delete(&self, id: &str) -> Result<(), VectorStoreError> {
+        self.remove(id).await?;
+        Ok(())
+    }
+
+    /// Like [`VectorStore::delete`], but fails with
+    /// [`VectorStoreError::NotFound`] if there is no record with `id`
+    pub async fn delete_strict(&self, id: &str) -> Result<(), VectorStoreError> {
+        if !self.remove(id).await? {
+            return Err(VectorStoreError::NotFound(id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Delete the record with `id`, returning whether there was one
+    async fn remove(&self, id: &str) -> Result<bool, VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            let vec_key = self.keys().record_key(DEFAULT_NS, "vec", id);
+            let Some(vec_bytes) = self.db.get(vec_key.as_bytes()).await? else {
+                return Ok(false);
+            };
+
+            let doc_key = self.keys().record_key(DEFAULT_NS, "doc", id);
+            let mut batch = WriteBatch::new();
+            batch.delete(vec_key.as_bytes());
+            batch.delete(self.norm_key(DEFAULT_NS, vec_key.as_bytes()));
+            batch.delete(doc_key.as_bytes());
+            let lsh = self.lsh.read().expect("LSH lock poisoned").clone();
+            if let Some(lsh) = lsh
+                && let Ok(vector) = decode_record(&vec_bytes)
+            {
+                for key in self.lsh_keys(&lsh, id, &vector) {
+                    batch.delete(key.as_bytes());
+                }
+            }
+            if self.pq.read().expect("PQ lock poisoned").is_some() {
+                batch.delete(self.keys().record_key(DEFAULT_NS, "pq", id).as_bytes());
+            }
+
+            // Update approx row count (best effort — not atomic)
+            let meta = self.get_namespace(DEFAULT_NS).await?;
+            let updated_meta = NamespaceMetadata {
+                approx_row_count: meta.approx_row_count.saturating_sub(1),
+                ..meta
+            };
+            let meta_key = self.keys().meta_key(DEFAULT_NS);
+            batch.put(meta_key.as_bytes(), &serde_json::to_vec(&updated_meta)?);
+
+            self.db.write(batch).await?;
+            if let Some(index) = self.ann.write().expect("ANN index lock poisoned").as_mut() {
+                index.remove(id);
+            }
+            if let Some(cache) = self
+                .cache
+                .write()
+                .expect("query cache lock poisoned")
+                .as_mut()
+            {
+                cache.remove(&vec_key);
+            }
+            Ok(true)
+        }
+        .await;
+        record_op("delete", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Replace the metadata of the record with `id`, leaving its vector as it
+    /// is, so re-tagging a record doesn't mean sending its embedding again.
+    /// `None` removes the metadata. Fails with
+    /// [`VectorStoreError::NotFound`] if there is no record with `id`.
+    pub async fn update_metadata(
+        &self,
+        id: &str,
+        metadata: Option<serde_json::Value>,
+    ) -> Result<(), VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            let vec_key = self.keys().record_key(DEFAULT_NS, "vec", id);
+            if self.db.get(vec_key.as_bytes()).await?.is_none() {
+                return Err(VectorStoreError::NotFound(id.to_string()));
+            }
+
+            let doc_key = self.keys().record_key(DEFAULT_NS, "doc", id);
+            match metadata {
+                Some(metadata) => {
+                    self.db
+                        .put(doc_key.as_bytes(), &self.serialization.encode(&metadata)?)
+                        .await?
+                }
+                None => self.db.delete(doc_key.as_bytes()).await?,
+            }
+            Ok(())
+        }
+        .await;
+        record_op("update_metadata", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Merge `patch`, a JSON object, into the metadata of the record with
+    /// `id`: each key in it overwrites the record's, and a `null` value
+    /// removes the key (a shallow RFC 7386 merge patch). A record without
+    /// metadata, or whose metadata isn't an object, gets the patch as its
+    /// metadata. Fails with [`VectorStoreError::NotFound`] if there is
+    /// no record with `id`.
+    ///
+    /// The read and the write are separate, so concurrent patches to the
+    /// same record can overwrite each other.
+    pub async fn patch_metadata(
+        &self,
+        id: &str,
+        patch: serde_json::Value,
+    ) -> Result<(), VectorStoreError> {
+        let serde_json::Value::Object(patch) = patch else {
+            return Err(VectorStoreError::InvalidRequest(
+                "Metadata patch must be a JSON object".to_string(),
+            ));
+        };
+
+        let start = Instant::now();
+        let result = async {
+            let vec_key = self.keys().record_key(DEFAULT_NS, "vec", id);
+            if self.db.get(vec_key.as_bytes()).await?.is_none() {
+                return Err(VectorStoreError::NotFound(id.to_string()));
+            }
+
+            let doc_key = self.keys().record_key(DEFAULT_NS, "doc", id);
+            let mut metadata = match self.db.get(doc_key.as_bytes()).await? {
+                Some(val) => match self.serialization.decode(&val)? {
+                    serde_json::Value::Object(map) => map,
+                    _ => serde_json::Map::new(),
+                },
+                None => serde_json::Map::new(),
+            };
+            for (key, value) in patch {
+                if value.is_null() {
+                    metadata.remove(&key);
+                } else {
+                    metadata.insert(key, value);
+                }
+            }
+
+            let metadata = serde_json::Value::Object(metadata);
+            self.db
+                .put(doc_key.as_bytes(), &self.serialization.encode(&metadata)?)
+                .await?;
+            Ok(())
+        }
+        .await;
+        record_op("patch_metadata", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Remove every record (vector, cached norm and metadata) from the
+    /// default namespace, along with its sparse records and document chunks,
+    /// and return how many dense records there were. The namespace
+    /// itself stays, with its dimension and metric, and its row count reset
+    /// to zero.
+    pub async fn clear(&self) -> Result<usize, VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            let batch_size = self.options.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1);
+            let mut removed = 0;
+            for kind in RECORD_KINDS {
+                let (prefix, end) = self.keys().range(DEFAULT_NS, kind);
+                let mut iter = self.db.scan(prefix.as_bytes()..end.as_bytes()).await?;
+                let mut batch = WriteBatch::new();
+                let mut pending = 0;
+                while let Ok(Some(item)) = iter.next().await {
+                    batch.delete(&item.key);
+                    pending += 1;
+                    if kind == "vec" {
+                        removed += 1;
+                    }
+
+                    if pending == batch_size {
+                        self.db
+                            .write(std::mem::replace(&mut batch, WriteBatch::new()))
+                            .await?;
+                        pending = 0;
+                    }
+                }
+                self.db.write(batch).await?;
+            }
+
+            let meta_key = self.keys().meta_key(DEFAULT_NS);
+            if let Some(value) = self.db.get(meta_key.as_bytes()).await? {
+                let meta: NamespaceMetadata = serde_json::from_slice(&value)?;
+                let updated_meta = NamespaceMetadata {
+                    approx_row_count: 0,
+                    ..meta
+                };
+                self.db
+                    .put(meta_key.as_bytes(), &serde_json::to_vec(&updated_meta)?)
+                    .await?;
+            }
+            self.reset_ann_index();
+            self.reset_cache();
+            Ok(removed)
+        }
+        .await;
+        record_op("clear", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Add records read from `reader` as JSON Lines, one
+    /// `{"id", "vector", "metadata"}` object per line (`metadata` optional),
+    /// and return how many were imported. Blank lines are skipped.
+    ///
+    /// Lines are read incrementally and written through
+    /// [`VectorStore::batch_add`] in batches of
+    /// [`VectorStoreOptions::batch_size`], later lines winning when an id
+    /// repeats. A malformed line fails the import with its line number;
+    /// batches before it stay written.
+    pub async fn import_jsonl<R: AsyncRead + Unpin>(
+        &self,
+        reader: R,
+    ) -> Result<usize, VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            let batch_size = self.options.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1);
+            let mut lines = BufReader::new(reader).lines();
+            let mut batch = Vec::with_capacity(batch_size);
+            let mut imported = 0;
+            let mut line_number = 0;
+            while let Some(line) = lines.next_line().await? {
+                line_number += 1;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let record: JsonlRecord = serde_json::from_str(&line).map_err(|e| {
+                    VectorStoreError::InvalidRequest(format!("Line {line_number}: {e}"))
+                })?;
+                batch.push((record.id, record.vector, record.metadata));
+
+                if batch.len() == batch_size {
+                    imported += batch.len();
+                    self.batch_add(std::mem::take(&mut batch), DuplicatePolicy::KeepLast)
+                        .await?;
+                }
+            }
+            imported += batch.len();
+            self.batch_add(batch, DuplicatePolicy::KeepLast).await?;
+            Ok(imported)
+        }
+        .await;
+        record_op("import_jsonl", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Write every record in the default namespace to `writer` as JSON
+    /// Lines, in the format [`VectorStore::import_jsonl`] reads, and return
+    /// how many were written. Records are written as they are scanned, so
+    /// memory use doesn't grow with the store. Vectors are exported as
+    /// stored, i.e. after any standardization or quantization.
+    pub async fn export_jsonl<W: AsyncWrite + Unpin>(
+        &self,
+        writer: W,
+    ) -> Result<usize, VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            let mut writer = tokio::io::BufWriter::new(writer);
+            let mut exported = 0;
+
+            let (vec_prefix, vec_end) = self.keys().range(DEFAULT_NS, "vec");
+            let mut iter = self
+                .db
+                .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
+                .await?;
+            while let Ok(Some(item)) = iter.next().await {
+                let id = self.keys().id_from_key(&vec_prefix, &item.key);
+                let doc_key = self.keys().record_key(DEFAULT_NS, "doc", &id);
+                let metadata = match self.db.get(doc_key.as_bytes()).await? {
+                    Some(val) => Some(self.serialization.decode(&val)?),
+                    None => None,
+                };
+                let record = JsonlRecord {
+                    vector: decode_record(&item.value)?,
+                    id,
+                    metadata,
+                };
+
+                let mut line = serde_json::to_vec(&record)?;
+                line.push(b'\n');
+                writer.write_all(&line).await?;
+                exported += 1;
+            }
+            writer.flush().await?;
+            Ok(exported)
+        }
+        .await;
+        record_op("export_jsonl", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Copy every record in the default namespace into one of `targets`,
+    /// picked by `hash_fn(id) % targets.len()`. Returns how many records each
+    /// target received, in the same order as `targets`.
+    pub async fn shard_into(
+        &self,
+        targets: &[&VectorStore],
+        hash_fn: impl Fn(&str) -> u64,
+    ) -> Result<Vec<usize>, VectorStoreError> {
+        if targets.is_empty() {
+            return Err(VectorStoreError::InvalidRequest(
+                "shard_into needs at least one target store".to_string(),
+            ));
+        }
+
+        let start = Instant::now();
+        let result = async {
+            let mut counts = vec![0; targets.len()];
+
+            let (vec_prefix, vec_end) = self.keys().range(DEFAULT_NS, "vec");
+            let mut iter = self
+                .db
+                .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
+                .await?;
+            while let Ok(Some(item)) = iter.next().await {
+                let id = self.keys().id_from_key(&vec_prefix, &item.key);
+
+                let doc_key = self.keys().record_key(DEFAULT_NS, "doc", &id);
+                let metadata = match self.db.get(doc_key.as_bytes()).await? {
+                    Some(val) => Some(self.serialization.decode(&val)?),
+                    None => None,
+                };
+
+                let shard = (hash_fn(&id) % targets.len() as u64) as usize;
+                targets[shard]
+                    .add(&id, decode_record(&item.value)?, metadata)
+                    .await?;
+                counts[shard] += 1;
+            }
+
+            Ok(counts)
+        }
+        .await;
+        record_op("shard_into", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Run each query under both metrics and report how much their top-`k`
+    /// results agree, to judge whether switching metrics would change results.
+    /// Both are applied as given, whatever the namespace's own metric or a
+    /// custom distance, so pass the namespace's metric (see
+    /// [`VectorStore::get_namespace`]) as one of them to compare against
+    /// what queries use now. An empty store has no results to agree on and
+    /// reports zero for both.
+    pub async fn compare_metrics(
+        &self,
+        queries: &[Vec<f32>],
+        a: DistanceMetric,
+        b: DistanceMetric,
+        k: usize,
+    ) -> Result<MetricComparison, VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            for query in queries {
+                self.check_dimension(query.len())?;
+            }
+            if self.is_empty().await? {
+                return Ok(MetricComparison::default());
+            }
+
+            let meta = self.get_namespace(DEFAULT_NS).await?;
+            let mut overlap_sum = 0.0;
+            let mut correlation_sum = 0.0;
+
+            for query in queries {
+                let query = standardized(&meta, query);
+                let ranked_a = self.top_k_ids(&query, &a, k).await?;
+                let ranked_b = self.top_k_ids(&query, &b, k).await?;
+                overlap_sum += top_k_overlap(&ranked_a, &ranked_b);
+                correlation_sum += spearman(&ranked_a, &ranked_b);
+            }
+
+            let n = queries.len().max(1) as f64;
+            Ok(MetricComparison {
+                mean_overlap: overlap_sum / n,
+                mean_spearman: correlation_sum / n,
+            })
+        }
+        .await;
+        record_op("compare_metrics", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Rescale every vector in the default namespace to unit L2 norm and
+    /// return how many were rewritten. Vectors already within [`NORM_EPSILON`]
+    /// of unit length are left alone, as are zero vectors, which have no
+    /// direction to keep.
+    pub async fn normalize_all(&self) -> Result<usize, VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            let batch_size = self.options.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1);
+            let mut batch = WriteBatch::new();
+            let mut pending = 0;
+            let mut updated = 0;
+
+            let (vec_prefix, vec_end) = self.keys().range(DEFAULT_NS, "vec");
+            let mut iter = self
+                .db
+                .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
+                .await?;
+            while let Ok(Some(item)) = iter.next().await {
+                let vector = decode_record(&item.value)?;
+                let norm = magnitude(&vector);
+                if norm == 0.0 || (norm - 1.0).abs() <= NORM_EPSILON {
+                    continue;
+                }
+
+                let unit: Vec<f32> = vector.iter().map(|f| f / norm).collect();
+                let (vec_bytes, norm) = self.encode_vector(&unit);
+                batch.put(&item.key, vec_bytes);
+                batch.put(self.norm_key(DEFAULT_NS, &item.key), norm);
+                pending += 1;
+                updated += 1;
+
+                if pending == batch_size {
+                    self.db
+                        .write(std::mem::replace(&mut batch, WriteBatch::new()))
+                        .await?;
+                    pending = 0;
+                }
+            }
+            if pending > 0 {
+                self.db.write(batch).await?;
+            }
+            self.rebuild_ann_index().await?;
+            self.reload_cache().await?;
+            self.rebuild_lsh().await?;
+            self.rebuild_pq().await?;
+
+            Ok(updated)
+        }
+        .await;
+        record_op("normalize_all", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Fetch the top `m` candidates for `query_vector`, rescore each with the
+    /// async `score` function (at most `concurrency` calls in flight), and
+    /// return the best `k` by that score, highest first. The returned results
+    /// carry the rescored value in `score`.
+    pub async fn retrieve_and_rerank<F, Fut>(
+        &self,
+        query_vector: &[f32],
+        m: usize,
+        k: usize,
+        score: F,
+        concurrency: usize,
+    ) -> Result<Vec<QueryResult>, VectorStoreError>
+    where
+        F: Fn(QueryResult) -> Fut,
+        Fut: Future<Output = f32>,
+    {
+        let candidates = self.query(query_vector, m).await?;
+
+        let mut reranked: Vec<QueryResult> = futures::stream::iter(candidates)
+            .map(|candidate| {
+                let rescored = score(candidate.clone());
+                async move {
+                    QueryResult {
+                        score: rescored.await,
+                        ..candidate
+                    }
+                }
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await;
+
+        // Rescored values are similarities; NaN ranks last and ties go by id
+        reranked
+            .sort_by(|a, b| compare_scores(true, a.score, b.score).then_with(|| a.id.cmp(&b.id)));
+        reranked.truncate(k);
+        Ok(reranked)
+    }
+
+    /// Copy every key (namespaces, records and counters) into a new store at
+    /// `dest_path` on `dest`, opened with this store's options. The destination
+    /// must be empty. Returns the number of vector records copied.
+    ///
+    /// Unlike copying the object store's files, this goes through slatedb, so
+    /// the copy is compacted and free of deleted data.
+    pub async fn clone_to(
+        &self,
+        dest: Arc<dyn ObjectStore>,
+        dest_path: &str,
+    ) -> Result<usize, VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            let options = self.options.clone().metric(self.metric);
+            let target = Self::open_with_options(dest_path, dest, options).await?;
+            // Only namespaces count; opening always writes the metric key
+            let mut existing = target.db.scan("ns:".as_bytes().."ns;".as_bytes()).await?;
+            if let Ok(Some(_)) = existing.next().await {
+                return Err(VectorStoreError::InvalidRequest(format!(
+                    "clone_to destination {dest_path} is not empty"
+                )));
+            }
+
+            let batch_size = self.options.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1);
+            let mut batch = WriteBatch::new();
+            let mut pending = 0;
+            let mut records = 0;
+
+            let mut iter = self.db.scan::<Vec<u8>, _>(..).await?;
+            while let Ok(Some(item)) = iter.next().await {
+                if is_vec_key(&item.key) {
+                    records += 1;
+                }
+                batch.put(&item.key, &item.value);
+                pending += 1;
+
+                if pending == batch_size {
+                    target
+                        .db
+                        .write(std::mem::replace(&mut batch, WriteBatch::new()))
+                        .await?;
+                    pending = 0;
+                }
+            }
+            if pending > 0 {
+                target.db.write(batch).await?;
+            }
+
+            target.close().await?;
+            Ok(records)
+        }
+        .await;
+        record_op("clone_to", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Like [`VectorStore::query`], but each result also lists the `top_dims`
+    /// dimensions with the largest terms in the namespace metric's score,
+    /// largest first: `query[i] * vector[i]` for cosine and dot product,
+    /// whose sum divided by both vectors' norms is the cosine score, and
+    /// per-dimension differences for the distances (see
+    /// [`ExplainedResult::top_dimensions`]), where the largest terms are
+    /// where the vectors differ most. Terms are taken after standardizing
+    /// the query, as it is scored. A custom [`Distance`] can't be split by
+    /// dimension, so with one `top_dimensions` is empty.
+    pub async fn query_explained(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        top_dims: usize,
+    ) -> Result<Vec<ExplainedResult>, VectorStoreError> {
+        let results = self.query(query_vector, k).await?;
+        let meta = self.get_namespace(DEFAULT_NS).await?;
+        let metric = self.namespace_metric(&meta);
+        let query_vector = standardized(&meta, query_vector);
+
+        let mut explained = Vec::with_capacity(results.len());
+        for result in results {
+            let vec_key = self.keys().record_key(DEFAULT_NS, "vec", &result.id);
+            let vector = match self.db.get(vec_key.as_bytes()).await? {
+                Some(value) => decode_record(&value)?,
+                None => Vec::new(),
+            };
+
+            let mut top_dimensions: Vec<(usize, f32)> = match self.options.distance {
+                Some(_) => Vec::new(),
+                None => dimension_terms(metric, &query_vector, &vector)
+                    .into_iter()
+                    .enumerate()
+                    .collect(),
+            };
+            top_dimensions
+                .sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+            top_dimensions.truncate(top_dims);
+
+            explained.push(ExplainedResult {
+                result,
+                top_dimensions,
+            });
+        }
+
+        Ok(explained)
+    }
+
+    /// Like [`VectorStore::query`], but results with equal scores are ordered
+    /// by the top-level metadata field `tie_break_by` (higher values first,
+    /// records without it last), then by id. Ties at the k-th place are broken
+    /// the same way, so every record tied with the k-th best score is kept
+    /// until their metadata is read.
+    pub async fn query_with_tie_break(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        tie_break_by: Option<&str>,
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        let Some(field) = tie_break_by else {
+            return self.query(query_vector, k).await;
+        };
+        self.check_dimension(query_vector.len())?;
+        self.ensure_default_namespace(DEFAULT_DIM).await?;
+
+        let start = Instant::now();
+        let result = async {
+            let meta = self.get_namespace(DEFAULT_NS).await?;
+            if meta.vector_dim > 0 && query_vector.len() != meta.vector_dim {
+                return Err(VectorStoreError::DimensionMismatch {
+                    expected: meta.vector_dim,
+                    got: query_vector.len(),
+                });
+            }
+            let query_vector = standardized(&meta, query_vector);
+            let query_norm = magnitude(&query_vector);
+            let metric = self.namespace_metric(&meta);
+            let custom = self.options.distance.as_deref();
+            let higher_is_better = match custom {
+                Some(distance) => distance.higher_is_better(),
+                None => metric.higher_is_better(),
+            };
+            let mut heap = TopKWithTies::new(k, higher_is_better);
+
+            let (vec_prefix, vec_end) = self.keys().range(DEFAULT_NS, "vec");
+            let mut iter = self
+                .db
+                .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
+                .await?;
+            while let Ok(Some(item)) = iter.next().await {
+                let id = self.keys().id_from_key(&vec_prefix, &item.key);
+                let vector = decode_record(&item.value)?;
+                let score = match (custom, metric) {
+                    (Some(distance), _) => distance.score(&query_vector, &vector),
+                    (None, DistanceMetric::Cosine) => {
+                        cosine_similarity_with_norm(&query_vector, query_norm, &vector)
+                    }
+                    (None, other) => similarity(other, &query_vector, &vector),
+                };
+                heap.offer(ScoredItem { score, id });
+            }
+            self.with_tie_break(heap.into_vec(), field, k, higher_is_better)
+                .await
+        }
+        .await;
+        record_op("query", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Fetch metadata for `scored` and order it by score, then `field`, then id
+    async fn with_tie_break(
+        &self,
+        scored: Vec<ScoredItem>,
+        field: &str,
+        k: usize,
+        higher_is_better: bool,
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        let mut results = self.with_metadata(scored).await?;
+
+        let field_of = |r: &QueryResult| r.metadata.as_ref().and_then(|m| m.get(field)).cloned();
+        results.sort_by(|a, b| {
+            compare_scores(higher_is_better, a.score, b.score)
+                .then_with(|| compare_json_desc(field_of(a).as_ref(), field_of(b).as_ref()))
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        results.truncate(k);
+        Ok(results)
+    }
+
+    /// Suggest IVF parameters for the default namespace from its size. This
+    /// only applies rules of thumb; no index is built. `nlist` is about
+    /// `sqrt(n)` clusters and `nprobe` starts at 1/16th of them, which usually
+    /// lands around 90% recall and can be raised from there.
+    pub async fn suggest_ivf_params(&self) -> Result<IvfSuggestion, VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            let mut corpus_size = 0;
+            let (vec_prefix, vec_end) = self.keys().range(DEFAULT_NS, "vec");
+            let mut iter = self
+                .db
+                .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
+                .await?;
+            while iter.next().await?.is_some() {
+                corpus_size += 1;
+            }
+
+            let nlist = ((corpus_size as f64).sqrt().round() as usize).max(1);
+            let nprobe = nlist.div_ceil(16).clamp(1, nlist);
+            Ok(IvfSuggestion {
+                corpus_size,
+                nlist,
+                nprobe,
+            })
+        }
+        .await;
+        record_op("suggest_ivf_params", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Like [`VectorStore::query`], but metadata is left as the stored JSON
+    /// bytes and only parsed when [`LazyQueryResult::metadata`] is called.
+    /// Consumers that only need ids and scores skip deserializing (and
+    /// allocating) metadata entirely, and cloning a result is cheap.
+    pub async fn query_lazy(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+    ) -> Result<Vec<LazyQueryResult>, VectorStoreError> {
+        self.check_dimension(query_vector.len())?;
+
+        // Ensure _default namespace exists
+        self.ensure_default_namespace(DEFAULT_DIM).await?;
+
+        let start = Instant::now();
+        let result = async {
+            let meta = self.get_namespace(DEFAULT_NS).await?;
+            if meta.vector_dim > 0 && query_vector.len() != meta.vector_dim {
+                return Err(VectorStoreError::DimensionMismatch {
+                    expected: meta.vector_dim,
+                    got: query_vector.len(),
+                });
+            }
+            let query_vector = standardized(&meta, query_vector);
+
+            let distance = self.namespace_distance(&meta);
+            let scored = self.top_k_scored(&query_vector, &*distance, k).await?;
+            let mut results = Vec::with_capacity(scored.len());
+            for si in scored {
+                let doc_key = self.keys().record_key(DEFAULT_NS, "doc", &si.id);
+                let raw_metadata = self.db.get(doc_key.as_bytes()).await?;
+                results.push(LazyQueryResult {
+                    id: si.id,
+                    score: si.score,
+                    raw_metadata,
+                    format: self.serialization,
+                });
+            }
+            Ok(results)
+        }
+        .await;
+        record_op("query", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Like [`VectorStore::query`], but only dimensions where `mask` is true
+    /// take part: both the dot product and the two norms are computed over
+    /// the unmasked dimensions alone, so the score is the cosine of the
+    /// vectors restricted to them. `mask` must be as long as the query.
+    pub async fn query_masked(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        mask: &[bool],
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        if mask.len() != query_vector.len() {
+            return Err(VectorStoreError::InvalidRequest(format!(
+                "Mask has {} entries but the query has {} dimensions",
+                mask.len(),
+                query_vector.len()
+            )));
+        }
+        self.check_dimension(query_vector.len())?;
+        self.ensure_default_namespace(DEFAULT_DIM).await?;
+
+        let start = Instant::now();
+        let result = async {
+            let meta = self.get_namespace(DEFAULT_NS).await?;
+            if meta.vector_dim > 0 && query_vector.len() != meta.vector_dim {
+                return Err(VectorStoreError::DimensionMismatch {
+                    expected: meta.vector_dim,
+                    got: query_vector.len(),
+                });
+            }
+
+            // Zeroing masked dimensions on both sides drops them from the
+            // dot product and from both norms
+            let apply_mask = |v: &[f32]| -> Vec<f32> {
+                v.iter()
+                    .zip(mask)
+                    .map(|(x, &keep)| if keep { *x } else { 0.0 })
+                    .collect()
+            };
+            let query = apply_mask(&standardized(&meta, query_vector));
+            let query_norm = magnitude(&query);
+
+            let scored = self
+                .top_k_by(k, true, |vector| {
+                    cosine_similarity_with_norm(&query, query_norm, &apply_mask(vector))
+                })
+                .await?;
+            self.with_metadata(scored).await
+        }
+        .await;
+        record_op("query", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Rewrite metadata in the default namespace without keys whose value is
+    /// `null` or an empty string, at any depth of nested objects. Records that
+    /// have nothing to strip are not rewritten. Returns how many were changed.
+    pub async fn compact_metadata(&self) -> Result<usize, VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            let batch_size = self.options.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1);
+            let mut batch = WriteBatch::new();
+            let mut pending = 0;
+            let mut changed = 0;
+
+            let (doc_prefix, doc_end) = self.keys().range(DEFAULT_NS, "doc");
+            let mut iter = self
+                .db
+                .scan(doc_prefix.as_bytes()..doc_end.as_bytes())
+                .await?;
+            while let Ok(Some(item)) = iter.next().await {
+                let mut doc: serde_json::Value = self.serialization.decode(&item.value)?;
+                if !strip_empty_fields(&mut doc) {
+                    continue;
+                }
+
+                batch.put(&item.key, &self.serialization.encode(&doc)?);
+                pending += 1;
+                changed += 1;
+
+                if pending == batch_size {
+                    self.db
+                        .write(std::mem::replace(&mut batch, WriteBatch::new()))
+                        .await?;
+                    pending = 0;
+                }
+            }
+            if pending > 0 {
+                self.db.write(batch).await?;
+            }
+
+            Ok(changed)
+        }
+        .await;
+        record_op("compact_metadata", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// The `k` best matches among records whose metadata passes `filter`.
+    /// Records without metadata never match. Filtering happens during the
+    /// scan, so fewer than `k` results come back only when fewer records match.
+    pub async fn query_filtered(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        filter: impl Fn(&serde_json::Value) -> bool + Send + Sync,
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        let search = self
+            .search(query_vector.to_vec())
+            .k(k)
+            .filter(move |metadata| metadata.is_some_and(&filter));
+        Ok(search.run().await?.results)
+    }
+
+    /// The `k` best matches among records whose top-level metadata `field`
+    /// is a number in `min..=max`. Records without metadata, without the
+    /// field, or with a non-numeric value never match.
+    pub async fn query_numeric_range(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        field: &str,
+        min: f64,
+        max: f64,
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        let filter = Filter::Range(field.to_string(), min, max);
+        self.query_where(query_vector, k, &filter).await
+    }
+
+    /// The `k` best matches among records whose metadata passes `filter`.
+    /// Records without metadata never match.
+    pub async fn query_where(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        filter: &Filter,
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        self.query_filtered(query_vector, k, |metadata| filter.matches(metadata))
+            .await
+    }
+
+    /// Like [`VectorStore::query`], but records scoring worse than `min_score`
+    /// never enter the top-k, so fewer than `k` results may come back.
+    pub async fn query_with_threshold(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        min_score: f32,
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        let search = self.search(query_vector.to_vec()).k(k).min_score(min_score);
+        Ok(search.run().await?.results)
+    }
+
+    /// Results `offset..offset + limit` of the ranking [`VectorStore::query`]
+    /// would produce, for paging. Ranking is global, so this keeps the top
+    /// `offset + limit` during the scan and drops the first `offset`: every
+    /// page costs a full scan, and deep pages also hold every result before
+    /// them in memory.
+    pub async fn query_page(
+        &self,
+        query_vector: &[f32],
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        let search = self.search(query_vector.to_vec()).offset(offset).k(limit);
+        Ok(search.run().await?.results)
+    }
+
+    /// [`VectorStore::query`] for many query vectors at once, in one scan:
+    /// each stored vector is read and decoded once and scored against every
+    /// query, with a top-`k` heap per query. The results line up with
+    /// `queries`.
+    pub async fn query_batch(
+        &self,
+        queries: &[Vec<f32>],
+        k: usize,
+    ) -> Result<Vec<Vec<QueryResult>>, VectorStoreError> {
+        for query in queries {
+            self.check_dimension(query.len())?;
+        }
+        self.ensure_default_namespace(DEFAULT_DIM).await?;
+
+        let start = Instant::now();
+        let result = async {
+            let meta = self.get_namespace(DEFAULT_NS).await?;
+            if let Some(query) = queries
+                .iter()
+                .find(|q| meta.vector_dim > 0 && q.len() != meta.vector_dim)
+            {
+                return Err(VectorStoreError::DimensionMismatch {
+                    expected: meta.vector_dim,
+                    got: query.len(),
+                });
+            }
+            let queries: Vec<(Cow<[f32]>, f32)> = queries
+                .iter()
+                .map(|q| {
+                    let q = standardized(&meta, q);
+                    let norm = magnitude(&q);
+                    (q, norm)
+                })
+                .collect();
+
+            let metric = self.namespace_metric(&meta);
+            let custom = self.options.distance.as_deref();
+            let higher_is_better = match custom {
+                Some(distance) => distance.higher_is_better(),
+                None => metric.higher_is_better(),
+            };
+            let capacity = k.min(meta.approx_row_count as usize);
+            let mut heaps: Vec<TopK> = queries
+                .iter()
+                .map(|_| TopK::with_capacity(k, capacity, higher_is_better))
+                .collect();
+            let mut offer = |id: &str, vector: &[f32], norm: f32| {
+                for ((query, query_norm), heap) in queries.iter().zip(&mut heaps) {
+                    let score = match (custom, metric) {
+                        (Some(distance), _) => distance.score(query, vector),
+                        (None, DistanceMetric::Cosine) => {
+                            cosine_similarity_with_norms(query, *query_norm, vector, norm)
+                        }
+                        (None, other) => similarity(other, query, vector),
+                    };
+                    heap.offer(ScoredItem {
+                        score,
+                        id: id.to_string(),
+                    });
+                }
+            };
+
+            let cached = {
+                let cache = self.cache.read().expect("query cache lock poisoned");
+                if let Some(cache) = cache.as_ref() {
+                    for cached in cache.values() {
+                        offer(&cached.id, &cached.vector, cached.norm);
+                    }
+                }
+                cache.is_some()
+            };
+            if !cached {
+                let (vec_prefix, vec_end) = self.keys().range(DEFAULT_NS, "vec");
+                let mut iter = self
+                    .db
+                    .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
+                    .await?;
+                while let Ok(Some(item)) = iter.next().await {
+                    let id = self.keys().id_from_key(&vec_prefix, &item.key);
+                    let vector = decode_record(&item.value)?;
+                    offer(&id, &vector, magnitude(&vector));
+                }
+            }
+
+            // Queries often share results, so each one's metadata is read once
+            let mut metadata: HashMap<String, Option<serde_json::Value>> = HashMap::new();
+            let mut results = Vec::with_capacity(heaps.len());
+            for heap in heaps {
+                let mut ranked = Vec::new();
+                for si in heap.into_sorted_vec() {
+                    if !metadata.contains_key(&si.id) {
+                        let doc_key = self.keys().record_key(DEFAULT_NS, "doc", &si.id);
+                        let doc = match self.db.get(doc_key.as_bytes()).await? {
+                            Some(val) => Some(self.serialization.decode(&val)?),
+                            None => None,
+                        };
+                        metadata.insert(si.id.clone(), doc);
+                    }
+                    ranked.push(QueryResult {
+                        metadata: metadata[&si.id].clone(),
+                        id: si.id,
+                        score: si.score,
+                        vector: None,
+                    });
+                }
+                results.push(ranked);
+            }
+            Ok(results)
+        }
+        .await;
+        record_op("query_batch", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Like [`VectorStore::query`], but scored and ranked with `metric`
+    /// instead of the store's, for this call only: best first, which is the
+    /// lowest score for distances such as [`DistanceMetric::Euclidean`].
+    pub async fn query_with_metric(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        metric: DistanceMetric,
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        let search = self.search(query_vector.to_vec()).metric(metric).k(k);
+        Ok(search.run().await?.results)
+    }
+
+    /// The `k` best matches among eligible ids: those in `allow` when it is
+    /// `Some`, minus any in `deny`. Ineligible records are skipped during the
+    /// scan, so they never take a place in the top-k.
+    pub async fn query_with_ids(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        allow: Option<&HashSet<String>>,
+        deny: &HashSet<String>,
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        let mut search = self
+            .search(query_vector.to_vec())
+            .k(k)
+            .exclude(deny.iter().cloned());
+        if let Some(allow) = allow {
+            search = search.only(allow.iter().cloned());
+        }
+        Ok(search.run().await?.results)
+    }
+
+    /// Like [`VectorStore::query`], but records are scored in chunks of
+    /// [`PARALLEL_CHUNK_SIZE`] on blocking tasks while the scan continues,
+    /// with at most [`VectorStoreOptions::query_concurrency`] chunks in
+    /// flight. Each chunk keeps its own top-k, and the global top-k is picked
+    /// from their union, so results match [`VectorStore::query`].
+    pub async fn query_parallel(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        self.check_dimension(query_vector.len())?;
+        self.ensure_default_namespace(DEFAULT_DIM).await?;
+
+        let start = Instant::now();
+        let result = async {
+            let meta = self.get_namespace(DEFAULT_NS).await?;
+            if meta.vector_dim > 0 && query_vector.len() != meta.vector_dim {
+                return Err(VectorStoreError::DimensionMismatch {
+                    expected: meta.vector_dim,
+                    got: query_vector.len(),
+                });
+            }
+            let metric = self.namespace_metric(&meta);
+            let custom = self.options.distance.clone();
+            let higher_is_better = self.namespace_distance(&meta).higher_is_better();
+            let query: Arc<[f32]> = standardized(&meta, query_vector).into();
+            let query_norm = magnitude(&query);
+            let concurrency = self
+                .options
+                .query_concurrency
+                .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()))
+                .max(1);
+
+            let score_chunk = |chunk: Vec<(String, Vec<f32>)>| {
+                let query = Arc::clone(&query);
+                let custom = custom.clone();
+                move || {
+                    let mut heap = TopK::with_capacity(k, k.min(chunk.len()), higher_is_better);
+                    for (id, vector) in chunk {
+                        let score = match (&custom, metric) {
+                            (Some(distance), _) => distance.score(&query, &vector),
+                            (None, DistanceMetric::Cosine) => {
+                                cosine_similarity_with_norm(&query, query_norm, &vector)
+                            }
+                            (None, other) => similarity(other, &query, &vector),
+                        };
+                        heap.offer(ScoredItem { score, id });
+                    }
+                    heap.into_sorted_vec()
+                }
+            };
+
+            let mut merged = TopK::with_capacity(k, k.min(1024), higher_is_better);
+            let mut tasks: JoinSet<Vec<ScoredItem>> = JoinSet::new();
+            let mut chunk = Vec::with_capacity(PARALLEL_CHUNK_SIZE);
+
+            let (vec_prefix, vec_end) = self.keys().range(DEFAULT_NS, "vec");
+            let mut iter = self
+                .db
+                .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
+                .await?;
+            while let Ok(Some(item)) = iter.next().await {
+                let id = self.keys().id_from_key(&vec_prefix, &item.key);
+                chunk.push((id, decode_record(&item.value)?));
+                if chunk.len() < PARALLEL_CHUNK_SIZE {
+                    continue;
+                }
+
+                if tasks.len() >= concurrency
+                    && let Some(scored) = tasks.join_next().await
+                {
+                    scored?.into_iter().for_each(|si| merged.offer(si));
+                }
+                let full = std::mem::replace(&mut chunk, Vec::with_capacity(PARALLEL_CHUNK_SIZE));
+                tasks.spawn_blocking(score_chunk(full));
+            }
+            if !chunk.is_empty() {
+                tasks.spawn_blocking(score_chunk(chunk));
+            }
+            while let Some(scored) = tasks.join_next().await {
+                scored?.into_iter().for_each(|si| merged.offer(si));
+            }
+
+            self.with_metadata(merged.into_sorted_vec()).await
+        }
+        .await;
+        record_op("query", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Top `k` by Maximal Marginal Relevance: results are picked one at a
+    /// time from the best `k * MMR_POOL_FACTOR` matches, each maximizing
+    /// `lambda * relevance - (1 - lambda) * redundancy`, where redundancy is
+    /// the candidate's highest similarity to anything already picked, both
+    /// under the namespace's metric. `lambda = 1.0` is plain top-`k`; lower
+    /// values trade relevance for diversity. Results are in pick order and
+    /// keep their relevance scores.
+    pub async fn query_mmr(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        lambda: f32,
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        let pool = self
+            .search(query_vector.to_vec())
+            .k(k.saturating_mul(MMR_POOL_FACTOR))
+            .with_vectors()
+            .run()
+            .await?
+            .results;
+
+        let distance = self.namespace_distance(&self.get_namespace(DEFAULT_NS).await?);
+        // Oriented so that higher always means closer
+        let oriented = |score: f32| {
+            if distance.higher_is_better() {
+                score
+            } else {
+                -score
+            }
+        };
+        let mut candidates: Vec<(QueryResult, Option<f32>)> =
+            pool.into_iter().map(|result| (result, None)).collect();
+
+        let mut picked: Vec<QueryResult> = Vec::with_capacity(k.min(candidates.len()));
+        while picked.len() < k && !candidates.is_empty() {
+            let mmr = |(result, redundancy): &(QueryResult, Option<f32>)| {
+                lambda * oriented(result.score) - (1.0 - lambda) * redundancy.unwrap_or(0.0)
+            };
+            let mut best = 0;
+            for i in 1..candidates.len() {
+                if mmr(&candidates[i]) > mmr(&candidates[best]) {
+                    best = i;
+                }
+            }
+
+            let (mut chosen, _) = candidates.remove(best);
+            let chosen_vector = chosen.vector.take().unwrap_or_default();
+            for (result, redundancy) in &mut candidates {
+                let vector = result.vector.as_deref().unwrap_or_default();
+                let similarity = oriented(distance.score(&chosen_vector, vector));
+                *redundancy = Some(redundancy.map_or(similarity, |r| r.max(similarity)));
+            }
+            picked.push(chosen);
+        }
+        Ok(picked)
+    }
+
+    /// The `k` nearest neighbors of the stored record `id`, which is itself
+    /// left out of the results. The stored vector is scored as is, so it
+    /// isn't standardized a second time.
+    pub async fn query_by_id(
+        &self,
+        id: &str,
+        k: usize,
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            let vec_key = self.keys().record_key(DEFAULT_NS, "vec", id);
+            let Some(bytes) = self.get_visible(vec_key.as_bytes()).await? else {
+                return Err(VectorStoreError::NotFound(id.to_string()));
+            };
+            let vector = decode_record(&bytes)?;
+
+            // One extra, since the record is its own best match
+            let distance = self.namespace_distance(&self.get_namespace(DEFAULT_NS).await?);
+            let mut scored = self
+                .top_k_scored(&vector, &*distance, k.saturating_add(1))
+                .await?;
+            scored.retain(|si| si.id != id);
+            scored.truncate(k);
+            self.with_metadata(scored).await
+        }
+        .await;
+        record_op("query", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Every record in the default namespace scoring at least `min_score`
+    /// (at most, for distances), yielded as the scan reaches it. Results come
+    /// in key order, not score order, so consumers can start on the first
+    /// match without waiting for the scan to finish. The stream ends after
+    /// the first error.
+    pub fn query_stream<'a>(
+        &'a self,
+        query_vector: &'a [f32],
+        min_score: f32,
+    ) -> impl Stream<Item = Result<QueryResult, VectorStoreError>> + 'a {
+        struct Scan {
+            iter: DbIterator,
+            prefix: String,
+            query: Vec<f32>,
+            distance: Arc<dyn Distance>,
+        }
+
+        futures::stream::try_unfold(None, move |scan: Option<Scan>| async move {
+            let mut scan = match scan {
+                Some(scan) => scan,
+                None => {
+                    self.check_dimension(query_vector.len())?;
+                    self.ensure_default_namespace(DEFAULT_DIM).await?;
+                    let meta = self.get_namespace(DEFAULT_NS).await?;
+                    if meta.vector_dim > 0 && query_vector.len() != meta.vector_dim {
+                        return Err(VectorStoreError::DimensionMismatch {
+                            expected: meta.vector_dim,
+                            got: query_vector.len(),
+                        });
+                    }
+                    let (prefix, end) = self.keys().range(DEFAULT_NS, "vec");
+                    Scan {
+                        iter: self.db.scan(prefix.as_bytes()..end.as_bytes()).await?,
+                        query: standardized(&meta, query_vector).into_owned(),
+                        distance: self.namespace_distance(&meta),
+                        prefix,
+                    }
+                }
+            };
+
+            while let Some(item) = scan.iter.next().await? {
+                let score = scan
+                    .distance
+                    .score(&scan.query, &decode_record(&item.value)?);
+                if compare_scores(scan.distance.higher_is_better(), score, min_score).is_gt() {
+                    continue;
+                }
+
+                let id = self.keys().id_from_key(&scan.prefix, &item.key);
+                let doc_key = self.keys().record_key(DEFAULT_NS, "doc", &id);
+                let metadata = match self.db.get(doc_key.as_bytes()).await? {
+                    Some(val) => Some(self.serialization.decode(&val)?),
+                    None => None,
+                };
+                let result = QueryResult {
+                    id,
+                    score,
+                    metadata,
+                    vector: None,
+                };
+                return Ok(Some((result, Some(scan))));
+            }
+            Ok(None)
+        })
+    }
+
+    /// Start a [`QueryBuilder`] for `query_vector` against the default namespace
+    pub fn search(&self, query_vector: Vec<f32>) -> QueryBuilder<'_> {
+        QueryBuilder::new(self, query_vector)
+    }
+
+    pub(crate) async fn run_search(
+        &self,
+        search: QueryBuilder<'_>,
+    ) -> Result<SearchResults, VectorStoreError> {
+        self.check_dimension(search.vector.len())?;
+
+        // Ensure _default namespace exists
+        self.ensure_default_namespace(DEFAULT_DIM).await?;
+
+        let start = Instant::now();
+        let result = async {
+            let meta = self.get_namespace(DEFAULT_NS).await?;
+            if meta.vector_dim > 0 && search.vector.len() != meta.vector_dim {
+                return Err(VectorStoreError::DimensionMismatch {
+                    expected: meta.vector_dim,
+                    got: search.vector.len(),
+                });
+            }
+            let query_vector = standardized(&meta, &search.vector);
+
+            let distance: Arc<dyn Distance> = match search.metric {
+                Some(metric) => Arc::new(metric),
+                None => self.namespace_distance(&meta),
+            };
+            let higher_is_better = distance.higher_is_better();
+
+            let mut profile = QueryProfile::default();
+            let wanted = search.offset.saturating_add(search.k);
+            let mut heap = TopK::with_capacity(wanted, wanted.min(1024), higher_is_better);
+
+            let scan_start = Instant::now();
+            let mut filter_time = Duration::ZERO;
+            let (vec_prefix, vec_end) = self.keys().range(DEFAULT_NS, "vec");
+            let mut iter = self
+                .db
+                .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
+                .await?;
+            while let Ok(Some(item)) = iter.next().await {
+                profile.records_scanned += 1;
+                profile.bytes_read += item.key.len() + item.value.len();
+
+                let id = self.keys().id_from_key(&vec_prefix, &item.key);
+                if search.exclude.contains(&id)
+                    || search.only.as_ref().is_some_and(|only| !only.contains(&id))
+                {
+                    continue;
+                }
+
+                let score_start = Instant::now();
+                let score = distance.score(&query_vector, &decode_record(&item.value)?);
+                profile.score_time += score_start.elapsed();
+                if search
+                    .min_score
+                    .is_some_and(|min| compare_scores(higher_is_better, score, min).is_gt())
+                    || !heap.would_accept(score)
+                {
+                    continue;
+                }
+
+                // Metadata is only read for records that would make the cut
+                if let Some(filter) = &search.filter {
+                    let filter_start = Instant::now();
+                    let doc_key = self.keys().record_key(DEFAULT_NS, "doc", &id);
+                    let metadata: Option<serde_json::Value> =
+                        match self.db.get(doc_key.as_bytes()).await? {
+                            Some(val) => {
+                                profile.bytes_read += val.len();
+                                Some(self.serialization.decode(&val)?)
+                            }
+                            None => None,
+                        };
+                    filter_time += filter_start.elapsed();
+                    if !filter(metadata.as_ref()) {
+                        continue;
+                    }
+                }
+
+                heap.offer(ScoredItem { score, id });
+            }
+            profile.scan_time = scan_start.elapsed().saturating_sub(profile.score_time);
+            profile.fetch_time = filter_time;
+
+            let sort_start = Instant::now();
+            let page: Vec<ScoredItem> = heap
+                .into_sorted_vec()
+                .into_iter()
+                .skip(search.offset)
+                .collect();
+            profile.sort_time = sort_start.elapsed();
+
+            let fetch_start = Instant::now();
+            let mut results = self.with_metadata(page).await?;
+            if search.with_vectors {
+                for result in &mut results {
+                    let vec_key = self.keys().record_key(DEFAULT_NS, "vec", &result.id);
+                    if let Some(value) = self.db.get(vec_key.as_bytes()).await? {
+                        profile.bytes_read += value.len();
+                        result.vector = Some(decode_record(&value)?);
+                    }
+                }
+            }
+            profile.fetch_time += fetch_start.elapsed();
+            if let Some(normalization) = search.normalization {
+                normalization.apply(&mut results, higher_is_better);
+            }
+
+            Ok(SearchResults {
+                results,
+                profile: search.profile.then_some(profile),
+            })
+        }
+        .await;
+        record_op("query", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Tally how often each value of the top-level metadata `field` occurs.
+    /// Records without metadata or without the field are ignored.
+    pub async fn distinct_values(
+        &self,
+        field: &str,
+    ) -> Result<HashMap<serde_json::Value, usize>, VectorStoreError> {
+        let start = Instant::now();
+        let result = async {
+            let mut counts = HashMap::new();
+
+            let (doc_prefix, doc_end) = self.keys().range(DEFAULT_NS, "doc");
+            let mut iter = self
+                .db
+                .scan(doc_prefix.as_bytes()..doc_end.as_bytes())
+                .await?;
+            while let Ok(Some(item)) = iter.next().await {
+                let doc: serde_json::Value = self.serialization.decode(&item.value)?;
+                if let Some(value) = doc.get(field) {
+                    *counts.entry(value.clone()).or_insert(0) += 1;
+                }
+            }
+
+            Ok(counts)
+        }
+        .await;
+        record_op("distinct_values", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Stream every record in the default namespace to a Parquet file at `path`.
+    ///
+    /// Columns are `id`, `vector` (a fixed-size list of f32) and one nullable
+    /// string column per metadata field, with nested objects flattened to
+    /// dotted names. Records are written in batches of [`EXPORT_BATCH_SIZE`],
+    /// so memory stays bounded by the batch size rather than the store size.
+    /// Returns the number of rows written.
+    #[cfg(feature = "parquet")]
+    pub async fn export_parquet(&self, path: &str) -> Result<usize, VectorStoreError> {
+        use arrow_array::{ArrayRef, FixedSizeListArray, Float32Array, RecordBatch, StringArray};
+        use arrow_schema::{DataType, Field, Schema};
+        use parquet::arrow::ArrowWriter;
+        use parquet::errors::ParquetError;
+        use std::collections::BTreeSet;
+
+        let start = Instant::now();
+        let result = async {
+            self.ensure_default_namespace(DEFAULT_DIM).await?;
+            let meta = self.get_namespace(DEFAULT_NS).await?;
+
+            // First pass: collect the metadata columns so the schema is known up front
+            let mut columns = BTreeSet::new();
+            let (doc_prefix, doc_end) = self.keys().range(DEFAULT_NS, "doc");
+            let mut iter = self
+                .db
+                .scan(doc_prefix.as_bytes()..doc_end.as_bytes())
+                .await?;
+            while let Ok(Some(item)) = iter.next().await {
+                let doc: serde_json::Value = self.serialization.decode(&item.value)?;
+                let mut fields = Vec::new();
+                flatten_metadata("", &doc, &mut fields);
+                columns.extend(fields.into_iter().map(|(name, _)| name));
+            }
+            let columns: Vec<String> = columns.into_iter().collect();
+
+            // A namespace created with "any dimension" takes its width from the first record
+            let (vec_prefix, vec_end) = self.keys().range(DEFAULT_NS, "vec");
+            let dim = if meta.vector_dim > 0 {
+                meta.vector_dim
+            } else {
+                let mut iter = self
+                    .db
+                    .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
+                    .await?;
+                match iter.next().await {
+                    Ok(Some(item)) => decode_record(&item.value)?.len(),
+                    _ => 0,
+                }
+            };
+
+            let item_field = Arc::new(Field::new("item", DataType::Float32, false));
+            let mut fields = vec![
+                Field::new("id", DataType::Utf8, false),
+                Field::new(
+                    "vector",
+                    DataType::FixedSizeList(item_field.clone(), dim as i32),
+                    false,
+                ),
+            ];
+            fields.extend(
+                columns
+                    .iter()
+                    .map(|name| Field::new(format!("metadata.{name}"), DataType::Utf8, true)),
+            );
+            let schema = Arc::new(Schema::new(fields));
+
+            let file = std::fs::File::create(path).map_err(ParquetError::from)?;
+            let mut writer = ArrowWriter::try_new(file, schema.clone(), None)?;
+
+            let mut ids: Vec<String> = Vec::with_capacity(EXPORT_BATCH_SIZE);
+            let mut values: Vec<f32> = Vec::with_capacity(EXPORT_BATCH_SIZE * dim);
+            let mut metadata: Vec<Vec<Option<String>>> = vec![Vec::new(); columns.len()];
+            let mut rows = 0;
+
+            // Second pass: stream vectors and their attributes out in batches
+            let mut iter = self
+                .db
+                .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
+                .await?;
+            loop {
+                let item = match iter.next().await {
+                    Ok(Some(item)) => Some(item),
+                    _ => None,
+                };
+
+                if let Some(item) = &item {
+                    let id = self.keys().id_from_key(&vec_prefix, &item.key);
+
+                    let vector = decode_record(&item.value)?;
+                    if vector.len() != dim {
+                        return Err(VectorStoreError::DimensionMismatch {
+                            expected: dim,
+                            got: vector.len(),
+                        });
+                    }
+                    values.extend(vector);
+
+                    let doc_key = self.keys().record_key(DEFAULT_NS, "doc", &id);
+                    let mut row: HashMap<String, Option<String>> = HashMap::new();
+                    if let Some(val) = self.db.get(doc_key.as_bytes()).await? {
+                        let doc: serde_json::Value = self.serialization.decode(&val)?;
+                        let mut fields = Vec::new();
+                        flatten_metadata("", &doc, &mut fields);
+                        row.extend(fields);
+                    }
+                    for (column, cells) in columns.iter().zip(metadata.iter_mut()) {
+                        cells.push(row.remove(column).flatten());
+                    }
+
+                    ids.push(id);
+                }
+
+                let done = item.is_none();
+                if ids.len() == EXPORT_BATCH_SIZE || (done && !ids.is_empty()) {
+                    let vectors = FixedSizeListArray::try_new(
+                        item_field.clone(),
+                        dim as i32,
+                        Arc::new(Float32Array::from(std::mem::take(&mut values))),
+                        None,
+                    )
+                    .map_err(ParquetError::from)?;
+                    let mut arrays: Vec<ArrayRef> = vec![
+                        Arc::new(StringArray::from(std::mem::take(&mut ids))),
+                        Arc::new(vectors),
+                    ];
+                    for cells in metadata.iter_mut() {
+                        arrays.push(Arc::new(StringArray::from(std::mem::take(cells))));
+                    }
+
+                    let batch =
+                        RecordBatch::try_new(schema.clone(), arrays).map_err(ParquetError::from)?;
+                    rows += batch.num_rows();
+                    writer.write(&batch)?;
+                }
+
+                if done {
+                    break;
+                }
+            }
+
+            writer.close()?;
+            Ok(rows)
+        }
+        .await;
+        record_op("export_parquet", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Point read that honors the read-after-write retry option
+    async fn get_visible(&self, key: &[u8]) -> Result<Option<Bytes>, VectorStoreError> {
+        match self.options.read_after_write_timeout {
+            Some(timeout) => {
+                retry_until_visible(timeout, || async move { Ok(self.db.get(key).await?) }).await
+            }
+            None => Ok(self.db.get(key).await?),
+        }
+    }
+
+    /// Ids of the `k` best matches for `query` in the default namespace under
+    /// `distance`, best first
+    async fn top_k_ids(
+        &self,
+        query: &[f32],
+        distance: &dyn Distance,
+        k: usize,
+    ) -> Result<Vec<String>, VectorStoreError> {
+        let scored = self.top_k_scored(query, distance, k).await?;
+        Ok(scored.into_iter().map(|si| si.id).collect())
+    }
+
+    /// The `k` best matches for `query` in the default namespace under
+    /// `distance`, best first
+    async fn top_k_scored(
+        &self,
+        query: &[f32],
+        distance: &dyn Distance,
+        k: usize,
+    ) -> Result<Vec<ScoredItem>, VectorStoreError> {
+        self.top_k_by(k, distance.higher_is_better(), |vector| {
+            distance.score(query, vector)
+        })
+        .await
+    }
+
+    /// The `k` records in the default namespace with the best `score`, best
+    /// first: the highest if `higher_is_better`, else the lowest
+    async fn top_k_by(
+        &self,
+        k: usize,
+        higher_is_better: bool,
+        score: impl Fn(&[f32]) -> f32,
+    ) -> Result<Vec<ScoredItem>, VectorStoreError> {
+        // Capped so a huge k doesn't allocate everything up front
+        let mut heap = TopK::with_capacity(k, k.min(1024), higher_is_better);
+
+        let (vec_prefix, vec_end) = self.keys().range(DEFAULT_NS, "vec");
+        let mut iter = self
+            .db
+            .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
+            .await?;
+        while let Ok(Some(item)) = iter.next().await {
+            let id = self.keys().id_from_key(&vec_prefix, &item.key);
+            let score = score(&decode_record(&item.value)?);
+            heap.offer(ScoredItem { score, id });
+        }
+
+        Ok(heap.into_sorted_vec())
+    }
+
+    /// `put`, followed by a read-back check when `verify_writes` is enabled
+    async fn put_verified(&self, key: &[u8], value: &[u8]) -> Result<(), VectorStoreError> {
+        self.db.put(key, value).await?;
+        if self.options.verify_writes {
+            let actual = self.db.get(key).await?;
+            verify_write(key, value, actual.as_deref())?;
+        }
+        Ok(())
+    }
+
+    /// Attach stored metadata to scored default-namespace records, keeping their order
+    async fn with_metadata(
+        &self,
+        scored: Vec<ScoredItem>,
+    ) -> Result<Vec<QueryResult>, VectorStoreError> {
+        let mut results = Vec::with_capacity(scored.len());
+        for si in scored {
+            let doc_key = self.keys().record_key(DEFAULT_NS, "doc", &si.id);
+            let metadata = match self.db.get(doc_key.as_bytes()).await? {
+                Some(val) => Some(self.serialization.decode(&val)?),
+                None => None,
+            };
+            results.push(QueryResult {
+                id: si.id,
+                score: si.score,
+                metadata,
+                vector: None,
+            });
+        }
+        Ok(results)
+    }
+
+    async fn ensure_default_namespace(&self, dim: usize) -> Result<(), VectorStoreError> {
+        self.ensure_namespace(DEFAULT_NS, dim).await
+    }
+
+    /// Create `ns` if needed. Writes pass their vector's dimension, which
+    /// becomes the namespace's when none is recorded yet (e.g. because a query
+    /// created it first); reads pass [`DEFAULT_DIM`].
+    pub(crate) async fn ensure_namespace(
+        &self,
+        ns: &str,
+        dim: usize,
+    ) -> Result<(), VectorStoreError> {
+        let meta_key = self.keys().meta_key(ns);
+        let Some(value) = self.db.get(meta_key.as_bytes()).await? else {
+            self.create_namespace(ns, dim, self.metric.as_str()).await?;
+            return Ok(());
+        };
+
+        let meta: NamespaceMetadata = serde_json::from_slice(&value)?;
+        if meta.vector_dim == DEFAULT_DIM && dim != DEFAULT_DIM {
+            // Only an empty namespace can adopt a dimension
+            let (vec_prefix, vec_end) = self.keys().range(ns, "vec");
+            let mut iter = self
+                .db
+                .scan(vec_prefix.as_bytes()..vec_end.as_bytes())
+                .await?;
+            if let Ok(None) = iter.next().await {
+                let updated_meta = NamespaceMetadata {
+                    vector_dim: dim,
+                    ..meta
+                };
+                self.db
+                    .put(meta_key.as_bytes(), &serde_json::to_vec(&updated_meta)?)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Make every write so far durable in the object store without closing
+    /// the store, e.g. at a checkpoint or from a shutdown hook that only has
+    /// a shared reference.
+    pub async fn flush(&self) -> Result<(), VectorStoreError> {
+        let start = Instant::now();
+        let result = self.db.flush().await.map_err(VectorStoreError::from);
+        record_op("flush", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Write the in-memory table out as a level-0 table, unlike
+    /// [`VectorStore::flush`], which only persists the write-ahead log.
+    /// slatedb's background compactor merges level-0 tables once a few have
+    /// accumulated, dropping deleted and overwritten versions; until then a
+    /// scan merges every one of them, so heavy delete and re-add churn slows
+    /// queries down. Calling this after bulk churn lets that happen sooner.
+    pub async fn flush_memtable(&self) -> Result<(), VectorStoreError> {
+        let start = Instant::now();
+        let result = self
+            .db
+            .flush_with_options(FlushOptions {
+                flush_type: FlushType::MemTable,
+            })
+            .await
+            .map_err(VectorStoreError::from);
+        record_op("flush_memtable", DEFAULT_NS, start, result.is_ok());
+        result
+    }
+
+    /// Release this handle, closing the database if it is the last one.
+    /// Closing an earlier clone only drops that clone, so the others keep
+    /// working.
+    pub async fn close(self) -> Result<(), VectorStoreError> {
+        if let Some(db) = Arc::into_inner(self.db) {
+            db.close().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Backward-compatible QueryResult for benchmarks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueryResult {
+    pub id: String,
+    pub score: f32,
+    pub metadata: Option<serde_json::Value>,
+    /// The stored vector, only filled in when asked for with
+    /// [`QueryBuilder::with_vectors`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vector: Option<Vec<f32>>,
+}
+
+/// A stored record, as returned by [`VectorStore::get`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorRecord {
+    pub id: String,
+    pub vector: Vec<f32>,
+    pub metadata: Option<serde_json::Value>,
+}
+
+/// One line of [`VectorStore::import_jsonl`] input and
+/// [`VectorStore::export_jsonl`] output
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonlRecord {
+    id: String,
+    vector: Vec<f32>,
+    #[serde(default)]
+    metadata: Option<serde_json::Value>,
+}
+
+/// A [`QueryResult`] annotated by [`VectorStore::query_explained`]
+#[derive(Debug, Clone)]
+pub struct ExplainedResult {
+    pub result: QueryResult,
+    /// `(dimension, term)`, largest term first: `query[dimension] *
+    /// vector[dimension]` for cosine and dot product, the squared difference
+    /// for the Euclidean metrics, the absolute difference for Manhattan and
+    /// 1 for a sign mismatch for Hamming
+    pub top_dimensions: Vec<(usize, f32)>,
+}
+
+/// A query vector with its magnitude precomputed, for issuing the same query
+/// repeatedly through [`VectorStore::query_prepared`].
+#[derive(Debug, Clone)]
+pub struct PreparedQuery {
+    vector: Vec<f32>,
+    norm: f32,
+}
+
+impl PreparedQuery {
+    pub fn new(vector: Vec<f32>) -> Self {
+        let norm = magnitude(&vector);
+        Self { vector, norm }
+    }
+
+    pub fn vector(&self) -> &[f32] {
+        &self.vector
+    }
+}
+
+/// Returned by [`VectorStore::info`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoreInfo {
+    /// Dimension of the default namespace, once its first vector pins it
+    pub dimension: Option<usize>,
+    pub metric: DistanceMetric,
+    /// The default namespace's `approx_row_count`: exact unless concurrent
+    /// writes raced on it
+    pub record_count: u64,
+    /// [`FORMAT_VERSION`] of the build that created the store
+    pub format_version: u32,
+}
+
+/// Outcome of the integrity scan run by [`VectorStore::open_and_verify`]
+#[derive(Debug, Clone, Default)]
+pub struct RecoveryReport {
+    /// Number of namespaces whose metadata was found
+    pub namespaces: usize,
+    /// Number of vector and document records decoded
+    pub records_sampled: usize,
+    /// Human-readable description of every inconsistency found
+    pub problems: Vec<String>,
+}
+
+impl RecoveryReport {
+    pub fn is_healthy(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Starting IVF parameters from [`VectorStore::suggest_ivf_params`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IvfSuggestion {
+    /// Number of vectors the suggestion is based on
+    pub corpus_size: usize,
+    /// Number of clusters to partition the corpus into
+    pub nlist: usize,
+    /// Number of clusters to search per query
+    pub nprobe: usize,
+}
+
+/// Agreement between two metrics' rankings, from [`VectorStore::compare_metrics`]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MetricComparison {
+    /// Mean fraction of top-k ids both metrics returned, from 0.0 to 1.0
+    pub mean_overlap: f64,
+    /// Mean Spearman rank correlation over the union of both top-k lists, from
+    /// -1.0 to 1.0. Ids missing from one list rank just past its end.
+    pub mean_spearman: f64,
+}
+
+/// A query result whose metadata stays serialized until asked for, returned
+/// by [`VectorStore::query_lazy`]
+#[derive(Debug, Clone)]
+pub struct LazyQueryResult {
+    pub id: String,
+    pub score: f32,
+    raw_metadata: Option<Bytes>,
+    format: SerializationFormat,
+}
+
+impl LazyQueryResult {
+    /// Parse the metadata, if the record has any
+    pub fn metadata(&self) -> Result<Option<serde_json::Value>, VectorStoreError> {
+        match &self.raw_metadata {
+            Some(raw) => Ok(Some(self.format.decode(raw)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The metadata exactly as stored, in the store's
+    /// [`SerializationFormat`], without parsing it
+    pub fn raw_metadata(&self) -> Option<&[u8]> {
+        self.raw_metadata.as_deref()
+    }
+}
+
+/// Per-query counters returned by [`VectorStore::query_profiled`]
+#[derive(Debug, Clone, Default)]
+pub struct QueryProfile {
+    /// Number of vector records visited by the scan
+    pub records_scanned: usize,
+    /// Key and value bytes read, including attributes fetched for the top-k
+    pub bytes_read: usize,
+    /// Time spent iterating and decoding records, excluding scoring
+    pub scan_time: Duration,
+    /// Time spent computing scores and maintaining the top-k heap
+    pub score_time: Duration,
+    /// Time spent ordering the surviving top-k
+    pub sort_time: Duration,
+    /// Time spent fetching attributes for the top-k
+    pub fetch_time: Duration,
+}
+
+/// Summary counters returned by [`VectorStore::query_with_stats`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QueryStats {
+    /// Number of vector records the scan examined
+    pub scanned: usize,
+    /// Wall-clock time of the scan, scoring included
+    pub elapsed: Duration,
+}
+
+/// Fraction of the ids in `exact` that also appear in `approx`: 1.0 when an
+/// approximate search found every true match. An empty `exact` counts as
+/// fully recalled.
+pub fn recall_at_k(exact: &[QueryResult], approx: &[QueryResult]) -> f64 {
+    if exact.is_empty() {
+        return 1.0;
+    }
+    let found: HashSet<&str> = approx.iter().map(|r| r.id.as_str()).collect();
+    let recalled = exact
+        .iter()
+        .filter(|r| found.contains(r.id.as_str()))
+        .count();
+    recalled as f64 / exact.len() as f64
+}
+
+fn top_k_overlap(a: &[String], b: &[String]) -> f64 {
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    let shared = a.iter().filter(|id| b.contains(*id)).count();
+    shared as f64 / longest as f64
+}
+
+/// Spearman correlation between two best-first rankings, computed as the
+/// Pearson correlation of ranks over the union of ids (which also handles the
+/// ties among ids missing from a list).
+fn spearman(a: &[String], b: &[String]) -> f64 {
+    let rank = |list: &[String], id: &String| {
+        list.iter()
+            .position(|x| x == id)
+            .map_or(list.len() as f64 + 1.0, |p| p as f64 + 1.0)
+    };
+
+    let mut ids: Vec<&String> = a.iter().collect();
+    ids.extend(b.iter().filter(|id| !a.contains(*id)));
+    let ranks_a: Vec<f64> = ids.iter().map(|&id| rank(a, id)).collect();
+    let ranks_b: Vec<f64> = ids.iter().map(|&id| rank(b, id)).collect();
+
+    let n = ids.len() as f64;
+    let mean_a = ranks_a.iter().sum::<f64>() / n;
+    let mean_b = ranks_b.iter().sum::<f64>() / n;
+    let mut cov = 0.0;
+    let mut var_a = 0.0;
+    let mut var_b = 0.0;
+    for (x, y) in ranks_a.iter().zip(&ranks_b) {
+        cov += (x - mean_a) * (y - mean_b);
+        var_a += (x - mean_a).powi(2);
+        var_b += (y - mean_b).powi(2);
+    }
+
+    if var_a == 0.0 || var_b == 0.0 {
+        // Zero or one id, or every rank tied: only identical lists agree
+        return if a == b { 1.0 } else { 0.0 };
+    }
+    cov / (var_a.sqrt() * var_b.sqrt())
+}
+
+/// One `(id, vector, metadata)` record of a [`VectorStore::batch_add`] batch
+type BatchItem = (String, Vec<f32>, Option<serde_json::Value>);
+
+/// Drop repeated ids from a batch according to `policy`, keeping the original
+/// order of the records that survive.
+fn dedup_by_id(
+    items: Vec<BatchItem>,
+    policy: DuplicatePolicy,
+) -> Result<Vec<BatchItem>, VectorStoreError> {
+    match policy {
+        DuplicatePolicy::Error => {
+            let mut seen = HashSet::new();
+            if let Some((id, _, _)) = items.iter().find(|(id, _, _)| !seen.insert(id.as_str())) {
+                return Err(VectorStoreError::InvalidRequest(format!(
+                    "Duplicate id in batch: {id}"
+                )));
+            }
+            Ok(items)
+        }
+        DuplicatePolicy::KeepFirst => {
+            let mut seen = HashSet::new();
+            Ok(items
+                .into_iter()
+                .filter(|(id, _, _)| seen.insert(id.clone()))
+                .collect())
+        }
+        DuplicatePolicy::KeepLast => {
+            let last: HashMap<&str, usize> = items
+                .iter()
+                .enumerate()
+                .map(|(i, (id, _, _))| (id.as_str(), i))
+                .collect();
+            let keep: HashSet<usize> = last.into_values().collect();
+            Ok(items
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| keep.contains(i))
+                .map(|(_, item)| item)
+                .collect())
+        }
+    }
+}
+
+/// Whether `key` is a `ns:{ns}:vec:{id}` key. Holds for both key encodings,
+/// since an escaped namespace never contains `:`.
+fn is_vec_key(key: &[u8]) -> bool {
+    let key_str = String::from_utf8_lossy(key);
+    let mut parts = key_str.strip_prefix("ns:").unwrap_or("").splitn(3, ':');
+    parts.next().is_some() && parts.next() == Some("vec")
+}
+
+/// Order metadata values highest first: numbers numerically, strings and
+/// booleans in their natural order, anything else (or a missing value) last.
+fn compare_json_desc(
+    a: Option<&serde_json::Value>,
+    b: Option<&serde_json::Value>,
+) -> std::cmp::Ordering {
+    use serde_json::Value;
+    use std::cmp::Ordering;
+
+    match (a, b) {
+        (Some(Value::Number(x)), Some(Value::Number(y))) => {
+            let (x, y) = (x.as_f64().unwrap_or(0.0), y.as_f64().unwrap_or(0.0));
+            y.partial_cmp(&x).unwrap_or(Ordering::Equal)
+        }
+        (Some(Value::String(x)), Some(Value::String(y))) => y.cmp(x),
+        (Some(Value::Bool(x)), Some(Value::Bool(y))) => y.cmp(x),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        _ => Ordering::Equal,
+    }
+}
+
+/// `vector` as the namespace stores it: standardized if the namespace has
+/// statistics of the same dimension, otherwise unchanged
+fn standardized<'a>(meta: &NamespaceMetadata, vector: &'a [f32]) -> Cow<'a, [f32]> {
+    match &meta.standardization {
+        Some(stats) if stats.mean.len() == vector.len() => Cow::Owned(stats.apply(vector)),
+        _ => Cow::Borrowed(vector),
+    }
+}
+
+/// Remove `null` and `""` values from every object in `value`, returning
+/// whether anything was removed
+fn strip_empty_fields(value: &mut serde_json::Value) -> bool {
+    use serde_json::Value;
+
+    match value {
+        Value::Object(map) => {
+            let before = map.len();
+            map.retain(|_, v| !matches!(v, Value::Null) && v.as_str() != Some(""));
+            let mut changed = map.len() != before;
+            for child in map.values_mut() {
+                changed |= strip_empty_fields(child);
+            }
+            changed
+        }
+        Value::Array(items) => {
+            let mut changed = false;
+            for item in items {
+                changed |= strip_empty_fields(item);
+            }
+            changed
+        }
+        _ => false,
+    }
+}
+
+/// Flatten a metadata document into `(column, value)` pairs, joining nested
+/// object keys with `.`. Strings are kept as-is; other scalars and arrays are
+/// rendered as JSON text, and JSON `null` becomes a missing value.
+#[cfg(feature = "parquet")]
+fn flatten_metadata(
+    prefix: &str,
+    value: &serde_json::Value,
+    out: &mut Vec<(String, Option<String>)>,
+) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, child) in map {
+                let name = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                flatten_metadata(&name, child, out);
+            }
+        }
+        _ if prefix.is_empty() => {}
+        serde_json::Value::Null => out.push((prefix.to_string(), None)),
+        serde_json::Value::String(s) => out.push((prefix.to_string(), Some(s.clone()))),
+        other => out.push((prefix.to_string(), Some(other.to_string()))),
+    }
+}
+
+/// Vectors are stored as a [`RECORD_HEADER_LEN`]-byte header followed by
+/// their `f32`s back to back in little-endian order (format version 1).
+/// Metadata lives under its own `doc` key, so a query scan never parses JSON.
+fn encode_f32_vec(vector: &[f32]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(RECORD_HEADER_LEN + vector.len() * 4);
+    bytes.push(RECORD_V1_BINARY);
+    bytes.extend_from_slice(&RECORD_MAGIC);
+    bytes.extend(vector.iter().flat_map(|f| f.to_le_bytes()));
+    bytes
+}
+
+/// The record id [`VectorStore::append`] stores sequence number `seq` under
+fn append_id(seq: u64) -> String {
+    format!("{seq:020}")
+}
+
+/// A sparse vector as `(u32 index, f32 weight)` little-endian pairs, in
+/// index order
+fn encode_sparse_vec(vector: &SparseVector) -> Vec<u8> {
+    let mut entries: Vec<(&u32, &f32)> = vector.iter().collect();
+    entries.sort_unstable_by_key(|(index, _)| **index);
+    entries
+        .into_iter()
+        .flat_map(|(index, weight)| index.to_le_bytes().into_iter().chain(weight.to_le_bytes()))
+        .collect()
+}
 
-    if magnitude_a == 0.0 || magnitude_b == 0.0 {
-        return 0.0;
+fn decode_sparse_vec(bytes: &[u8]) -> Result<SparseVector, VectorStoreError> {
+    if !bytes.len().is_multiple_of(8) {
+        return Err(VectorStoreError::InvalidVector(format!(
+            "Stored sparse vector is {} bytes, not a whole number of entries",
+            bytes.len()
+        )));
     }
+    Ok(bytes
+        .chunks_exact(8)
+        .map(|entry| {
+            let index = u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]);
+            let weight = f32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]);
+            (index, weight)
+        })
+        .collect())
+}
 
-    dot_product / (magnitude_a * magnitude_b)
+/// Reject vectors that would poison scoring: empty ones, and any with a NaN
+/// or infinite component
+fn check_vector(id: &str, vector: &[f32]) -> Result<(), VectorStoreError> {
+    if vector.is_empty() {
+        return Err(VectorStoreError::InvalidVector(format!(
+            "Record {id} has an empty vector"
+        )));
+    }
+    if let Some((i, x)) = vector.iter().enumerate().find(|(_, x)| !x.is_finite()) {
+        return Err(VectorStoreError::InvalidVector(format!(
+            "Record {id} has non-finite component {x} at index {i}"
+        )));
+    }
+    Ok(())
 }
 
-fn decode_f32_vec(bytes: &[u8]) -> Vec<f32> {
+/// Format version 2: [`encode_f32_vec`]'s header, then the scale and the
+/// quantized components
+fn encode_int8_vec(quantized: &QuantizedVector) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(RECORD_HEADER_LEN + 4 + quantized.values.len());
+    bytes.push(RECORD_V2_INT8);
+    bytes.extend_from_slice(&RECORD_MAGIC);
+    bytes.extend_from_slice(&quantized.scale.to_le_bytes());
+    bytes.extend(quantized.values.iter().map(|&v| v as u8));
     bytes
-        .chunks_exact(4)
-        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+}
+
+/// `vector` rounded to half precision, clamping components outside its
+/// range rather than letting them become infinite
+fn to_f16(vector: &[f32]) -> Vec<f16> {
+    let max = f16::MAX.to_f32();
+    vector
+        .iter()
+        .map(|&x| f16::from_f32(x.clamp(-max, max)))
         .collect()
 }
+
+/// Format version 4: [`encode_f32_vec`]'s header, then little-endian `f16`s
+fn encode_f16_vec(halves: &[f16]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(RECORD_HEADER_LEN + halves.len() * 2);
+    bytes.push(RECORD_V4_F16);
+    bytes.extend_from_slice(&RECORD_MAGIC);
+    bytes.extend(halves.iter().flat_map(|h| h.to_le_bytes()));
+    bytes
+}
+
+/// Format version 3: [`encode_f32_vec`]'s header, the dimension, and the
+/// packed sign bits
+fn encode_binary_vec(binary: &BinaryVector) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(RECORD_HEADER_LEN + 4 + binary.bits.len() * 8);
+    bytes.push(RECORD_V3_SIGN_BITS);
+    bytes.extend_from_slice(&RECORD_MAGIC);
+    bytes.extend_from_slice(&(binary.dim as u32).to_le_bytes());
+    bytes.extend(binary.bits.iter().flat_map(|w| w.to_le_bytes()));
+    bytes
+}
+
+/// The packed sign bits of a format version 3 record, or `None` for any
+/// other record
+fn decode_binary_record(bytes: &[u8]) -> Option<BinaryVector> {
+    let [RECORD_V3_SIGN_BITS, rest @ ..] = bytes else {
+        return None;
+    };
+    let payload = rest.strip_prefix(&RECORD_MAGIC)?;
+    let (dim, words) = payload.split_first_chunk::<4>()?;
+    let dim = u32::from_le_bytes(*dim) as usize;
+    if words.len() != dim.div_ceil(64) * 8 {
+        return None;
+    }
+    let bits = words
+        .chunks_exact(8)
+        .map(|w| u64::from_le_bytes(w.try_into().expect("8-byte chunk")))
+        .collect();
+    Some(BinaryVector { bits, dim })
+}
+
+/// The components and scale of a format version 2 record, or `None` for
+/// any other record
+fn decode_int8_record(bytes: &[u8]) -> Option<QuantizedVector> {
+    let [RECORD_V2_INT8, rest @ ..] = bytes else {
+        return None;
+    };
+    let payload = rest.strip_prefix(&RECORD_MAGIC)?;
+    let (scale, values) = payload.split_first_chunk::<4>()?;
+    Some(QuantizedVector {
+        values: values.iter().map(|&v| v as i8).collect(),
+        scale: f32::from_le_bytes(*scale),
+    })
+}
+
+/// A vector's magnitude as stored under its `norm` key
+fn encode_norm(vector: &[f32]) -> [u8; 4] {
+    magnitude(vector).to_le_bytes()
+}
+
+/// Decode a stored vector in any format version this build can read:
+/// headerless records from before versioning, JSON (v0), binary (v1),
+/// int8-quantized (v2), sign bits (v3) and half precision (v4).
+/// Anything newer fails with [`VectorStoreError::UnsupportedVersion`].
+fn decode_record(bytes: &[u8]) -> Result<Vec<f32>, VectorStoreError> {
+    let (version, payload) = match bytes {
+        [version, rest @ ..] if rest.starts_with(&RECORD_MAGIC) => {
+            (*version, &rest[RECORD_MAGIC.len()..])
+        }
+        // Written before records carried a header
+        _ => (RECORD_V1_BINARY, bytes),
+    };
+    match version {
+        RECORD_V0_JSON => Ok(serde_json::from_slice(payload)?),
+        RECORD_V1_BINARY => {
+            if !payload.len().is_multiple_of(4) {
+                return Err(VectorStoreError::InvalidVector(format!(
+                    "Stored vector is {} bytes, not a whole number of f32s",
+                    payload.len()
+                )));
+            }
+            Ok(payload
+                .chunks_exact(4)
+                .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+                .collect())
+        }
+        RECORD_V2_INT8 => match decode_int8_record(bytes) {
+            Some(quantized) => Ok(quantized.dequantize()),
+            None => Err(VectorStoreError::InvalidVector(
+                "Stored int8 vector is missing its scale".to_string(),
+            )),
+        },
+        RECORD_V3_SIGN_BITS => match decode_binary_record(bytes) {
+            Some(binary) => Ok(binary.dequantize()),
+            None => Err(VectorStoreError::InvalidVector(
+                "Stored sign-bit vector is truncated".to_string(),
+            )),
+        },
+        RECORD_V4_F16 => {
+            if !payload.len().is_multiple_of(2) {
+                return Err(VectorStoreError::InvalidVector(format!(
+                    "Stored vector is {} bytes, not a whole number of f16s",
+                    payload.len()
+                )));
+            }
+            Ok(payload
+                .chunks_exact(2)
+                .map(|chunk| f16::from_le_bytes([chunk[0], chunk[1]]).to_f32())
+                .collect())
+        }
+        other => Err(VectorStoreError::UnsupportedVersion(other)),
+    }
+}
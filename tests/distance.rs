@@ -0,0 +1,64 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use smolpuff::distance::{
+    DistanceMetric, SparseVector, cosine_similarity, dot_product, euclidean_distance,
+    manhattan_distance, similarity, sparse_dot_product, squared_euclidean_distance,
+};
+
+#[test]
+fn test_similarity_dispatches_on_metric() {
+    let a = [3.0, 4.0];
+    let b = [6.0, 8.0];
+
+    assert!((similarity(DistanceMetric::Cosine, &a, &b) - 1.0).abs() < 1e-6);
+    assert_eq!(similarity(DistanceMetric::DotProduct, &a, &b), 50.0);
+    assert_eq!(similarity(DistanceMetric::Euclidean, &a, &b), 5.0);
+    assert_eq!(similarity(DistanceMetric::SquaredEuclidean, &a, &b), 25.0);
+    assert_eq!(similarity(DistanceMetric::Manhattan, &a, &b), 7.0);
+    assert_eq!(similarity(DistanceMetric::Hamming, &a, &[-1.0, 8.0]), 1.0);
+}
+
+#[test]
+fn test_mismatched_lengths_score_as_no_match() {
+    assert_eq!(cosine_similarity(&[1.0, 0.0], &[1.0]), 0.0);
+    assert_eq!(dot_product(&[1.0, 0.0], &[1.0]), 0.0);
+    assert_eq!(euclidean_distance(&[1.0, 0.0], &[1.0]), f32::INFINITY);
+    assert_eq!(
+        squared_euclidean_distance(&[1.0, 0.0], &[1.0]),
+        f32::INFINITY
+    );
+    assert_eq!(manhattan_distance(&[1.0, 0.0], &[1.0]), f32::INFINITY);
+}
+
+#[test]
+fn test_lane_sums_match_scalar_reference() {
+    let scalar_cosine = |a: &[f32], b: &[f32]| {
+        let dot: f64 = a.iter().zip(b).map(|(x, y)| *x as f64 * *y as f64).sum();
+        let norm = |v: &[f32]| v.iter().map(|x| *x as f64 * *x as f64).sum::<f64>().sqrt();
+        (dot / (norm(a) * norm(b))) as f32
+    };
+
+    let mut rng = StdRng::seed_from_u64(7);
+    // Lengths below, at, and off multiples of the lane width
+    for dim in [1, 7, 8, 9, 31, 128, 1000] {
+        for _ in 0..20 {
+            let a: Vec<f32> = (0..dim).map(|_| rng.gen_range(-1.0..1.0)).collect();
+            let b: Vec<f32> = (0..dim).map(|_| rng.gen_range(-1.0..1.0)).collect();
+            let (fast, reference) = (cosine_similarity(&a, &b), scalar_cosine(&a, &b));
+            assert!(
+                (fast - reference).abs() < 1e-6,
+                "dim {dim}: {fast} vs {reference}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_sparse_dot_product_intersects_indices() {
+    let a = SparseVector::from([(1, 2.0), (7, 0.5), (40, 3.0)]);
+    let b = SparseVector::from([(7, 4.0), (40, -1.0), (99, 10.0)]);
+
+    assert_eq!(sparse_dot_product(&a, &b), 2.0 - 3.0);
+    assert_eq!(sparse_dot_product(&b, &a), sparse_dot_product(&a, &b));
+    assert_eq!(sparse_dot_product(&a, &SparseVector::new()), 0.0);
+}
@@ -0,0 +1,513 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use smolpuff::{BinaryVector, QuantizedVector};
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    dot / (norm(a) * norm(b))
+}
+
+#[test]
+fn test_integer_scoring_matches_dequantized_ranking() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let stored: Vec<QuantizedVector> = (0..200)
+        .map(|_| {
+            let v: Vec<f32> = (0..64).map(|_| rng.gen_range(-1.0..1.0)).collect();
+            QuantizedVector::quantize(&v)
+        })
+        .collect();
+    let query: Vec<f32> = (0..64).map(|_| rng.gen_range(-1.0..1.0)).collect();
+    let quantized_query = QuantizedVector::quantize(&query);
+
+    // Reference: dequantize each stored vector and score against the f32 query
+    let reference: Vec<f32> = stored
+        .iter()
+        .map(|q| cosine(&query, &q.dequantize()))
+        .collect();
+    let asymmetric: Vec<f32> = stored.iter().map(|q| quantized_query.cosine(q)).collect();
+
+    for (r, a) in reference.iter().zip(&asymmetric) {
+        assert!((r - a).abs() < 0.02, "{r} vs {a}");
+    }
+
+    let top = |scores: &[f32]| {
+        let mut idx: Vec<usize> = (0..scores.len()).collect();
+        idx.sort_by(|&i, &j| scores[j].partial_cmp(&scores[i]).unwrap());
+        idx.truncate(10);
+        idx
+    };
+    let reference_top = top(&reference);
+    let asymmetric_top = top(&asymmetric);
+    let shared = reference_top
+        .iter()
+        .filter(|i| asymmetric_top.contains(i))
+        .count();
+    assert!(shared >= 8, "only {shared} of the top 10 agree");
+}
+
+#[test]
+fn test_quantized_dot_rescales() {
+    let a = QuantizedVector::quantize(&[1.0, -2.0, 0.5]);
+    let b = QuantizedVector::quantize(&[2.0, 1.0, 4.0]);
+    assert!((a.dot(&b) - 2.0).abs() < 0.05);
+
+    let zero = QuantizedVector::quantize(&[0.0, 0.0, 0.0]);
+    assert_eq!(zero.cosine(&a), 0.0);
+}
+
+#[tokio::test]
+async fn test_int8_store_recall_against_exact() {
+    use object_store::memory::InMemory;
+    use smolpuff::{VectorEncoding, VectorStore, VectorStoreOptions};
+    use std::sync::Arc;
+
+    let exact = VectorStore::open_in_memory().await.unwrap();
+    let quantized = VectorStoreOptions::new()
+        .vector_encoding(VectorEncoding::Int8)
+        .build_and_open("/test", Arc::new(InMemory::new()))
+        .await
+        .unwrap();
+
+    let mut rng = StdRng::seed_from_u64(11);
+    let dim = 64;
+    let items: Vec<_> = (0..500)
+        .map(|i| {
+            let v: Vec<f32> = (0..dim).map(|_| rng.gen_range(-1.0..1.0)).collect();
+            (format!("v{i:03}"), v, None)
+        })
+        .collect();
+    exact
+        .batch_add(items.clone(), smolpuff::DuplicatePolicy::Error)
+        .await
+        .unwrap();
+    quantized
+        .batch_add(items, smolpuff::DuplicatePolicy::Error)
+        .await
+        .unwrap();
+
+    // 4-byte header, 4-byte scale, one byte per component: about 4x smaller
+    let f32_len = exact.get_raw("v000").await.unwrap().unwrap().len();
+    let int8_len = quantized.get_raw("v000").await.unwrap().unwrap().len();
+    assert_eq!(f32_len, 4 + dim * 4);
+    assert_eq!(int8_len, 8 + dim);
+
+    // Recall@10 over 20 queries: quantization costs a little, not a lot
+    let mut shared = 0;
+    for _ in 0..20 {
+        let query: Vec<f32> = (0..dim).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        let truth = exact.query(&query, 10).await.unwrap();
+        let approx = quantized.query(&query, 10).await.unwrap();
+        shared += approx
+            .iter()
+            .filter(|r| truth.iter().any(|t| t.id == r.id))
+            .count();
+    }
+    let recall = shared as f32 / 200.0;
+    assert!(recall >= 0.9, "recall@10 was {recall}");
+}
+
+#[tokio::test]
+async fn test_int8_store_scores_in_integer_space() {
+    use object_store::memory::InMemory;
+    use smolpuff::{DistanceMetric, VectorEncoding, VectorStoreOptions};
+    use std::sync::Arc;
+
+    let mut rng = StdRng::seed_from_u64(23);
+    let dim = 64;
+    let vectors: Vec<Vec<f32>> = (0..300)
+        .map(|_| (0..dim).map(|_| rng.gen_range(-1.0..1.0)).collect())
+        .collect();
+    let query: Vec<f32> = (0..dim).map(|_| rng.gen_range(-1.0..1.0)).collect();
+
+    for metric in [DistanceMetric::Cosine, DistanceMetric::DotProduct] {
+        let store = VectorStoreOptions::new()
+            .metric(metric)
+            .vector_encoding(VectorEncoding::Int8)
+            .build_and_open("/int8", Arc::new(InMemory::new()))
+            .await
+            .unwrap();
+        let items: Vec<_> = vectors
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (format!("v{i:03}"), v.clone(), None))
+            .collect();
+        store
+            .batch_add(items, smolpuff::DuplicatePolicy::Error)
+            .await
+            .unwrap();
+        let results = store.query(&query, 10).await.unwrap();
+
+        // Scores are the integer-space ones, from a query quantized once
+        let quantized_query = QuantizedVector::quantize(&query);
+        for r in &results {
+            let i: usize = r.id[1..].parse().unwrap();
+            let stored = QuantizedVector::quantize(&vectors[i]);
+            let integer = match metric {
+                DistanceMetric::Cosine => quantized_query.cosine(&stored),
+                _ => quantized_query.dot(&stored),
+            };
+            assert_eq!(r.score, integer, "{metric:?}");
+        }
+
+        // and rank like dequantizing every record and scoring it in f32
+        let mut reference: Vec<(usize, f32)> = vectors
+            .iter()
+            .map(|v| QuantizedVector::quantize(v).dequantize())
+            .map(|v| match metric {
+                DistanceMetric::Cosine => cosine(&query, &v),
+                _ => query.iter().zip(&v).map(|(x, y)| x * y).sum(),
+            })
+            .enumerate()
+            .collect();
+        reference.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        let shared = reference[..10]
+            .iter()
+            .filter(|(i, _)| results.iter().any(|r| r.id == format!("v{i:03}")))
+            .count();
+        assert!(shared >= 8, "{metric:?}: only {shared} of the top 10 agree");
+        for (r, (_, score)) in results.iter().zip(&reference) {
+            let tolerance = if metric == DistanceMetric::Cosine {
+                0.02
+            } else {
+                0.2
+            };
+            assert!((r.score - score).abs() < tolerance, "{metric:?}: {r:?}");
+        }
+    }
+}
+
+#[test]
+fn test_binary_packing_round_trips() {
+    // Spans a word boundary; zero counts as negative
+    let vector: Vec<f32> = (0..70)
+        .map(|i| match i % 3 {
+            0 => 0.5,
+            1 => -2.0,
+            _ => 0.0,
+        })
+        .collect();
+    let binary = BinaryVector::quantize(&vector);
+    assert_eq!(binary.dim, 70);
+    assert_eq!(binary.bits.len(), 2);
+
+    let signs: Vec<f32> = vector
+        .iter()
+        .map(|&x| if x > 0.0 { 1.0 } else { -1.0 })
+        .collect();
+    assert_eq!(binary.dequantize(), signs);
+    assert_eq!(BinaryVector::quantize(&binary.dequantize()), binary);
+
+    let flipped: Vec<f32> = vector.iter().map(|x| -x).collect();
+    // Positives become negative; negatives become positive; zeros stay clear
+    let flipped = BinaryVector::quantize(&flipped);
+    assert_eq!(binary.hamming_distance(&flipped), 47);
+    assert_eq!(binary.hamming_distance(&binary), 0);
+    assert_eq!(
+        binary.hamming_distance(&BinaryVector::quantize(&[1.0])),
+        u32::MAX
+    );
+}
+
+#[tokio::test]
+async fn test_hamming_ranking_tracks_cosine_on_clusters() {
+    use object_store::memory::InMemory;
+    use smolpuff::{DistanceMetric, VectorEncoding, VectorStore, VectorStoreOptions};
+    use std::sync::Arc;
+
+    let exact = VectorStore::open_in_memory().await.unwrap();
+    let binary = VectorStoreOptions::new()
+        .vector_encoding(VectorEncoding::Binary)
+        .metric(DistanceMetric::Hamming)
+        .build_and_open("/test", Arc::new(InMemory::new()))
+        .await
+        .unwrap();
+
+    // Five clusters of 20 noisy points around random centers
+    let mut rng = StdRng::seed_from_u64(3);
+    let dim = 128;
+    let centers: Vec<Vec<f32>> = (0..5)
+        .map(|_| (0..dim).map(|_| rng.gen_range(-1.0..1.0)).collect())
+        .collect();
+    let mut items = Vec::new();
+    for (c, center) in centers.iter().enumerate() {
+        for i in 0..20 {
+            let v: Vec<f32> = center
+                .iter()
+                .map(|x| x + rng.gen_range(-0.3..0.3))
+                .collect();
+            items.push((format!("c{c}-{i:02}"), v, None));
+        }
+    }
+    exact
+        .batch_add(items.clone(), smolpuff::DuplicatePolicy::Error)
+        .await
+        .unwrap();
+    binary
+        .batch_add(items, smolpuff::DuplicatePolicy::Error)
+        .await
+        .unwrap();
+
+    let f32_len = exact.get_raw("c0-00").await.unwrap().unwrap().len();
+    let binary_len = binary.get_raw("c0-00").await.unwrap().unwrap().len();
+    assert_eq!(binary_len, 8 + dim / 8);
+    assert!(binary_len * 20 < f32_len);
+
+    for (c, center) in centers.iter().enumerate() {
+        let prefix = format!("c{c}-");
+        let truth = exact.query(center, 10).await.unwrap();
+        let approx = binary.query(center, 10).await.unwrap();
+        assert!(truth.iter().all(|r| r.id.starts_with(&prefix)));
+        // Hamming lands in the same cluster, if not in the same order
+        assert!(
+            approx.iter().all(|r| r.id.starts_with(&prefix)),
+            "{:?}",
+            approx.iter().map(|r| &r.id).collect::<Vec<_>>()
+        );
+        assert!(approx.windows(2).all(|w| w[0].score <= w[1].score));
+    }
+}
+
+#[tokio::test]
+async fn test_f16_store_round_trips_within_epsilon() {
+    use half::f16;
+    use object_store::memory::InMemory;
+    use smolpuff::{VectorEncoding, VectorStoreOptions};
+    use std::sync::Arc;
+
+    let store = VectorStoreOptions::new()
+        .vector_encoding(VectorEncoding::F16)
+        .build_and_open("/test", Arc::new(InMemory::new()))
+        .await
+        .unwrap();
+
+    let mut rng = StdRng::seed_from_u64(5);
+    let vector: Vec<f32> = (0..48).map(|_| rng.gen_range(-4.0..4.0)).collect();
+    store.add("v", vector.clone(), None).await.unwrap();
+
+    // 4-byte header, then two bytes per component
+    assert_eq!(store.get_raw("v").await.unwrap().unwrap().len(), 4 + 48 * 2);
+
+    let stored = store.get("v").await.unwrap().unwrap().vector;
+    let epsilon = f16::EPSILON.to_f32();
+    for (x, y) in vector.iter().zip(&stored) {
+        assert!((x - y).abs() <= x.abs() * epsilon, "{x} came back as {y}");
+    }
+
+    // Out-of-range components saturate instead of becoming infinite
+    let mut big = vec![0.5; 48];
+    big[0] = 1e6;
+    big[1] = -1e6;
+    store.add("big", big, None).await.unwrap();
+    let big = store.get("big").await.unwrap().unwrap().vector;
+    assert_eq!(big[..3], [65504.0, -65504.0, 0.5]);
+}
+
+#[tokio::test]
+async fn test_f16_store_recall_against_exact() {
+    use object_store::memory::InMemory;
+    use smolpuff::{VectorEncoding, VectorStore, VectorStoreOptions};
+    use std::sync::Arc;
+
+    let exact = VectorStore::open_in_memory().await.unwrap();
+    let half = VectorStoreOptions::new()
+        .vector_encoding(VectorEncoding::F16)
+        .build_and_open("/test", Arc::new(InMemory::new()))
+        .await
+        .unwrap();
+
+    let mut rng = StdRng::seed_from_u64(13);
+    let dim = 64;
+    let items: Vec<_> = (0..500)
+        .map(|i| {
+            let v: Vec<f32> = (0..dim).map(|_| rng.gen_range(-1.0..1.0)).collect();
+            (format!("v{i:03}"), v, None)
+        })
+        .collect();
+    exact
+        .batch_add(items.clone(), smolpuff::DuplicatePolicy::Error)
+        .await
+        .unwrap();
+    half.batch_add(items, smolpuff::DuplicatePolicy::Error)
+        .await
+        .unwrap();
+
+    // Half precision barely moves cosine scores, so the top 10 all but
+    // always agree
+    let mut shared = 0;
+    for _ in 0..20 {
+        let query: Vec<f32> = (0..dim).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        let truth = exact.query(&query, 10).await.unwrap();
+        let approx = half.query(&query, 10).await.unwrap();
+        shared += approx
+            .iter()
+            .filter(|r| truth.iter().any(|t| t.id == r.id))
+            .count();
+    }
+    let recall = shared as f32 / 200.0;
+    assert!(recall >= 0.98, "recall@10 was {recall}");
+}
+
+#[test]
+fn test_product_quantizer_codes_and_codebooks() {
+    use smolpuff::{PqParams, ProductQuantizer};
+
+    let mut rng = StdRng::seed_from_u64(17);
+    let vectors: Vec<Vec<f32>> = (0..300)
+        .map(|_| (0..24).map(|_| rng.gen_range(-1.0..1.0)).collect())
+        .collect();
+    let params = PqParams {
+        subspaces: 6,
+        centroids: 32,
+        iterations: 10,
+        ..PqParams::default()
+    };
+    let pq = ProductQuantizer::train(&vectors, params).unwrap();
+    assert_eq!((pq.dim(), pq.subspaces()), (24, 6));
+    // Training is seeded, so it is repeatable
+    assert_eq!(ProductQuantizer::train(&vectors, params).unwrap(), pq);
+
+    // One byte per subspace, decoding to a vector nearer than chance
+    let codes = pq.encode(&vectors[0]);
+    assert_eq!(codes.len(), 6);
+    let decoded = pq.decode(&codes);
+    assert_eq!(decoded.len(), 24);
+    assert_eq!(pq.encode(&decoded), codes);
+    let error: f32 = vectors[0]
+        .iter()
+        .zip(&decoded)
+        .map(|(x, y)| (x - y).powi(2))
+        .sum();
+    let spread: f32 = vectors[0]
+        .iter()
+        .zip(&vectors[1])
+        .map(|(x, y)| (x - y).powi(2))
+        .sum();
+    assert!(error < spread / 2.0, "{error} vs {spread}");
+
+    // Fewer vectors than centroids leaves a smaller codebook
+    let tiny = ProductQuantizer::train(&vectors[..3], params).unwrap();
+    assert!(tiny.encode(&vectors[9]).iter().all(|&c| c < 3));
+
+    for bad in [
+        PqParams {
+            subspaces: 5,
+            ..params
+        },
+        PqParams {
+            subspaces: 0,
+            ..params
+        },
+        PqParams {
+            centroids: 0,
+            ..params
+        },
+        PqParams {
+            centroids: 257,
+            ..params
+        },
+    ] {
+        assert!(ProductQuantizer::train(&vectors, bad).is_err(), "{bad:?}");
+    }
+    assert!(ProductQuantizer::train(&[], params).is_err());
+
+    let codebooks = vec![vec![vec![0.0, 0.0], vec![1.0, 1.0]]; 2];
+    let pq = ProductQuantizer::from_codebooks(codebooks).unwrap();
+    assert_eq!(pq.encode(&[0.9, 0.8, 0.1, -0.2]), [1, 0]);
+    assert!(pq.fits(&[1, 0]));
+    assert!(!pq.fits(&[2, 0]));
+    assert!(!pq.fits(&[1]));
+    assert_eq!(pq.decode(&[1, 0]), [1.0, 1.0, 0.0, 0.0]);
+    assert!(ProductQuantizer::from_codebooks(vec![vec![vec![0.0], vec![0.0, 1.0]]]).is_err());
+    assert!(ProductQuantizer::from_codebooks(vec![vec![vec![0.0]], vec![]]).is_err());
+    assert!(ProductQuantizer::from_codebooks(Vec::new()).is_err());
+}
+
+#[tokio::test]
+async fn test_query_pq_recall_against_exact() {
+    use object_store::ObjectStore;
+    use object_store::memory::InMemory;
+    use smolpuff::{PqParams, ProductQuantizer, VectorStore};
+    use std::sync::Arc;
+
+    // Clusters, so every query has true neighbors to find
+    let mut rng = StdRng::seed_from_u64(19);
+    let dim = 32;
+    let centers: Vec<Vec<f32>> = (0..40)
+        .map(|_| (0..dim).map(|_| rng.gen_range(-1.0..1.0)).collect())
+        .collect();
+    let mut near = |center: &[f32]| -> Vec<f32> {
+        center
+            .iter()
+            .map(|x| x + rng.gen_range(-0.2..0.2))
+            .collect()
+    };
+    let items: Vec<_> = (0..2000)
+        .map(|i| (format!("v{i:04}"), near(&centers[i % 40]), None))
+        .collect();
+    let queries: Vec<Vec<f32>> = (0..20).map(|i| near(&centers[i])).collect();
+
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let store = VectorStore::open("/pq", object_store.clone())
+        .await
+        .unwrap();
+    store
+        .batch_add(items.clone(), smolpuff::DuplicatePolicy::Error)
+        .await
+        .unwrap();
+    // Without codebooks, query_pq is an exact query
+    assert_eq!(
+        store.query_pq(&queries[0], 10).await.unwrap(),
+        store.query(&queries[0], 10).await.unwrap()
+    );
+
+    let params = PqParams {
+        subspaces: 16,
+        centroids: 256,
+        iterations: 8,
+        ..PqParams::default()
+    };
+    store.train_pq(params).await.unwrap();
+    let mut recall = 0.0;
+    for query in &queries {
+        let truth = store.query_exact(query, 10).await.unwrap();
+        let approx = store.query_pq(query, 10).await.unwrap();
+        assert_eq!(approx.len(), 10);
+        assert!(approx.windows(2).all(|w| w[0].score >= w[1].score));
+        recall += smolpuff::recall_at_k(&truth, &approx) / queries.len() as f64;
+    }
+    assert!(recall >= 0.75, "recall@10 was {recall}");
+
+    // 16 bytes of codes stand in for a 4-byte header and 32 f32s
+    let quantizer = ProductQuantizer::train(&queries, params).unwrap();
+    let codes_len = quantizer.encode(&items[0].1).len();
+    let raw_len = store.get_raw("v0000").await.unwrap().unwrap().len();
+    assert_eq!((codes_len, raw_len), (16, 4 + 4 * dim));
+    assert_eq!(raw_len / codes_len, 8);
+
+    // Writes are encoded as they land, deletes drop their codes
+    store.add("exact", queries[1].clone(), None).await.unwrap();
+    assert_eq!(store.query_pq(&queries[1], 1).await.unwrap()[0].id, "exact");
+    store.delete("exact").await.unwrap();
+    let found = store.query_pq(&queries[1], 10).await.unwrap();
+    assert!(found.iter().all(|r| r.id != "exact"));
+    let before = store.query_pq(&queries[2], 10).await.unwrap();
+    store.close().await.unwrap();
+
+    // The codebooks are persisted under meta:pq
+    let store = VectorStore::open("/pq", object_store.clone())
+        .await
+        .unwrap();
+    assert_eq!(store.query_pq(&queries[2], 10).await.unwrap(), before);
+
+    // Codebooks have to match the store's dimension
+    let wrong = ProductQuantizer::from_codebooks(vec![vec![vec![0.0; 4]]; 4]).unwrap();
+    assert!(matches!(
+        store.set_pq(wrong).await,
+        Err(smolpuff::VectorStoreError::DimensionMismatch {
+            expected: 32,
+            got: 16
+        })
+    ));
+    assert_eq!(store.query_pq(&queries[2], 10).await.unwrap(), before);
+}
@@ -0,0 +1,4033 @@
+use object_store::ObjectStore;
+use object_store::memory::InMemory;
+use smolpuff::consistency::{retry_until_visible, verify_write};
+use smolpuff::{
+    DistanceMetric, DuplicatePolicy, Filter, KeyEncoding, PreparedQuery, ScoreNormalization,
+    SerializationFormat, SessionQuery, SparseVector, VectorRecord, VectorStore, VectorStoreError,
+    VectorStoreOptions,
+};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+async fn open_store() -> VectorStore {
+    VectorStore::open_in_memory()
+        .await
+        .expect("Failed to open store")
+}
+
+/// A cosine store whose default namespace was created as Euclidean, holding
+/// records whose nearest Euclidean neighbors aren't their cosine ones
+async fn open_euclidean_default() -> VectorStore {
+    let store = open_store().await;
+    store
+        .create_namespace("_default", 2, "euclidean")
+        .await
+        .unwrap();
+    for i in 0..40 {
+        let x = i as f32;
+        let metadata = serde_json::json!({ "i": i });
+        store
+            .add(
+                &format!("v{i:02}"),
+                vec![x * 0.25, (x * 0.7).sin()],
+                Some(metadata),
+            )
+            .await
+            .unwrap();
+    }
+    store
+}
+
+#[tokio::test]
+async fn test_query_profiled_scans_every_record() {
+    let store = open_store().await;
+
+    for i in 0..10 {
+        let vector = vec![i as f32, 1.0, 0.0];
+        store.add(&format!("v{i}"), vector, None).await.unwrap();
+    }
+
+    let (results, profile) = store.query_profiled(&[1.0, 0.0, 0.0], 5).await.unwrap();
+    assert_eq!(results.len(), 5);
+    assert_eq!(profile.records_scanned, 10);
+    assert!(profile.bytes_read > 0);
+}
+
+#[tokio::test]
+async fn test_query_prepared_matches_query() {
+    let store = open_store().await;
+
+    for i in 0..10 {
+        let vector = vec![i as f32, 1.0, 0.5];
+        store.add(&format!("v{i}"), vector, None).await.unwrap();
+    }
+
+    let query = vec![1.0, 0.2, 0.0];
+    let prepared = PreparedQuery::new(query.clone());
+    for _ in 0..3 {
+        let expected = store.query(&query, 5).await.unwrap();
+        let actual = store.query_prepared(&prepared, 5).await.unwrap();
+        let expected: Vec<_> = expected.iter().map(|r| (&r.id, r.score)).collect();
+        let actual: Vec<_> = actual.iter().map(|r| (&r.id, r.score)).collect();
+        assert_eq!(expected, actual);
+    }
+}
+
+#[tokio::test]
+async fn test_max_dimension_rejects_before_write() {
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let store = VectorStoreOptions::new()
+        .max_dimension(4)
+        .build_and_open("/test", object_store)
+        .await
+        .unwrap();
+
+    let err = store.add("big", vec![1.0; 5], None).await.unwrap_err();
+    assert!(matches!(
+        err,
+        VectorStoreError::DimensionTooLarge { max: 4, got: 5 }
+    ));
+
+    let err = store.query(&[1.0; 5], 1).await.unwrap_err();
+    assert!(matches!(err, VectorStoreError::DimensionTooLarge { .. }));
+
+    // Nothing was written, so the store is still empty
+    store.add("ok", vec![1.0, 0.0, 0.0], None).await.unwrap();
+    let results = store.query(&[1.0, 0.0, 0.0], 10).await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].id, "ok");
+}
+
+#[tokio::test]
+async fn test_distinct_values_counts_categories() {
+    let store = open_store().await;
+
+    let categories = ["A", "B", "A", "C", "A", "B"];
+    for (i, category) in categories.iter().enumerate() {
+        let metadata = serde_json::json!({ "category": category });
+        store
+            .add(&format!("v{i}"), vec![1.0, i as f32], Some(metadata))
+            .await
+            .unwrap();
+    }
+    store
+        .add(
+            "untagged",
+            vec![0.0, 1.0],
+            Some(serde_json::json!({ "other": 1 })),
+        )
+        .await
+        .unwrap();
+    store.add("bare", vec![1.0, 1.0], None).await.unwrap();
+
+    let counts = store.distinct_values("category").await.unwrap();
+    assert_eq!(counts.len(), 3);
+    assert_eq!(counts[&serde_json::json!("A")], 3);
+    assert_eq!(counts[&serde_json::json!("B")], 2);
+    assert_eq!(counts[&serde_json::json!("C")], 1);
+}
+
+#[tokio::test]
+async fn test_batch_add_lands_every_record_for_any_batch_size() {
+    for batch_size in [1, 3, 10, 100] {
+        let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let store = VectorStoreOptions::new()
+            .batch_size(batch_size)
+            .build_and_open("/test", object_store)
+            .await
+            .unwrap();
+
+        let items = (0..10)
+            .map(|i| {
+                let metadata = serde_json::json!({ "i": i });
+                (format!("v{i}"), vec![i as f32, 1.0, 0.0], Some(metadata))
+            })
+            .collect();
+        store
+            .batch_add(items, DuplicatePolicy::Error)
+            .await
+            .unwrap();
+
+        let results = store.query(&[1.0, 0.0, 0.0], 100).await.unwrap();
+        assert_eq!(results.len(), 10, "batch_size {batch_size}");
+        assert!(results.iter().all(|r| r.metadata.is_some()));
+    }
+}
+
+#[tokio::test]
+async fn test_batch_add_duplicate_policies() {
+    let batch = || {
+        vec![
+            (
+                "a".to_string(),
+                vec![1.0, 0.0],
+                Some(serde_json::json!({ "v": 1 })),
+            ),
+            ("b".to_string(), vec![0.0, 1.0], None),
+            (
+                "a".to_string(),
+                vec![1.0, 0.0],
+                Some(serde_json::json!({ "v": 2 })),
+            ),
+        ]
+    };
+
+    let store = open_store().await;
+    let err = store
+        .batch_add(batch(), DuplicatePolicy::Error)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, VectorStoreError::InvalidRequest(_)));
+    assert!(!store.contains("a").await.unwrap());
+    assert!(!store.contains("b").await.unwrap());
+
+    for (policy, expected) in [
+        (DuplicatePolicy::KeepFirst, 1),
+        (DuplicatePolicy::KeepLast, 2),
+    ] {
+        let store = open_store().await;
+        store.batch_add(batch(), policy).await.unwrap();
+
+        let results = store.query(&[1.0, 0.0], 10).await.unwrap();
+        assert_eq!(results.len(), 2);
+        let a = results.iter().find(|r| r.id == "a").unwrap();
+        assert_eq!(
+            a.metadata,
+            Some(serde_json::json!({ "v": expected })),
+            "{policy:?}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_similarity_matrix_is_symmetric_with_known_values() {
+    let store = open_store().await;
+
+    store.add("x", vec![1.0, 0.0], None).await.unwrap();
+    store.add("y", vec![0.0, 1.0], None).await.unwrap();
+    store.add("xy", vec![1.0, 1.0], None).await.unwrap();
+
+    let ids = ["x", "y", "xy"].map(String::from);
+    let matrix = store
+        .similarity_matrix(&ids, DistanceMetric::Cosine)
+        .await
+        .unwrap();
+
+    assert_eq!(matrix.len(), 3);
+    for (i, row) in matrix.iter().enumerate() {
+        assert!((row[i] - 1.0).abs() < 1e-6);
+        for (j, score) in row.iter().enumerate() {
+            assert_eq!(*score, matrix[j][i]);
+        }
+    }
+    assert!(matrix[0][1].abs() < 1e-6);
+    assert!((matrix[0][2] - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+
+    let missing = ["x", "nope"].map(String::from);
+    let err = store
+        .similarity_matrix(&missing, DistanceMetric::Cosine)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, VectorStoreError::InvalidRequest(_)));
+}
+
+#[tokio::test]
+async fn test_shard_into_distributes_disjointly_and_completely() {
+    let source = open_store().await;
+    for i in 0..20 {
+        let metadata = serde_json::json!({ "i": i });
+        source
+            .add(&format!("v{i}"), vec![i as f32, 1.0], Some(metadata))
+            .await
+            .unwrap();
+    }
+
+    let shard_a = open_store().await;
+    let shard_b = open_store().await;
+    let hash = |id: &str| id.bytes().map(u64::from).sum::<u64>();
+    let counts = source
+        .shard_into(&[&shard_a, &shard_b], hash)
+        .await
+        .unwrap();
+    assert_eq!(counts.iter().sum::<usize>(), 20);
+
+    let ids = |results: Vec<smolpuff::QueryResult>| {
+        results
+            .into_iter()
+            .map(|r| r.id)
+            .collect::<std::collections::HashSet<_>>()
+    };
+    let a = ids(shard_a.query(&[1.0, 1.0], 100).await.unwrap());
+    let b = ids(shard_b.query(&[1.0, 1.0], 100).await.unwrap());
+    assert_eq!(a.len(), counts[0]);
+    assert_eq!(b.len(), counts[1]);
+    assert!(a.is_disjoint(&b));
+    for id in &a {
+        assert_eq!(hash(id) % 2, 0);
+    }
+    assert_eq!(a.union(&b).count(), 20);
+}
+
+#[tokio::test]
+async fn test_compare_metrics_identical_metrics_agree_fully() {
+    let store = open_store().await;
+    for i in 0..20 {
+        let vector = vec![i as f32, (20 - i) as f32, 1.0];
+        store.add(&format!("v{i}"), vector, None).await.unwrap();
+    }
+
+    let queries = vec![
+        vec![1.0, 0.0, 0.0],
+        vec![0.0, 1.0, 0.5],
+        vec![1.0, 1.0, 1.0],
+    ];
+    let comparison = store
+        .compare_metrics(&queries, DistanceMetric::Cosine, DistanceMetric::Cosine, 5)
+        .await
+        .unwrap();
+    assert_eq!(comparison.mean_overlap, 1.0);
+    assert!((comparison.mean_spearman - 1.0).abs() < 1e-9);
+}
+
+#[tokio::test]
+async fn test_compare_metrics_ignores_the_namespace_metric() {
+    let euclidean = open_euclidean_default().await;
+    let cosine = open_store().await;
+    for id in euclidean.list_ids().await.unwrap() {
+        let record = euclidean.get(&id).await.unwrap().unwrap();
+        cosine.add(&id, record.vector, None).await.unwrap();
+    }
+
+    let queries = vec![vec![2.0, 0.5], vec![-1.0, 1.0]];
+    let (a, b) = (DistanceMetric::Euclidean, DistanceMetric::Cosine);
+    let from_euclidean = euclidean.compare_metrics(&queries, a, b, 5).await.unwrap();
+    let from_cosine = cosine.compare_metrics(&queries, a, b, 5).await.unwrap();
+    assert_eq!(from_euclidean, from_cosine);
+    assert!(from_euclidean.mean_overlap < 1.0);
+}
+
+#[tokio::test]
+async fn test_compare_metrics_on_empty_store_reports_no_agreement() {
+    let store = open_store().await;
+    let queries = vec![vec![1.0, 0.0, 0.0]];
+    let comparison = store
+        .compare_metrics(
+            &queries,
+            DistanceMetric::Cosine,
+            DistanceMetric::Euclidean,
+            5,
+        )
+        .await
+        .unwrap();
+    assert_eq!(comparison.mean_overlap, 0.0);
+    assert_eq!(comparison.mean_spearman, 0.0);
+
+    // Emptied after its namespace was created, too
+    store.add("v", vec![1.0, 0.0, 0.0], None).await.unwrap();
+    store.delete("v").await.unwrap();
+    let comparison = store
+        .compare_metrics(
+            &queries,
+            DistanceMetric::Cosine,
+            DistanceMetric::Euclidean,
+            5,
+        )
+        .await
+        .unwrap();
+    assert_eq!(comparison, smolpuff::MetricComparison::default());
+}
+
+#[tokio::test]
+async fn test_open_and_verify_after_unclean_shutdown() {
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+
+    let store = VectorStore::open("/test", object_store.clone())
+        .await
+        .unwrap();
+    for i in 0..10 {
+        let metadata = serde_json::json!({ "i": i });
+        store
+            .add(&format!("v{i}"), vec![i as f32, 1.0, 0.0], Some(metadata))
+            .await
+            .unwrap();
+    }
+    // Simulate a crash: drop without calling close()
+    drop(store);
+
+    let (store, report) = VectorStore::open_and_verify("/test", object_store)
+        .await
+        .unwrap();
+    assert!(report.is_healthy(), "{:?}", report.problems);
+    assert_eq!(report.namespaces, 1);
+    assert_eq!(report.records_sampled, 20);
+
+    let results = store.query(&[1.0, 0.0, 0.0], 100).await.unwrap();
+    assert_eq!(results.len(), 10);
+}
+
+#[tokio::test]
+async fn test_open_and_verify_accepts_sparse_chunk_and_index_records() {
+    use smolpuff::ProductQuantizer;
+
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let store = VectorStoreOptions::new()
+        .lsh(smolpuff::LshParams::default())
+        .build_and_open("/test", object_store.clone())
+        .await
+        .unwrap();
+    for i in 0..4 {
+        store
+            .add(&format!("v{i}"), vec![i as f32, 1.0, 0.0, 1.0], None)
+            .await
+            .unwrap();
+    }
+    let codebooks = vec![vec![vec![0.0, 0.0], vec![1.0, 1.0]]; 2];
+    store
+        .set_pq(ProductQuantizer::from_codebooks(codebooks).unwrap())
+        .await
+        .unwrap();
+    let metadata = Some(serde_json::json!({ "title": "t" }));
+    store
+        .add_sparse("s", SparseVector::from([(3, 0.5)]), metadata.clone())
+        .await
+        .unwrap();
+    store
+        .add_document("d", vec![vec![1.0, 0.0, 0.0, 0.0]; 2], metadata)
+        .await
+        .unwrap();
+    drop(store);
+
+    let (_, report) = VectorStore::open_and_verify("/test", object_store)
+        .await
+        .unwrap();
+    assert!(report.is_healthy(), "{:?}", report.problems);
+    assert_eq!(report.namespaces, 1);
+    // 4 vectors, a sparse vector and its document, 2 chunks and their document
+    assert_eq!(report.records_sampled, 9);
+}
+
+#[tokio::test]
+async fn test_concurrent_appends_get_unique_ids() {
+    let store = Arc::new(open_store().await);
+    store.add("seed", vec![0.0, 1.0], None).await.unwrap();
+
+    let handles: Vec<_> = (0..20)
+        .map(|i| {
+            let store = store.clone();
+            tokio::spawn(async move { store.append(vec![i as f32, 1.0], None).await })
+        })
+        .collect();
+    let mut ids = Vec::new();
+    for handle in handles {
+        ids.push(handle.await.unwrap().unwrap());
+    }
+
+    ids.sort_unstable();
+    assert_eq!(ids, (1..=20).collect::<Vec<u64>>());
+
+    let results = store.query(&[1.0, 1.0], 100).await.unwrap();
+    assert_eq!(results.len(), 21);
+    assert!(store.contains(&format!("{:020}", 20)).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_session_query_shifts_toward_recent_queries() {
+    let store = open_store().await;
+    store.add("x", vec![1.0, 0.0], None).await.unwrap();
+    store.add("y", vec![0.0, 1.0], None).await.unwrap();
+
+    let mut session = SessionQuery::new(&store, 0.5);
+    assert!(session.query(1).await.unwrap().is_empty());
+
+    session.submit(&[1.0, 0.0]).unwrap();
+    assert_eq!(session.current(), Some(&[1.0, 0.0][..]));
+    assert_eq!(session.query(1).await.unwrap()[0].id, "x");
+
+    session.submit(&[0.0, 1.0]).unwrap();
+    assert_eq!(session.current(), Some(&[0.5, 0.5][..]));
+
+    session.submit(&[0.0, 1.0]).unwrap();
+    assert_eq!(session.current(), Some(&[0.25, 0.75][..]));
+    assert_eq!(session.query(1).await.unwrap()[0].id, "y");
+
+    let err = session.submit(&[1.0, 0.0, 0.0]).unwrap_err();
+    assert!(matches!(err, VectorStoreError::DimensionMismatch { .. }));
+}
+
+#[tokio::test]
+async fn test_normalize_all_yields_unit_vectors_and_skips_zero() {
+    let store = open_store().await;
+    store.add("a", vec![3.0, 4.0], None).await.unwrap();
+    store.add("b", vec![0.0, 10.0], None).await.unwrap();
+    store.add("unit", vec![1.0, 0.0], None).await.unwrap();
+    store.add("zero", vec![0.0, 0.0], None).await.unwrap();
+
+    assert_eq!(store.normalize_all().await.unwrap(), 2);
+    // Everything left is either unit length or zero
+    assert_eq!(store.normalize_all().await.unwrap(), 0);
+
+    let ids = ["a", "b", "unit"].map(String::from);
+    let matrix = store
+        .similarity_matrix(&ids, DistanceMetric::Cosine)
+        .await
+        .unwrap();
+    assert!((matrix[0][2] - 0.6).abs() < 1e-6);
+}
+
+#[tokio::test]
+async fn test_retrieve_and_rerank_orders_by_model_score() {
+    let store = open_store().await;
+    for i in 0..10 {
+        let metadata = serde_json::json!({ "rank": i });
+        store
+            .add(&format!("v{i}"), vec![1.0, i as f32 * 0.01], Some(metadata))
+            .await
+            .unwrap();
+    }
+
+    // The "model" prefers higher `rank`, regardless of vector similarity
+    let scorer = |result: smolpuff::QueryResult| async move {
+        tokio::task::yield_now().await;
+        result.metadata.unwrap()["rank"].as_f64().unwrap() as f32
+    };
+    let results = store
+        .retrieve_and_rerank(&[1.0, 0.0], 10, 3, scorer, 4)
+        .await
+        .unwrap();
+
+    let ids: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(ids, ["v9", "v8", "v7"]);
+    assert_eq!(results[0].score, 9.0);
+}
+
+#[tokio::test]
+async fn test_retrieve_and_rerank_ranks_nan_last_and_ties_by_id() {
+    let store = open_store().await;
+    for (id, score) in [("d", f32::NAN), ("c", 1.0), ("a", 1.0), ("b", 2.0)] {
+        let metadata = serde_json::json!({ "score": score.to_string() });
+        store.add(id, vec![1.0, 0.0], Some(metadata)).await.unwrap();
+    }
+
+    let scorer = |result: smolpuff::QueryResult| async move {
+        result.metadata.unwrap()["score"]
+            .as_str()
+            .unwrap()
+            .parse::<f32>()
+            .unwrap()
+    };
+    let results = store
+        .retrieve_and_rerank(&[1.0, 0.0], 10, 4, scorer, 4)
+        .await
+        .unwrap();
+
+    let ids: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(ids, ["b", "a", "c", "d"]);
+    assert!(results[3].score.is_nan());
+}
+
+#[tokio::test]
+async fn test_escaped_key_encoding_handles_edge_case_ids() {
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let store = VectorStoreOptions::new()
+        .key_encoding(KeyEncoding::Escaped)
+        .build_and_open("/test", object_store)
+        .await
+        .unwrap();
+
+    let long = "x".repeat(1000);
+    let ids = [
+        "a:b",
+        "a",
+        "a;",
+        "50%",
+        "tab\there",
+        "new\nline",
+        "é:ü",
+        long.as_str(),
+    ];
+    for (i, id) in ids.iter().enumerate() {
+        let metadata = serde_json::json!({ "i": i });
+        store
+            .add(id, vec![1.0, i as f32], Some(metadata))
+            .await
+            .unwrap();
+    }
+
+    for id in ids {
+        assert!(store.contains(id).await.unwrap(), "{id:?}");
+    }
+    let results = store.query(&[1.0, 0.0], 100).await.unwrap();
+    let mut found: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+    found.sort_unstable();
+    let mut expected = ids.to_vec();
+    expected.sort_unstable();
+    assert_eq!(found, expected);
+
+    // A namespace whose name looks like another namespace's key prefix
+    // stays out of that namespace's scans, and deleting it leaves "a" intact
+    store.create_namespace("a", 2, "cosine").await.unwrap();
+    store
+        .create_namespace("a:vec:x", 2, "cosine")
+        .await
+        .unwrap();
+    store
+        .upsert("a", "x:meta", vec![1.0, 0.0], None)
+        .await
+        .unwrap();
+    store
+        .upsert("a:vec:x", "y", vec![1.0, 0.0], None)
+        .await
+        .unwrap();
+
+    let in_a = store.query_ns("a", &[1.0, 0.0], 10).await.unwrap();
+    assert_eq!(in_a.len(), 1);
+    assert_eq!(in_a[0].id, "x:meta");
+
+    store.delete_namespace("a:vec:x").await.unwrap();
+    assert!(store.get_namespace("a").await.is_ok());
+    assert_eq!(store.query_ns("a", &[1.0, 0.0], 10).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_clone_to_preserves_query_results() {
+    let source = open_store().await;
+    for i in 0..15 {
+        let metadata = serde_json::json!({ "i": i });
+        source
+            .add(&format!("v{i}"), vec![i as f32, 1.0, 0.5], Some(metadata))
+            .await
+            .unwrap();
+    }
+
+    let dest: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let copied = source.clone_to(dest.clone(), "/copy").await.unwrap();
+    assert_eq!(copied, 15);
+
+    let clone = VectorStore::open("/copy", dest.clone()).await.unwrap();
+    let query = [1.0, 0.3, 0.0];
+    let expected = source.query(&query, 5).await.unwrap();
+    let actual = clone.query(&query, 5).await.unwrap();
+    let expected: Vec<_> = expected
+        .iter()
+        .map(|r| (&r.id, r.score, &r.metadata))
+        .collect();
+    let actual: Vec<_> = actual
+        .iter()
+        .map(|r| (&r.id, r.score, &r.metadata))
+        .collect();
+    assert_eq!(expected, actual);
+    clone.close().await.unwrap();
+
+    // The destination now has data, so a second clone is refused
+    let err = source.clone_to(dest, "/copy").await.unwrap_err();
+    assert!(matches!(err, VectorStoreError::InvalidRequest(_)));
+}
+
+#[tokio::test]
+async fn test_query_explained_contributions_add_up_to_score() {
+    let store = open_store().await;
+    store
+        .add("a", vec![3.0, 0.5, 1.0, 0.0], None)
+        .await
+        .unwrap();
+    store
+        .add("b", vec![0.0, 2.0, 0.0, 1.0], None)
+        .await
+        .unwrap();
+
+    let query = [1.0, 1.0, 0.5, 0.0];
+    let explained = store.query_explained(&query, 1, 4).await.unwrap();
+    assert_eq!(explained.len(), 1);
+    let top = &explained[0];
+    assert_eq!(top.result.id, "a");
+
+    let dims: Vec<usize> = top.top_dimensions.iter().map(|(d, _)| *d).collect();
+    assert_eq!(dims[..3], [0, 1, 2]);
+
+    let dot: f32 = top.top_dimensions.iter().map(|(_, c)| c).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let cosine = dot / (norm(&query) * norm(&[3.0, 0.5, 1.0, 0.0]));
+    assert!((cosine - top.result.score).abs() < 1e-6);
+
+    let truncated = store.query_explained(&query, 2, 2).await.unwrap();
+    assert!(truncated.iter().all(|e| e.top_dimensions.len() == 2));
+}
+
+#[tokio::test]
+async fn test_query_explained_uses_namespace_metric_and_standardization() {
+    let store = VectorStoreOptions::new()
+        .metric(DistanceMetric::Euclidean)
+        .build_and_open("/explained", Arc::new(InMemory::new()))
+        .await
+        .unwrap();
+    for i in 0..6 {
+        let i = i as f32;
+        store
+            .add(
+                &format!("v{i}"),
+                vec![10.0 + i, 100.0 - 3.0 * i, i * 0.5],
+                None,
+            )
+            .await
+            .unwrap();
+    }
+    store.standardize("_default").await.unwrap();
+
+    let query = [12.0, 95.0, 0.2];
+    let explained = store.query_explained(&query, 3, 3).await.unwrap();
+    for e in &explained {
+        assert!(e.top_dimensions.windows(2).all(|w| w[0].1 >= w[1].1));
+        // Squared differences from the standardized query add up to the
+        // squared distance the result was scored with
+        let squared: f32 = e.top_dimensions.iter().map(|(_, t)| t).sum();
+        assert!((squared.sqrt() - e.result.score).abs() < 1e-4, "{e:?}");
+    }
+}
+
+#[tokio::test]
+async fn test_query_with_tie_break_orders_ties_by_field() {
+    let store = open_store().await;
+    // Same direction, so every record scores exactly the same
+    for (id, ts) in [("old", 1), ("newest", 3), ("mid", 2)] {
+        let metadata = serde_json::json!({ "ts": ts });
+        store.add(id, vec![1.0, 1.0], Some(metadata)).await.unwrap();
+    }
+    store.add("undated", vec![1.0, 1.0], None).await.unwrap();
+    store.add("far", vec![1.0, -1.0], None).await.unwrap();
+
+    let results = store
+        .query_with_tie_break(&[1.0, 1.0], 3, Some("ts"))
+        .await
+        .unwrap();
+    let ids: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(ids, ["newest", "mid", "old"]);
+
+    let results = store
+        .query_with_tie_break(&[1.0, 1.0], 10, Some("ts"))
+        .await
+        .unwrap();
+    let ids: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(ids, ["newest", "mid", "old", "undated", "far"]);
+}
+
+#[tokio::test]
+async fn test_query_with_tie_break_scores_with_the_namespace_metric() {
+    // A default namespace created as Euclidean in a store that predates the
+    // persisted store metric, which then opens as cosine
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let db = slatedb::Db::open("/tied", object_store.clone())
+        .await
+        .unwrap();
+    let meta = serde_json::json!({
+        "name": "_default",
+        "vector_dim": 2,
+        "distance": "euclidean",
+        "approx_row_count": 0,
+        "created_at": "2024-01-01T00:00:00Z",
+    });
+    db.put(b"ns:_default:meta", &serde_json::to_vec(&meta).unwrap())
+        .await
+        .unwrap();
+    db.close().await.unwrap();
+
+    let store = VectorStore::open("/tied", object_store).await.unwrap();
+    assert_eq!(store.metric(), DistanceMetric::Cosine);
+    for (id, v) in [
+        ("near", [1.0, 0.0]),
+        ("same_dir", [9.0, 0.0]),
+        ("off", [1.0, 1.0]),
+    ] {
+        let metadata = serde_json::json!({ "ts": 1 });
+        store.add(id, v.to_vec(), Some(metadata)).await.unwrap();
+    }
+
+    let expected = store.query(&[1.0, 0.0], 3).await.unwrap();
+    let results = store
+        .query_with_tie_break(&[1.0, 0.0], 3, Some("ts"))
+        .await
+        .unwrap();
+    assert_eq!(results, expected);
+    let ids: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(ids, ["near", "off", "same_dir"]);
+}
+
+#[tokio::test]
+async fn test_query_with_tie_break_keeps_ties_found_before_the_cutoff_rises() {
+    let store = open_store().await;
+    let tied = |ts: i64| Some(serde_json::json!({ "ts": ts }));
+    // Scanned in id order: two ties fill k = 2 before the best record
+    // pushes one of them out, and a third tie arrives after it
+    let items = vec![
+        ("a_tie".to_string(), vec![1.0, 1.0], tied(1)),
+        ("b_tie".to_string(), vec![1.0, 1.0], tied(5)),
+        ("c_best".to_string(), vec![1.0, 0.0], None),
+        ("d_tie".to_string(), vec![1.0, 1.0], tied(9)),
+        ("e_far".to_string(), vec![0.0, 1.0], tied(100)),
+    ];
+    store
+        .batch_add(items, DuplicatePolicy::Error)
+        .await
+        .unwrap();
+
+    let results = store
+        .query_with_tie_break(&[1.0, 0.0], 2, Some("ts"))
+        .await
+        .unwrap();
+    let ids: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(ids, ["c_best", "d_tie"]);
+
+    let err = store
+        .query_with_tie_break(&[1.0, 0.0, 0.0], 2, Some("ts"))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        VectorStoreError::DimensionMismatch {
+            expected: 2,
+            got: 3
+        }
+    ));
+}
+
+#[tokio::test]
+async fn test_standardized_namespace_transforms_queries_like_writes() {
+    let store = open_store().await;
+    store.create_namespace("raw", 3, "cosine").await.unwrap();
+    store.create_namespace("manual", 3, "cosine").await.unwrap();
+
+    let vectors: Vec<Vec<f32>> = (0..12)
+        .map(|i| {
+            let i = i as f32;
+            vec![10.0 + i, 100.0 - 3.0 * i, (i * 0.7).sin()]
+        })
+        .collect();
+    for (i, v) in vectors.iter().enumerate() {
+        store
+            .upsert("raw", &format!("v{i}"), v.clone(), None)
+            .await
+            .unwrap();
+    }
+
+    // "raw" lets the store standardize; "manual" gets the same transform by hand
+    let stats = store.standardize("raw").await.unwrap();
+    for (i, v) in vectors.iter().enumerate() {
+        store
+            .upsert("manual", &format!("v{i}"), stats.apply(v), None)
+            .await
+            .unwrap();
+    }
+    store
+        .upsert("raw", "late", vec![15.0, 80.0, 0.5], None)
+        .await
+        .unwrap();
+    store
+        .upsert("manual", "late", stats.apply(&[15.0, 80.0, 0.5]), None)
+        .await
+        .unwrap();
+
+    let query = [12.0, 95.0, -0.2];
+    let from_raw = store.query_ns("raw", &query, 13).await.unwrap();
+    let from_manual = store
+        .query_ns("manual", &stats.apply(&query), 13)
+        .await
+        .unwrap();
+    let raw_ids: Vec<&str> = from_raw.iter().map(|r| r.id.as_str()).collect();
+    let manual_ids: Vec<&str> = from_manual.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(raw_ids, manual_ids);
+    for (a, b) in from_raw.iter().zip(&from_manual) {
+        assert!((a.score - b.score).abs() < 1e-5);
+    }
+
+    let err = store.standardize("raw").await.unwrap_err();
+    assert!(matches!(err, VectorStoreError::InvalidRequest(_)));
+}
+
+#[tokio::test]
+async fn test_suggest_ivf_params_scales_with_corpus() {
+    let store = open_store().await;
+    let empty = store.suggest_ivf_params().await.unwrap();
+    assert_eq!((empty.corpus_size, empty.nlist, empty.nprobe), (0, 1, 1));
+
+    let mut previous = empty;
+    for target in [100, 400, 1600] {
+        let items = (previous.corpus_size..target)
+            .map(|i| (format!("v{i}"), vec![i as f32, 1.0], None))
+            .collect();
+        store
+            .batch_add(items, DuplicatePolicy::Error)
+            .await
+            .unwrap();
+        let suggestion = store.suggest_ivf_params().await.unwrap();
+        assert_eq!(suggestion.corpus_size, target);
+        assert!(suggestion.nlist > previous.nlist);
+        assert!(suggestion.nprobe >= previous.nprobe);
+        assert!(suggestion.nprobe <= suggestion.nlist);
+        previous = suggestion;
+    }
+    assert_eq!(previous.nlist, 40);
+}
+
+#[tokio::test]
+async fn test_query_lazy_matches_query() {
+    let store = open_store().await;
+    for i in 0..10 {
+        let metadata = serde_json::json!({ "i": i, "text": "x".repeat(100) });
+        store
+            .add(&format!("v{i}"), vec![i as f32, 1.0], Some(metadata))
+            .await
+            .unwrap();
+    }
+    store.add("bare", vec![5.0, 1.0], None).await.unwrap();
+
+    let eager = store.query(&[1.0, 0.2], 11).await.unwrap();
+    let lazy = store.query_lazy(&[1.0, 0.2], 11).await.unwrap();
+    assert_eq!(eager.len(), lazy.len());
+    for (e, l) in eager.iter().zip(&lazy) {
+        assert_eq!(e.id, l.id);
+        assert_eq!(e.score, l.score);
+        assert_eq!(e.metadata, l.metadata().unwrap());
+    }
+    let bare = lazy.iter().find(|r| r.id == "bare").unwrap();
+    assert!(bare.raw_metadata().is_none());
+}
+
+#[tokio::test]
+async fn test_query_lazy_scores_with_the_namespace_metric() {
+    let store = open_euclidean_default().await;
+    let query = [2.0, 0.5];
+    let expected = store.query(&query, 5).await.unwrap();
+    let lazy = store.query_lazy(&query, 5).await.unwrap();
+    let lazy: Vec<_> = lazy.iter().map(|r| (r.id.clone(), r.score)).collect();
+    let expected: Vec<_> = expected.iter().map(|r| (r.id.clone(), r.score)).collect();
+    assert_eq!(lazy, expected);
+}
+
+#[tokio::test]
+async fn test_add_raw_round_trips_between_stores() {
+    let source = open_store().await;
+    for i in 0..5 {
+        source
+            .add(&format!("v{i}"), vec![i as f32, 1.0, -0.5], None)
+            .await
+            .unwrap();
+    }
+
+    let replica = open_store().await;
+    for i in 0..5 {
+        let id = format!("v{i}");
+        let bytes = source.get_raw(&id).await.unwrap().unwrap();
+        replica.add_raw(&id, bytes, true).await.unwrap();
+    }
+    assert!(source.get_raw("missing").await.unwrap().is_none());
+
+    let query = [1.0, 0.5, 0.0];
+    let expected = source.query(&query, 5).await.unwrap();
+    let actual = replica.query(&query, 5).await.unwrap();
+    let expected: Vec<_> = expected.iter().map(|r| (&r.id, r.score)).collect();
+    let actual: Vec<_> = actual.iter().map(|r| (&r.id, r.score)).collect();
+    assert_eq!(expected, actual);
+
+    let err = replica.add_raw("bad", vec![0; 7], true).await.unwrap_err();
+    assert!(matches!(err, VectorStoreError::InvalidRequest(_)));
+    let err = replica
+        .add_raw("short", vec![0; 8], true)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        VectorStoreError::DimensionMismatch {
+            expected: 3,
+            got: 2
+        }
+    ));
+}
+
+#[tokio::test]
+async fn test_rejected_add_raw_leaves_dimension_unpinned() {
+    let source = open_store().await;
+    source.add("wide", vec![1.0; 8], None).await.unwrap();
+    let wide = source.get_raw("wide").await.unwrap().unwrap();
+
+    let store = VectorStoreOptions::new()
+        .max_dimension(4)
+        .build_and_open("/raw", Arc::new(InMemory::new()))
+        .await
+        .unwrap();
+    let err = store.add_raw("wide", wide, true).await.unwrap_err();
+    assert!(matches!(
+        err,
+        VectorStoreError::DimensionTooLarge { max: 4, got: 8 }
+    ));
+    let err = store.add_raw("bad", vec![0; 7], true).await.unwrap_err();
+    assert!(matches!(err, VectorStoreError::InvalidRequest(_)));
+    assert_eq!(store.info().await.unwrap().dimension, None);
+
+    store.add("ok", vec![1.0, 0.0, 0.0], None).await.unwrap();
+    assert_eq!(store.info().await.unwrap().dimension, Some(3));
+}
+
+#[tokio::test]
+async fn test_query_never_repeats_an_id() {
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let escaped = VectorStoreOptions::new()
+        .key_encoding(KeyEncoding::Escaped)
+        .build_and_open("/test", object_store.clone())
+        .await
+        .unwrap();
+    escaped.add("a:b", vec![1.0, 0.0], None).await.unwrap();
+    escaped.add("other", vec![0.0, 1.0], None).await.unwrap();
+    escaped.add("copy", vec![1.0, 0.1], None).await.unwrap();
+    escaped.close().await.unwrap();
+
+    // Escaped, "a:b" is stored under "a%3Ab"; a key ending in the raw "a:b"
+    // decodes to the same id, so move "copy" there to get two of it
+    let db = slatedb::Db::open("/test", object_store.clone())
+        .await
+        .unwrap();
+    for kind in ["vec", "norm"] {
+        let from = format!("ns:_default:{kind}:copy");
+        if let Some(bytes) = db.get(from.as_bytes()).await.unwrap() {
+            db.put(format!("ns:_default:{kind}:a:b").as_bytes(), &bytes)
+                .await
+                .unwrap();
+            db.delete(from.as_bytes()).await.unwrap();
+        }
+    }
+    db.close().await.unwrap();
+
+    let escaped = VectorStore::open("/test", object_store).await.unwrap();
+    let results = escaped.query(&[1.0, 0.0], 10).await.unwrap();
+    let ids: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(ids, ["a:b", "other"]);
+    // The better-scoring of the two copies wins
+    assert!((results[0].score - 1.0).abs() < 1e-6);
+
+    let results = escaped.query(&[1.0, 0.0], 1).await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].id, "a:b");
+}
+
+#[tokio::test]
+async fn test_key_encoding_is_persisted_and_checked_on_open() {
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let store = VectorStoreOptions::new()
+        .key_encoding(KeyEncoding::Escaped)
+        .build_and_open("/test", object_store.clone())
+        .await
+        .unwrap();
+    store.add("a:b", vec![1.0, 0.0], None).await.unwrap();
+    store.close().await.unwrap();
+
+    let err = VectorStoreOptions::new()
+        .key_encoding(KeyEncoding::Raw)
+        .build_and_open("/test", object_store.clone())
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(err, VectorStoreError::InvalidRequest(_)));
+
+    // Left unset, the stored encoding is used
+    let store = VectorStore::open("/test", object_store).await.unwrap();
+    assert_eq!(store.list_ids().await.unwrap(), ["a:b"]);
+    assert_eq!(store.get("a:b").await.unwrap().unwrap().vector, [1.0, 0.0]);
+}
+
+#[tokio::test]
+async fn test_verify_writes_passes_good_writes_and_catches_corruption() {
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let store = VectorStoreOptions::new()
+        .verify_writes(true)
+        .build_and_open("/test", object_store)
+        .await
+        .unwrap();
+
+    let metadata = serde_json::json!({ "k": "v" });
+    store
+        .add("a", vec![1.0, 2.0], Some(metadata))
+        .await
+        .unwrap();
+    assert!(store.contains("a").await.unwrap());
+
+    // The same check the store runs after each put, fed a corrupted read-back
+    let written = [0u8, 0, 128, 63];
+    assert!(verify_write(b"ns:_default:vec:a", &written, Some(&written)).is_ok());
+    let corrupted = [0u8, 0, 128, 64];
+    let err = verify_write(b"ns:_default:vec:a", &written, Some(&corrupted)).unwrap_err();
+    assert!(matches!(
+        err,
+        VectorStoreError::WriteVerificationFailed(key) if key == "ns:_default:vec:a"
+    ));
+    let err = verify_write(b"ns:_default:vec:a", &written, None).unwrap_err();
+    assert!(matches!(err, VectorStoreError::WriteVerificationFailed(_)));
+}
+
+#[tokio::test]
+async fn test_query_masked_ignores_masked_dimensions() {
+    let store = open_store().await;
+    // "near" matches the query on the first two dimensions, "noisy" only on the third
+    store.add("near", vec![1.0, 1.0, 0.0], None).await.unwrap();
+    store.add("noisy", vec![0.1, 0.3, 5.0], None).await.unwrap();
+
+    let query = [1.0, 1.0, 10.0];
+    let full = store.query(&query, 1).await.unwrap();
+    assert_eq!(full[0].id, "noisy");
+
+    let masked = store
+        .query_masked(&query, 2, &[true, true, false])
+        .await
+        .unwrap();
+    let ids: Vec<&str> = masked.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(ids, ["near", "noisy"]);
+    // Restricted to the first two dimensions "near" points exactly along the query
+    assert!((masked[0].score - 1.0).abs() < 1e-6);
+    assert!(masked[1].score < 0.95);
+
+    let err = store.query_masked(&query, 2, &[true]).await.unwrap_err();
+    assert!(matches!(err, VectorStoreError::InvalidRequest(_)));
+}
+
+#[tokio::test]
+async fn test_compact_metadata_strips_null_and_empty_fields() {
+    let store = open_store().await;
+    let messy = serde_json::json!({
+        "title": "keep me",
+        "legacy": null,
+        "note": "",
+        "count": 0,
+        "nested": { "a": 1, "b": null },
+    });
+    store
+        .add("messy", vec![1.0, 0.0], Some(messy))
+        .await
+        .unwrap();
+    let clean = serde_json::json!({ "title": "already clean", "flag": false });
+    store
+        .add("clean", vec![0.9, 0.1], Some(clean.clone()))
+        .await
+        .unwrap();
+    store.add("bare", vec![0.8, 0.2], None).await.unwrap();
+
+    assert_eq!(store.compact_metadata().await.unwrap(), 1);
+    assert_eq!(store.compact_metadata().await.unwrap(), 0);
+
+    let results = store.query(&[1.0, 0.0], 3).await.unwrap();
+    let metadata_of = |id: &str| {
+        results
+            .iter()
+            .find(|r| r.id == id)
+            .unwrap()
+            .metadata
+            .clone()
+    };
+    assert_eq!(
+        metadata_of("messy"),
+        Some(serde_json::json!({ "title": "keep me", "count": 0, "nested": { "a": 1 } }))
+    );
+    assert_eq!(metadata_of("clean"), Some(clean));
+    assert_eq!(metadata_of("bare"), None);
+}
+
+#[tokio::test]
+async fn test_small_block_cache_still_serves_queries() {
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let store = VectorStoreOptions::new()
+        .block_cache_bytes(64 * 1024)
+        .build_and_open("/test", object_store.clone())
+        .await
+        .unwrap();
+    for i in 0..50 {
+        store
+            .add(&format!("v{i}"), vec![i as f32, 1.0, 0.0], None)
+            .await
+            .unwrap();
+    }
+    store.close().await.unwrap();
+
+    // Reopen so reads go through the cache rather than the memtable
+    let store = VectorStoreOptions::new()
+        .block_cache_bytes(64 * 1024)
+        .build_and_open("/test", object_store)
+        .await
+        .unwrap();
+    let results = store.query(&[1.0, 0.0, 0.0], 5).await.unwrap();
+    assert_eq!(results.len(), 5);
+    assert_eq!(results[0].id, "v49");
+}
+
+#[tokio::test]
+async fn test_search_builder_combines_options() {
+    let store = open_store().await;
+    for i in 0..20 {
+        let lang = if i % 2 == 0 { "en" } else { "de" };
+        let metadata = serde_json::json!({ "lang": lang, "i": i });
+        // Higher i points closer to the query
+        store
+            .add(
+                &format!("v{i:02}"),
+                vec![i as f32, 20.0 - i as f32],
+                Some(metadata),
+            )
+            .await
+            .unwrap();
+    }
+    let query = vec![1.0, 0.0];
+
+    let plain = store.search(query.clone()).k(3).run().await.unwrap();
+    let ids: Vec<&str> = plain.results.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(ids, ["v19", "v18", "v17"]);
+    assert!(plain.profile.is_none());
+
+    let found = store
+        .search(query.clone())
+        .k(3)
+        .metric(DistanceMetric::Cosine)
+        .filter(|m| m.is_some_and(|m| m["lang"] == "en"))
+        .exclude(["v18"])
+        .offset(1)
+        .min_score(0.0)
+        .profile()
+        .run()
+        .await
+        .unwrap();
+    let ids: Vec<&str> = found.results.iter().map(|r| r.id.as_str()).collect();
+    // English only, minus v18, skipping the best remaining one (v16)
+    assert_eq!(ids, ["v14", "v12", "v10"]);
+    assert_eq!(found.profile.unwrap().records_scanned, 20);
+
+    let strict = store
+        .search(query)
+        .k(100)
+        .min_score(0.99)
+        .run()
+        .await
+        .unwrap();
+    assert!(!strict.results.is_empty());
+    assert!(strict.results.iter().all(|r| r.score >= 0.99));
+    assert!(strict.results.len() < 20);
+}
+
+/// Stand-in for an eventually consistent backend: a write only becomes
+/// readable once `delay` has passed.
+struct DelayedVisibility {
+    written_at: std::time::Instant,
+    delay: Duration,
+    value: &'static str,
+}
+
+impl DelayedVisibility {
+    async fn get(&self) -> Result<Option<&'static str>, VectorStoreError> {
+        if self.written_at.elapsed() >= self.delay {
+            Ok(Some(self.value))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_retry_until_visible_waits_for_delayed_write() {
+    let mock = DelayedVisibility {
+        written_at: std::time::Instant::now(),
+        delay: Duration::from_millis(50),
+        value: "v1",
+    };
+
+    // A plain read right after the write misses it
+    assert_eq!(mock.get().await.unwrap(), None);
+
+    let found = retry_until_visible(Duration::from_secs(1), || mock.get())
+        .await
+        .unwrap();
+    assert_eq!(found, Some("v1"));
+
+    let mock = DelayedVisibility {
+        written_at: std::time::Instant::now(),
+        delay: Duration::from_secs(60),
+        value: "v2",
+    };
+    let found = retry_until_visible(Duration::from_millis(20), || mock.get())
+        .await
+        .unwrap();
+    assert_eq!(found, None);
+}
+
+#[tokio::test]
+async fn test_contains_with_read_after_write_timeout() {
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let store = VectorStoreOptions::new()
+        .read_after_write_timeout(Duration::from_millis(20))
+        .build_and_open("/test", object_store)
+        .await
+        .unwrap();
+
+    store.add("a", vec![1.0, 0.0], None).await.unwrap();
+    assert!(store.contains("a").await.unwrap());
+    assert!(!store.contains("missing").await.unwrap());
+}
+
+#[cfg(feature = "parquet")]
+#[tokio::test]
+async fn test_export_parquet_round_trips_rows_and_schema() {
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    let store = open_store().await;
+
+    for i in 0..5 {
+        let metadata = serde_json::json!({ "category": "A", "info": { "rank": i } });
+        store
+            .add(&format!("v{i}"), vec![i as f32, 1.0, 0.0], Some(metadata))
+            .await
+            .unwrap();
+    }
+    store.add("bare", vec![0.0, 0.0, 1.0], None).await.unwrap();
+
+    let path = std::env::temp_dir().join(format!("smolpuff-export-{}.parquet", std::process::id()));
+    let rows = store.export_parquet(path.to_str().unwrap()).await.unwrap();
+    assert_eq!(rows, 6);
+
+    let file = std::fs::File::open(&path).unwrap();
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+    let schema = builder.schema().clone();
+    let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+    assert_eq!(
+        names,
+        ["id", "vector", "metadata.category", "metadata.info.rank"]
+    );
+    assert_eq!(
+        schema.field(1).data_type(),
+        &arrow_schema::DataType::FixedSizeList(
+            Arc::new(arrow_schema::Field::new(
+                "item",
+                arrow_schema::DataType::Float32,
+                false
+            )),
+            3
+        )
+    );
+
+    let read: usize = builder
+        .build()
+        .unwrap()
+        .map(|batch| batch.unwrap().num_rows())
+        .sum();
+    assert_eq!(read, 6);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_euclidean_metric_ranks_nearest_first_and_persists() {
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let store =
+        VectorStore::open_with_metric("/test", object_store.clone(), DistanceMetric::Euclidean)
+            .await
+            .unwrap();
+
+    // Same direction as the query, so cosine can't tell them apart
+    store.add("near", vec![1.0, 1.0], None).await.unwrap();
+    store.add("far", vec![10.0, 10.0], None).await.unwrap();
+    store.add("mid", vec![3.0, 3.0], None).await.unwrap();
+
+    let results = store.query(&[1.0, 1.0], 3).await.unwrap();
+    let ids: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(ids, ["near", "mid", "far"]);
+    assert_eq!(results[0].score, 0.0);
+
+    let results = store.query(&[1.0, 1.0], 1).await.unwrap();
+    assert_eq!(results[0].id, "near");
+    store.close().await.unwrap();
+
+    // Reopening without a metric picks up the persisted one
+    let store = VectorStore::open("/test", object_store.clone())
+        .await
+        .unwrap();
+    assert_eq!(store.metric(), DistanceMetric::Euclidean);
+    let results = store.query(&[9.0, 9.0], 1).await.unwrap();
+    assert_eq!(results[0].id, "far");
+    store.close().await.unwrap();
+
+    let err = VectorStore::open_with_metric("/test", object_store, DistanceMetric::Cosine)
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(err, VectorStoreError::InvalidRequest(_)));
+}
+
+#[tokio::test]
+async fn test_dot_product_metric_favors_magnitude() {
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let store = VectorStoreOptions::new()
+        .metric(DistanceMetric::DotProduct)
+        .build_and_open("/test", object_store)
+        .await
+        .unwrap();
+
+    store.add("small", vec![1.0, 0.0], None).await.unwrap();
+    store.add("large", vec![5.0, 1.0], None).await.unwrap();
+
+    let results = store.query(&[1.0, 0.0], 2).await.unwrap();
+    assert_eq!(results[0].id, "large");
+    assert_eq!(results[0].score, 5.0);
+}
+
+#[tokio::test]
+async fn test_euclidean_nearest_neighbor_differs_from_cosine() {
+    let store = open_store().await;
+
+    // "long" points almost exactly along the query but is far away; "short"
+    // is off-angle but close
+    store.add("long", vec![10.0, 1.0], None).await.unwrap();
+    store.add("short", vec![0.8, 0.5], None).await.unwrap();
+
+    let cosine = store.query(&[1.0, 0.0], 1).await.unwrap();
+    assert_eq!(cosine[0].id, "long");
+
+    let euclidean = store
+        .search(vec![1.0, 0.0])
+        .metric(DistanceMetric::Euclidean)
+        .k(2)
+        .run()
+        .await
+        .unwrap()
+        .results;
+    let ids: Vec<&str> = euclidean.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(ids, ["short", "long"]);
+    assert!((euclidean[0].score - 0.29f32.sqrt()).abs() < 1e-6);
+}
+
+#[tokio::test]
+async fn test_delete_removes_record_from_results() {
+    let store = open_store().await;
+
+    store.add("a", vec![1.0, 0.0], None).await.unwrap();
+    store
+        .add("b", vec![0.9, 0.1], Some(serde_json::json!({ "tag": "x" })))
+        .await
+        .unwrap();
+    store.add("c", vec![0.0, 1.0], None).await.unwrap();
+
+    store.delete("b").await.unwrap();
+    // Idempotent, including for ids that never existed
+    store.delete("b").await.unwrap();
+    store.delete("missing").await.unwrap();
+
+    let results = store.query(&[1.0, 0.0], 10).await.unwrap();
+    let ids: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(ids, ["a", "c"]);
+    assert!(!store.contains("b").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_get_returns_stored_record_or_none() {
+    let store = open_store().await;
+
+    let metadata = serde_json::json!({ "category": "A" });
+    store
+        .add("with", vec![1.0, 2.0], Some(metadata.clone()))
+        .await
+        .unwrap();
+    store.add("without", vec![3.0, 4.0], None).await.unwrap();
+
+    assert_eq!(
+        store.get("with").await.unwrap(),
+        Some(VectorRecord {
+            id: "with".to_string(),
+            vector: vec![1.0, 2.0],
+            metadata: Some(metadata),
+        })
+    );
+    assert_eq!(
+        store.get("without").await.unwrap(),
+        Some(VectorRecord {
+            id: "without".to_string(),
+            vector: vec![3.0, 4.0],
+            metadata: None,
+        })
+    );
+    assert_eq!(store.get("missing").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_add_batch_rejects_empty_vector_before_writing() {
+    let store = open_store().await;
+
+    let record = |id: &str, vector: Vec<f32>| VectorRecord {
+        id: id.to_string(),
+        vector,
+        metadata: None,
+    };
+    let err = store
+        .add_batch(vec![record("a", vec![1.0, 0.0]), record("empty", vec![])])
+        .await
+        .unwrap_err();
+    assert!(matches!(err, VectorStoreError::InvalidVector(_)));
+    assert!(!store.contains("a").await.unwrap());
+
+    store
+        .add_batch(vec![
+            record("a", vec![1.0, 0.0]),
+            record("b", vec![0.0, 1.0]),
+        ])
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get("b").await.unwrap(),
+        Some(record("b", vec![0.0, 1.0]))
+    );
+}
+
+#[tokio::test]
+async fn test_add_batch_with_wrong_dimension_leaves_store_unchanged() {
+    // One record per write batch, so a partial write would be visible
+    let store = VectorStoreOptions::new()
+        .batch_size(1)
+        .build_and_open("/atomic", Arc::new(InMemory::new()))
+        .await
+        .unwrap();
+    let record = |id: &str, vector: Vec<f32>| VectorRecord {
+        id: id.to_string(),
+        vector,
+        metadata: Some(serde_json::json!({ "doc": "d1" })),
+    };
+
+    // On an empty store the bad batch must not fix the dimension either
+    let err = store
+        .add_batch(vec![
+            record("d1", vec![1.0, 0.0]),
+            record("d1:0", vec![1.0]),
+        ])
+        .await
+        .unwrap_err();
+    assert!(matches!(err, VectorStoreError::DimensionMismatch { .. }));
+    assert_eq!(store.count().await.unwrap(), 0);
+
+    store
+        .add_batch(vec![record("kept", vec![0.0, 0.0, 1.0])])
+        .await
+        .unwrap();
+    let err = store
+        .add_batch(vec![
+            record("d2", vec![1.0, 0.0, 0.0]),
+            record("d2:0", vec![0.0, 1.0, 0.0]),
+            record("d2:1", vec![0.0, 1.0]),
+        ])
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        VectorStoreError::DimensionMismatch {
+            expected: 3,
+            got: 2
+        }
+    ));
+    assert_eq!(store.count().await.unwrap(), 1);
+    assert!(!store.contains("d2").await.unwrap());
+    assert!(!store.contains("d2:0").await.unwrap());
+    assert_eq!(store.info().await.unwrap().record_count, 1);
+}
+
+#[tokio::test]
+async fn test_query_filtered_returns_best_among_matches() {
+    let store = open_store().await;
+
+    // The closest records are all category A, so filtering after top-k
+    // would return nothing for B
+    for i in 0..5 {
+        let metadata = serde_json::json!({ "category": "A" });
+        store
+            .add(&format!("a{i}"), vec![1.0, i as f32 * 0.01], Some(metadata))
+            .await
+            .unwrap();
+    }
+    for i in 0..3 {
+        let metadata = serde_json::json!({ "category": "B" });
+        store
+            .add(&format!("b{i}"), vec![1.0, 1.0 + i as f32], Some(metadata))
+            .await
+            .unwrap();
+    }
+    store.add("bare", vec![1.0, 0.0], None).await.unwrap();
+
+    let results = store
+        .query_filtered(&[1.0, 0.0], 2, |m| m["category"] == "B")
+        .await
+        .unwrap();
+    let ids: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(ids, ["b0", "b1"]);
+
+    let results = store
+        .query_filtered(&[1.0, 0.0], 10, |_| true)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 8);
+    assert!(results.iter().all(|r| r.id != "bare"));
+}
+
+#[tokio::test]
+async fn test_dimension_mismatch_rejected_on_add_and_query() {
+    let store = open_store().await;
+
+    // A query on an empty store doesn't pin a dimension; the first add does
+    assert!(store.query(&[1.0, 0.0], 1).await.unwrap().is_empty());
+    store.add("a", vec![1.0, 0.0, 0.0], None).await.unwrap();
+
+    let err = store.add("b", vec![1.0, 0.0], None).await.unwrap_err();
+    assert!(matches!(
+        err,
+        VectorStoreError::DimensionMismatch {
+            expected: 3,
+            got: 2
+        }
+    ));
+    let err = store.query(&[1.0, 0.0], 1).await.unwrap_err();
+    assert!(matches!(
+        err,
+        VectorStoreError::DimensionMismatch {
+            expected: 3,
+            got: 2
+        }
+    ));
+    assert_eq!(store.query(&[1.0, 0.0, 0.0], 1).await.unwrap()[0].id, "a");
+}
+
+#[tokio::test]
+async fn test_query_with_threshold_keeps_scores_at_or_above_boundary() {
+    let store = open_store().await;
+
+    store.add("exact", vec![1.0, 0.0], None).await.unwrap();
+    // Scores exactly 0.6 against [1, 0]
+    store.add("boundary", vec![3.0, 4.0], None).await.unwrap();
+    store.add("orthogonal", vec![0.0, 1.0], None).await.unwrap();
+
+    let results = store
+        .query_with_threshold(&[1.0, 0.0], 10, 0.6)
+        .await
+        .unwrap();
+    let ids: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(ids, ["exact", "boundary"]);
+
+    let results = store
+        .query_with_threshold(&[1.0, 0.0], 10, 1.1)
+        .await
+        .unwrap();
+    assert!(results.is_empty());
+}
+
+#[tokio::test]
+async fn test_query_parallel_matches_serial_query() {
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let store = VectorStoreOptions::new()
+        .query_concurrency(2)
+        .build_and_open("/test", object_store)
+        .await
+        .unwrap();
+
+    // Several scoring chunks plus a partial one
+    let items = (0..3000)
+        .map(|i| {
+            let angle = i as f32 * 0.013;
+            (format!("v{i}"), vec![angle.cos(), angle.sin(), 0.5], None)
+        })
+        .collect();
+    store
+        .batch_add(items, DuplicatePolicy::Error)
+        .await
+        .unwrap();
+
+    let query = [0.3, 0.9, 0.5];
+    for k in [1, 10, 5000] {
+        let serial = store.query(&query, k).await.unwrap();
+        let parallel = store.query_parallel(&query, k).await.unwrap();
+        let ids = |results: &[smolpuff::QueryResult]| {
+            results.iter().map(|r| r.id.clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&parallel), ids(&serial));
+    }
+}
+
+#[tokio::test]
+async fn test_query_parallel_scores_with_the_namespace_metric() {
+    let store = open_euclidean_default().await;
+    let query = [2.0, 0.5];
+    let expected = store.query(&query, 5).await.unwrap();
+    // Nearest first: scored as Euclidean distances, not cosine similarities
+    assert!(expected.windows(2).all(|w| w[0].score <= w[1].score));
+    assert_eq!(store.query_parallel(&query, 5).await.unwrap(), expected);
+}
+
+#[tokio::test]
+async fn test_cached_norm_scores_match_from_scratch_cosine() {
+    use smolpuff::distance::cosine_similarity;
+
+    let store = open_store().await;
+    let query = [0.2, -0.7, 1.5];
+    let vectors = [
+        ("a", vec![1.0, 2.0, 3.0]),
+        ("b", vec![-4.0, 0.5, 2.0]),
+        ("c", vec![0.3, 0.3, -0.3]),
+    ];
+    for (id, vector) in &vectors {
+        store.add(id, vector.clone(), None).await.unwrap();
+    }
+    let (id, vector) = ("d", vec![2.0, -1.0, 0.0]);
+    store
+        .batch_add(
+            vec![(id.to_string(), vector.clone(), None)],
+            DuplicatePolicy::Error,
+        )
+        .await
+        .unwrap();
+
+    let check = |results: Vec<smolpuff::QueryResult>, expected: &[(&str, Vec<f32>)]| {
+        assert_eq!(results.len(), expected.len());
+        for result in results {
+            let (_, vector) = expected.iter().find(|(id, _)| *id == result.id).unwrap();
+            assert!((result.score - cosine_similarity(&query, vector)).abs() < 1e-6);
+        }
+    };
+    let mut expected = vectors.to_vec();
+    expected.push((id, vector));
+    check(store.query(&query, 10).await.unwrap(), &expected);
+
+    // Rewriting vectors in place must refresh their cached norms
+    store.normalize_all().await.unwrap();
+    let raw: Vec<u8> = [9.0f32, 9.0, 9.0]
+        .iter()
+        .flat_map(|f| f.to_le_bytes())
+        .collect();
+    store.add_raw("c", raw, true).await.unwrap();
+    let expected: Vec<(&str, Vec<f32>)> = expected
+        .into_iter()
+        .map(|(id, v)| match id {
+            "c" => (id, vec![9.0, 9.0, 9.0]),
+            _ => {
+                let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+                (id, v.iter().map(|x| x / norm).collect())
+            }
+        })
+        .collect();
+    check(store.query(&query, 10).await.unwrap(), &expected);
+}
+
+#[tokio::test]
+async fn test_tied_scores_order_by_id_and_nan_ranks_last() {
+    let store = open_store().await;
+
+    // Written out of id order so scan order can't be what sorts them
+    for id in ["c", "a", "b"] {
+        store.add(id, vec![1.0, 0.0], None).await.unwrap();
+    }
+    let nan: Vec<u8> = [f32::NAN, 0.0]
+        .iter()
+        .flat_map(|f| f.to_le_bytes())
+        .collect();
+    store.add_raw("0-nan", nan, true).await.unwrap();
+    store.add("z", vec![0.0, 1.0], None).await.unwrap();
+
+    let ids =
+        |results: Vec<smolpuff::QueryResult>| results.into_iter().map(|r| r.id).collect::<Vec<_>>();
+    for _ in 0..3 {
+        assert_eq!(ids(store.query(&[1.0, 0.0], 2).await.unwrap()), ["a", "b"]);
+        assert_eq!(
+            ids(store.query(&[1.0, 0.0], 10).await.unwrap()),
+            ["a", "b", "c", "z", "0-nan"]
+        );
+        assert_eq!(
+            ids(store.query_parallel(&[1.0, 0.0], 2).await.unwrap()),
+            ["a", "b"]
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_count_tracks_adds_overwrites_and_deletes() {
+    let store = open_store().await;
+    assert_eq!(store.count().await.unwrap(), 0);
+    assert!(store.is_empty().await.unwrap());
+
+    for id in ["a", "b", "c"] {
+        store.add(id, vec![1.0, 0.0], None).await.unwrap();
+    }
+    // Overwriting an id doesn't add a record
+    store.add("a", vec![0.0, 1.0], None).await.unwrap();
+    assert_eq!(store.count().await.unwrap(), 3);
+
+    store.delete("b").await.unwrap();
+    store.delete("missing").await.unwrap();
+    assert_eq!(store.count().await.unwrap(), 2);
+    assert!(!store.is_empty().await.unwrap());
+
+    store.delete("a").await.unwrap();
+    store.delete("c").await.unwrap();
+    assert!(store.is_empty().await.unwrap());
+}
+
+#[tokio::test]
+async fn test_add_reports_whether_it_replaced_a_record() {
+    let store = open_store().await;
+
+    assert!(!store.add("a", vec![1.0, 0.0], None).await.unwrap());
+    assert!(!store.add("b", vec![0.0, 1.0], None).await.unwrap());
+    assert!(store.add("a", vec![0.5, 0.5], None).await.unwrap());
+
+    store.delete("a").await.unwrap();
+    assert!(!store.add("a", vec![1.0, 0.0], None).await.unwrap());
+
+    store.create_namespace("docs", 2, "cosine").await.unwrap();
+    assert!(
+        !store
+            .upsert("docs", "a", vec![1.0, 0.0], None)
+            .await
+            .unwrap()
+    );
+    assert!(
+        store
+            .upsert("docs", "a", vec![1.0, 0.0], None)
+            .await
+            .unwrap()
+    );
+    assert_eq!(
+        store.get_namespace("docs").await.unwrap().approx_row_count,
+        1
+    );
+}
+
+#[test]
+fn test_query_result_round_trips_through_json() {
+    let result = smolpuff::QueryResult {
+        id: "a".to_string(),
+        score: 0.5,
+        metadata: Some(serde_json::json!({ "category": "A", "tags": ["x", "y"] })),
+        vector: None,
+    };
+    let json = serde_json::to_value(&result).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "id": "a",
+            "score": 0.5,
+            "metadata": { "category": "A", "tags": ["x", "y"] }
+        })
+    );
+    let back: smolpuff::QueryResult = serde_json::from_value(json).unwrap();
+    assert_eq!(back, result);
+
+    let bare = smolpuff::QueryResult {
+        metadata: None,
+        ..result
+    };
+    let back: smolpuff::QueryResult =
+        serde_json::from_str(&serde_json::to_string(&bare).unwrap()).unwrap();
+    assert_eq!(back, bare);
+}
+
+#[tokio::test]
+async fn test_collections_do_not_see_each_others_records() {
+    let store = open_store().await;
+
+    // "prod" is a prefix of "products"; neither may leak into the other
+    let products = store.collection("products");
+    let prod = store.collection("prod");
+    products.add("p1", vec![1.0, 0.0], None).await.unwrap();
+    products.add("p2", vec![0.9, 0.1], None).await.unwrap();
+    prod.add("x1", vec![1.0, 0.0], None).await.unwrap();
+    store.add("d1", vec![1.0, 0.0], None).await.unwrap();
+
+    let ids = |results: Vec<smolpuff::QueryResult>| {
+        let mut ids: Vec<String> = results.into_iter().map(|r| r.id).collect();
+        ids.sort();
+        ids
+    };
+    assert_eq!(
+        ids(products.query(&[1.0, 0.0], 10).await.unwrap()),
+        ["p1", "p2"]
+    );
+    assert_eq!(ids(prod.query(&[1.0, 0.0], 10).await.unwrap()), ["x1"]);
+    assert_eq!(ids(store.query(&[1.0, 0.0], 10).await.unwrap()), ["d1"]);
+    assert!(
+        store
+            .collection("reviews")
+            .query(&[1.0, 0.0], 10)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    let err = products.add("p3", vec![1.0], None).await.unwrap_err();
+    assert!(matches!(err, VectorStoreError::DimensionMismatch { .. }));
+}
+
+#[tokio::test]
+async fn test_query_stream_yields_same_matches_as_query() {
+    use futures::StreamExt;
+
+    let store = open_store().await;
+    let items = (0..50)
+        .map(|i| {
+            let angle = i as f32 * 0.1;
+            let metadata = serde_json::json!({ "i": i });
+            (
+                format!("v{i:02}"),
+                vec![angle.cos(), angle.sin()],
+                Some(metadata),
+            )
+        })
+        .collect();
+    store
+        .batch_add(items, DuplicatePolicy::Error)
+        .await
+        .unwrap();
+
+    let query = [1.0, 0.0];
+    let mut streamed: Vec<smolpuff::QueryResult> = store
+        .query_stream(&query, 0.5)
+        .map(|r| r.unwrap())
+        .collect()
+        .await;
+    streamed.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let mut expected: Vec<smolpuff::QueryResult> = store
+        .query(&query, 50)
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|r| r.score >= 0.5)
+        .collect();
+    expected.sort_by(|a, b| a.id.cmp(&b.id));
+    assert!(!expected.is_empty() && expected.len() < 50);
+
+    assert_eq!(streamed.len(), expected.len());
+    for (s, e) in streamed.iter().zip(&expected) {
+        assert_eq!(s.id, e.id);
+        assert_eq!(s.metadata, e.metadata);
+        assert!((s.score - e.score).abs() < 1e-6);
+    }
+}
+
+#[tokio::test]
+async fn test_query_stream_scores_with_the_namespace_metric() {
+    use futures::StreamExt;
+
+    let store = open_euclidean_default().await;
+    let query = [2.0, 0.5];
+    // A distance, so the stream keeps records no further than this
+    let mut streamed: Vec<smolpuff::QueryResult> = store
+        .query_stream(&query, 1.5)
+        .map(|r| r.unwrap())
+        .collect()
+        .await;
+    streamed.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let mut expected: Vec<smolpuff::QueryResult> = store
+        .query(&query, 40)
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|r| r.score <= 1.5)
+        .collect();
+    expected.sort_by(|a, b| a.id.cmp(&b.id));
+    assert!(!expected.is_empty() && expected.len() < 40);
+    assert_eq!(streamed, expected);
+}
+
+#[tokio::test]
+async fn test_query_by_id_excludes_the_source_record() {
+    let store = open_store().await;
+    let items = (0..6)
+        .map(|i| {
+            let angle = i as f32 * 0.2;
+            (format!("v{i}"), vec![angle.cos(), angle.sin()], None)
+        })
+        .collect();
+    store
+        .batch_add(items, DuplicatePolicy::Error)
+        .await
+        .unwrap();
+
+    let results = store.query_by_id("v2", 3).await.unwrap();
+    let mut ids: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(ids.len(), 3);
+    assert!(!ids.contains(&"v2"));
+    ids[..2].sort();
+    assert_eq!(&ids[..2], ["v1", "v3"]);
+
+    // Asking for everything returns every other record
+    assert_eq!(store.query_by_id("v2", 10).await.unwrap().len(), 5);
+
+    let err = store.query_by_id("missing", 3).await.unwrap_err();
+    assert!(matches!(err, VectorStoreError::NotFound(_)));
+}
+
+#[tokio::test]
+async fn test_query_by_id_scores_with_the_namespace_metric() {
+    let store = open_euclidean_default().await;
+    let source = store.get("v10").await.unwrap().unwrap();
+    let mut expected = store.query(&source.vector, 6).await.unwrap();
+    expected.retain(|r| r.id != "v10");
+    assert_eq!(store.query_by_id("v10", 5).await.unwrap(), expected);
+}
+
+#[tokio::test]
+async fn test_non_finite_and_empty_vectors_are_rejected() {
+    let store = open_store().await;
+
+    for vector in [vec![1.0, f32::NAN], vec![f32::INFINITY, 0.0], vec![]] {
+        let err = store.add("bad", vector.clone(), None).await.unwrap_err();
+        assert!(matches!(err, VectorStoreError::InvalidVector(_)), "{err}");
+
+        let record = VectorRecord {
+            id: "bad".to_string(),
+            vector,
+            metadata: None,
+        };
+        let err = store.add_batch(vec![record]).await.unwrap_err();
+        assert!(matches!(err, VectorStoreError::InvalidVector(_)), "{err}");
+    }
+    assert!(store.is_empty().await.unwrap());
+
+    // Nothing was pinned by the rejected writes
+    store.add("good", vec![1.0, 0.0, 0.0], None).await.unwrap();
+    assert!(store.contains("good").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_options_combine_metric_and_expected_dim() {
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let store = VectorStoreOptions::new()
+        .metric(DistanceMetric::Euclidean)
+        .expected_dim(3)
+        .build_and_open("/test", object_store.clone())
+        .await
+        .unwrap();
+    assert_eq!(store.metric(), DistanceMetric::Euclidean);
+
+    let err = store.add("short", vec![1.0, 0.0], None).await.unwrap_err();
+    assert!(matches!(
+        err,
+        VectorStoreError::DimensionMismatch {
+            expected: 3,
+            got: 2
+        }
+    ));
+    let err = store.query(&[1.0; 4], 1).await.unwrap_err();
+    assert!(matches!(err, VectorStoreError::DimensionMismatch { .. }));
+
+    store.add("near", vec![1.0, 0.0, 0.0], None).await.unwrap();
+    store.add("far", vec![5.0, 5.0, 5.0], None).await.unwrap();
+    let results = store.query(&[1.0, 0.0, 0.0], 2).await.unwrap();
+    assert_eq!(results[0].id, "near");
+    assert_eq!(results[0].score, 0.0);
+    store.close().await.unwrap();
+
+    // A tighter max_dimension still applies alongside the other knobs
+    let store = VectorStoreOptions::new()
+        .expected_dim(3)
+        .max_dimension(2)
+        .batch_size(1)
+        .build_and_open("/other", object_store)
+        .await
+        .unwrap();
+    assert_eq!(store.metric(), DistanceMetric::Cosine);
+    let err = store.add("v", vec![1.0, 0.0, 0.0], None).await.unwrap_err();
+    assert!(matches!(err, VectorStoreError::DimensionTooLarge { .. }));
+}
+
+#[tokio::test]
+async fn test_open_in_memory() {
+    let store = VectorStore::open_in_memory().await.unwrap();
+    store.add("v", vec![1.0, 0.0], None).await.unwrap();
+    assert_eq!(store.count().await.unwrap(), 1);
+}
+
+#[test]
+fn test_object_store_errors_convert() {
+    let err: VectorStoreError = object_store::Error::NotImplemented.into();
+    assert!(matches!(err, VectorStoreError::ObjectStore(_)));
+    assert!(err.to_string().starts_with("Object store error"));
+}
+
+#[tokio::test]
+async fn test_search_with_vectors_returns_stored_vectors() {
+    let store = open_store().await;
+    let inserted = [
+        ("a", vec![0.1, -2.5, 3.75]),
+        ("b", vec![1.0e-7, 4.0, f32::MAX]),
+        ("c", vec![-1.0, 0.0, 0.5]),
+    ];
+    for (id, vector) in &inserted {
+        store.add(id, vector.clone(), None).await.unwrap();
+    }
+
+    let found = store
+        .search(vec![1.0, 1.0, 1.0])
+        .k(3)
+        .with_vectors()
+        .run()
+        .await
+        .unwrap();
+    assert_eq!(found.results.len(), 3);
+    for result in &found.results {
+        let (_, expected) = inserted.iter().find(|(id, _)| *id == result.id).unwrap();
+        assert_eq!(result.vector.as_ref(), Some(expected));
+    }
+
+    // Left out unless asked for, including from the JSON
+    let plain = store.query(&[1.0, 1.0, 1.0], 3).await.unwrap();
+    assert!(plain.iter().all(|r| r.vector.is_none()));
+    let json = serde_json::to_value(&plain[0]).unwrap();
+    assert!(json.get("vector").is_none());
+}
+
+#[tokio::test]
+async fn test_list_ids_returns_every_id() {
+    let store = open_store().await;
+    assert!(store.list_ids().await.unwrap().is_empty());
+
+    for id in ["b", "a:1", "c d", "a"] {
+        store.add(id, vec![1.0, 0.0], None).await.unwrap();
+    }
+    store.add("a", vec![0.0, 1.0], None).await.unwrap();
+    store.delete("b").await.unwrap();
+
+    let mut ids = store.list_ids().await.unwrap();
+    ids.sort();
+    assert_eq!(ids, ["a", "a:1", "c d"]);
+}
+
+#[tokio::test]
+async fn test_get_many_aligns_with_input() {
+    let store = open_store().await;
+    assert!(store.get_many(&[]).await.unwrap().is_empty());
+
+    store.add("a", vec![1.0, 0.0], None).await.unwrap();
+    let metadata = serde_json::json!({ "lang": "en" });
+    store
+        .add("b", vec![0.0, 1.0], Some(metadata.clone()))
+        .await
+        .unwrap();
+
+    let records = store.get_many(&["b", "missing", "a", "b"]).await.unwrap();
+    assert_eq!(records.len(), 4);
+    let b = records[0].as_ref().unwrap();
+    assert_eq!(b.id, "b");
+    assert_eq!(b.metadata, Some(metadata));
+    assert!(records[1].is_none());
+    assert_eq!(records[2].as_ref().unwrap().vector, vec![1.0, 0.0]);
+    assert_eq!(records[3], records[0]);
+}
+
+#[tokio::test]
+async fn test_manhattan_nearest_neighbor_differs_from_cosine_and_euclidean() {
+    let store = open_store().await;
+
+    // Against [1, 0]: "aligned" wins on angle, "diagonal" on straight-line
+    // distance, and "upright" on summed per-axis distance
+    store.add("aligned", vec![10.0, 0.5], None).await.unwrap();
+    store.add("diagonal", vec![2.0, 1.0], None).await.unwrap();
+    store.add("upright", vec![1.0, 1.6], None).await.unwrap();
+
+    let nearest = |metric| {
+        let store = &store;
+        async move {
+            let results = store
+                .search(vec![1.0, 0.0])
+                .metric(metric)
+                .k(3)
+                .run()
+                .await
+                .unwrap()
+                .results;
+            (results[0].id.clone(), results[0].score)
+        }
+    };
+    assert_eq!(nearest(DistanceMetric::Cosine).await.0, "aligned");
+    assert_eq!(nearest(DistanceMetric::Euclidean).await.0, "diagonal");
+    let (id, score) = nearest(DistanceMetric::Manhattan).await;
+    assert_eq!(id, "upright");
+    assert!((score - 1.6).abs() < 1e-6);
+
+    // Like Euclidean, min_score is an upper bound on the distance
+    let close = store
+        .search(vec![1.0, 0.0])
+        .metric(DistanceMetric::Manhattan)
+        .min_score(2.0)
+        .run()
+        .await
+        .unwrap()
+        .results;
+    let ids: Vec<&str> = close.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(ids, ["upright", "diagonal"]);
+}
+
+/// Distance along the first axis only, ignoring every other component
+#[derive(Debug)]
+struct FirstAxisGap;
+
+impl smolpuff::Distance for FirstAxisGap {
+    fn score(&self, a: &[f32], b: &[f32]) -> f32 {
+        (a[0] - b[0]).abs()
+    }
+
+    fn higher_is_better(&self) -> bool {
+        false
+    }
+}
+
+#[tokio::test]
+async fn test_custom_distance_scores_queries() {
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let store = VectorStoreOptions::new()
+        .distance(FirstAxisGap)
+        .build_and_open("/test", object_store)
+        .await
+        .unwrap();
+
+    store.add("same_dir", vec![5.0, 0.0], None).await.unwrap();
+    store.add("near_x", vec![1.25, 9.0], None).await.unwrap();
+    store.add("mid_x", vec![2.0, -3.0], None).await.unwrap();
+
+    // Cosine would pick "same_dir"; the custom distance keeps the smallest gaps
+    let results = store.query(&[1.0, 0.0], 2).await.unwrap();
+    let ids: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(ids, ["near_x", "mid_x"]);
+    assert_eq!(results[0].score, 0.25);
+}
+
+#[tokio::test]
+async fn test_custom_distance_ranks_the_same_on_every_query_path() {
+    use futures::StreamExt;
+
+    let store = VectorStoreOptions::new()
+        .distance(FirstAxisGap)
+        .build_and_open("/test", Arc::new(InMemory::new()))
+        .await
+        .unwrap();
+    let items = vec![
+        (
+            "same_dir".to_string(),
+            vec![5.0, 0.0],
+            Some(serde_json::json!({ "n": 1 })),
+        ),
+        (
+            "near_x".to_string(),
+            vec![1.25, 9.0],
+            Some(serde_json::json!({ "n": 2 })),
+        ),
+        (
+            "mid_x".to_string(),
+            vec![2.0, -3.0],
+            Some(serde_json::json!({ "n": 3 })),
+        ),
+        (
+            "query".to_string(),
+            vec![1.0, 0.0],
+            Some(serde_json::json!({ "n": 0 })),
+        ),
+    ];
+    store
+        .batch_add(items, DuplicatePolicy::Error)
+        .await
+        .unwrap();
+
+    // Cosine would rank "same_dir" right after the query itself
+    let expected = ["query", "near_x", "mid_x"];
+    let q = [1.0, 0.0];
+    let ids = |results: Vec<smolpuff::store::QueryResult>| -> Vec<String> {
+        results.into_iter().map(|r| r.id).collect()
+    };
+    assert_eq!(ids(store.query(&q, 3).await.unwrap()), expected);
+    assert_eq!(
+        ids(store.query_filtered(&q, 3, |_| true).await.unwrap()),
+        expected
+    );
+    assert_eq!(
+        ids(store.query_with_threshold(&q, 3, 2.0).await.unwrap()),
+        expected
+    );
+    assert_eq!(
+        ids(store.query_page(&q, 1, 2).await.unwrap()),
+        expected[1..]
+    );
+    let all = HashSet::new();
+    assert_eq!(
+        ids(store.query_with_ids(&q, 3, None, &all).await.unwrap()),
+        expected
+    );
+    assert_eq!(ids(store.query_mmr(&q, 3, 1.0).await.unwrap()), expected);
+    assert_eq!(ids(store.query_parallel(&q, 3).await.unwrap()), expected);
+    assert_eq!(
+        ids(store.query_with_tie_break(&q, 3, None).await.unwrap()),
+        expected
+    );
+    assert_eq!(
+        ids(store.query_with_tie_break(&q, 3, Some("n")).await.unwrap()),
+        expected
+    );
+    assert_eq!(
+        ids(store.query_by_id("query", 2).await.unwrap()),
+        expected[1..]
+    );
+    let lazy = store.query_lazy(&q, 3).await.unwrap();
+    assert_eq!(
+        lazy.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(),
+        expected
+    );
+
+    // Within a gap of 1.0 on the first axis
+    let mut streamed: Vec<String> = store
+        .query_stream(&q, 1.0)
+        .map(|r| r.unwrap().id)
+        .collect()
+        .await;
+    streamed.sort();
+    assert_eq!(streamed, ["mid_x", "near_x", "query"]);
+
+    // A metric picked for the query still overrides the custom distance
+    let cosine = store
+        .query_with_metric(&q, 2, DistanceMetric::Cosine)
+        .await
+        .unwrap();
+    assert_eq!(ids(cosine)[1], "same_dir");
+}
+
+#[tokio::test]
+async fn test_info_tracks_dimension_and_count() {
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let store = VectorStore::open("/test", object_store.clone())
+        .await
+        .unwrap();
+
+    let info = store.info().await.unwrap();
+    assert_eq!(info.dimension, None);
+    assert_eq!(info.record_count, 0);
+
+    for i in 0..4 {
+        store
+            .add(&format!("v{i}"), vec![i as f32, 1.0, 0.0], None)
+            .await
+            .unwrap();
+    }
+    store.add("v0", vec![0.0, 0.0, 1.0], None).await.unwrap();
+    store.delete("v3").await.unwrap();
+
+    let info = store.info().await.unwrap();
+    assert_eq!(info.dimension, Some(3));
+    assert_eq!(info.record_count, 3);
+    assert_eq!(info.metric, DistanceMetric::Cosine);
+    assert_eq!(info.format_version, smolpuff::store::FORMAT_VERSION);
+    store.close().await.unwrap();
+
+    // Persisted, so a reopened store reports the same without scanning
+    let store = VectorStore::open("/test", object_store).await.unwrap();
+    assert_eq!(store.info().await.unwrap(), info);
+}
+
+#[tokio::test]
+async fn test_info_count_ignores_batch_overwrites() {
+    let store = VectorStoreOptions::new()
+        .batch_size(2)
+        .build_and_open("/test", Arc::new(InMemory::new()))
+        .await
+        .unwrap();
+    let record = |id: &str, x: f32| VectorRecord {
+        id: id.to_string(),
+        vector: vec![x, 1.0],
+        metadata: None,
+    };
+    store
+        .add_batch(vec![record("a", 1.0), record("b", 2.0), record("c", 3.0)])
+        .await
+        .unwrap();
+    assert_eq!(store.info().await.unwrap().record_count, 3);
+
+    // Two of these replace records, one of them in a later write batch
+    store
+        .add_batch(vec![record("a", 4.0), record("d", 5.0), record("c", 6.0)])
+        .await
+        .unwrap();
+    store
+        .batch_add(
+            vec![("b".to_string(), vec![7.0, 1.0], None)],
+            DuplicatePolicy::Error,
+        )
+        .await
+        .unwrap();
+    assert_eq!(store.info().await.unwrap().record_count, 4);
+    assert_eq!(store.count().await.unwrap(), 4);
+}
+
+#[tokio::test]
+async fn test_records_of_every_format_version_decode() {
+    let store = open_store().await;
+
+    // v1, as written today: version byte, magic, little-endian f32s
+    store.add("v1", vec![1.0, 0.0], None).await.unwrap();
+    let v1 = store.get_raw("v1").await.unwrap().unwrap();
+    assert_eq!(&v1[..4], [1, 0x50, 0xFF, 0x7F]);
+
+    // v0: the same header with version 0, then a JSON array
+    let mut v0 = vec![0, 0x50, 0xFF, 0x7F];
+    v0.extend_from_slice(b"[0.6,0.8]");
+    store.add_raw("v0", v0, true).await.unwrap();
+
+    // Records written before the header existed are bare f32s
+    let bare: Vec<u8> = [0.0f32, 1.0].iter().flat_map(|f| f.to_le_bytes()).collect();
+    store.add_raw("bare", bare, true).await.unwrap();
+
+    assert_eq!(store.get("v0").await.unwrap().unwrap().vector, [0.6, 0.8]);
+    assert_eq!(store.get("v1").await.unwrap().unwrap().vector, [1.0, 0.0]);
+    assert_eq!(store.get("bare").await.unwrap().unwrap().vector, [0.0, 1.0]);
+    let results = store.query(&[1.0, 0.0], 3).await.unwrap();
+    let ids: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(ids, ["v1", "v0", "bare"]);
+
+    let future = vec![9, 0x50, 0xFF, 0x7F, 0, 0, 0, 0];
+    let err = store.add_raw("v9", future.clone(), true).await.unwrap_err();
+    assert!(matches!(err, VectorStoreError::InvalidRequest(_)));
+    store.add_raw("v9", future, false).await.unwrap();
+    let err = store.get("v9").await.unwrap_err();
+    assert!(matches!(err, VectorStoreError::UnsupportedVersion(9)));
+}
+
+#[tokio::test]
+async fn test_clear_removes_every_record() {
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let store = VectorStoreOptions::new()
+        .batch_size(2)
+        .build_and_open("/test", object_store)
+        .await
+        .unwrap();
+    assert_eq!(store.clear().await.unwrap(), 0);
+
+    for i in 0..5 {
+        let metadata = serde_json::json!({ "i": i });
+        store
+            .add(&format!("v{i}"), vec![i as f32, 1.0], Some(metadata))
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(store.clear().await.unwrap(), 5);
+    assert_eq!(store.count().await.unwrap(), 0);
+    assert!(store.query(&[1.0, 1.0], 10).await.unwrap().is_empty());
+    assert!(store.get("v1").await.unwrap().is_none());
+
+    let info = store.info().await.unwrap();
+    assert_eq!(info.record_count, 0);
+    assert_eq!(info.dimension, Some(2));
+
+    // The dimension survives, so the store is ready for the same data again
+    let err = store.add("v", vec![1.0; 3], None).await.unwrap_err();
+    assert!(matches!(err, VectorStoreError::DimensionMismatch { .. }));
+    store.add("v", vec![1.0, 0.0], None).await.unwrap();
+    assert_eq!(store.count().await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_query_ann_recall_against_exact() {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(5);
+    let dim = 32;
+    let mut random_vector = || -> Vec<f32> { (0..dim).map(|_| rng.gen_range(-1.0..1.0)).collect() };
+    let items: Vec<_> = (0..2000)
+        .map(|i| (format!("v{i:04}"), random_vector(), None))
+        .collect();
+
+    // Records written before opening are indexed on open
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let store = VectorStore::open("/test", object_store.clone())
+        .await
+        .unwrap();
+    store
+        .batch_add(items, DuplicatePolicy::Error)
+        .await
+        .unwrap();
+    let queries: Vec<Vec<f32>> = (0..20).map(|_| random_vector()).collect();
+
+    // Without an index, query_ann is an exact query
+    assert_eq!(
+        store.query_ann(&queries[0], 10, 10).await.unwrap(),
+        store.query(&queries[0], 10).await.unwrap()
+    );
+    store.close().await.unwrap();
+
+    let store = VectorStoreOptions::new()
+        .hnsw(smolpuff::HnswParams {
+            m: 12,
+            ef_construction: 64,
+        })
+        .build_and_open("/test", object_store)
+        .await
+        .unwrap();
+    let mut recall = 0.0;
+    for query in &queries {
+        let truth = store.query_exact(query, 10).await.unwrap();
+        let approx = store.query_ann(query, 10, 64).await.unwrap();
+        assert_eq!(approx.len(), 10);
+        assert!(approx.windows(2).all(|w| w[0].score >= w[1].score));
+        recall += smolpuff::recall_at_k(&truth, &approx) / queries.len() as f64;
+    }
+    assert!(recall >= 0.9, "recall@10 was {recall}");
+
+    // Writes after opening keep the index current
+    store.add("exact", queries[1].clone(), None).await.unwrap();
+    let found = store.query_ann(&queries[1], 3, 64).await.unwrap();
+    assert_eq!(found[0].id, "exact");
+    store.delete("exact").await.unwrap();
+    let found = store.query_ann(&queries[1], 3, 64).await.unwrap();
+    assert!(found.iter().all(|r| r.id != "exact"));
+}
+
+#[tokio::test]
+async fn test_query_reads_metadata_only_for_top_k() {
+    let store = open_store().await;
+    let blob = "x".repeat(10_000);
+    let items = (0..50)
+        .map(|i| {
+            let metadata = serde_json::json!({ "blob": blob });
+            (format!("v{i:02}"), vec![i as f32, 1.0], Some(metadata))
+        })
+        .collect();
+    store
+        .batch_add(items, DuplicatePolicy::Error)
+        .await
+        .unwrap();
+
+    let (results, profile) = store.query_profiled(&[1.0, 0.0], 2).await.unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|r| r.metadata.is_some()));
+    assert_eq!(profile.records_scanned, 50);
+    // Two metadata blobs were read, not fifty
+    assert!(profile.bytes_read > 2 * 10_000);
+    assert!(profile.bytes_read < 3 * 10_000, "{}", profile.bytes_read);
+}
+
+#[tokio::test]
+async fn test_query_mmr_suppresses_near_duplicates() {
+    let store = open_store().await;
+
+    // Three near-copies right on the query, and two distinct, slightly
+    // less relevant records pointing elsewhere
+    store.add("dup1", vec![1.0, 0.0, 0.0], None).await.unwrap();
+    store.add("dup2", vec![1.0, 0.01, 0.0], None).await.unwrap();
+    store.add("dup3", vec![1.0, 0.0, 0.01], None).await.unwrap();
+    store.add("side", vec![0.8, 0.6, 0.0], None).await.unwrap();
+    store.add("other", vec![0.7, 0.0, 0.7], None).await.unwrap();
+
+    let ids = |results: &[smolpuff::QueryResult]| {
+        results.iter().map(|r| r.id.clone()).collect::<Vec<_>>()
+    };
+    let query = [1.0, 0.0, 0.0];
+    let plain = store.query(&query, 3).await.unwrap();
+    assert_eq!(ids(&plain), ["dup1", "dup2", "dup3"]);
+
+    // lambda = 1 is plain top-k
+    let relevant = store.query_mmr(&query, 3, 1.0).await.unwrap();
+    assert_eq!(ids(&relevant), ids(&plain));
+    assert_eq!(relevant[0].score, plain[0].score);
+
+    let diverse = store.query_mmr(&query, 3, 0.3).await.unwrap();
+    assert_eq!(ids(&diverse), ["dup1", "other", "side"]);
+    assert!(diverse.iter().all(|r| r.vector.is_none()));
+}
+
+#[tokio::test]
+async fn test_query_mmr_scores_with_the_namespace_metric() {
+    let store = open_euclidean_default().await;
+    let query = [2.0, 0.5];
+    let plain = store.query(&query, 5).await.unwrap();
+    assert_eq!(store.query_mmr(&query, 5, 1.0).await.unwrap(), plain);
+
+    // Diversifying still starts from the nearest match, then spreads out
+    let diverse = store.query_mmr(&query, 5, 0.3).await.unwrap();
+    assert_eq!(diverse[0], plain[0]);
+    assert_ne!(diverse[1].id, plain[1].id);
+}
+
+#[tokio::test]
+async fn test_query_with_ids_allow_and_deny() {
+    let store = open_store().await;
+
+    for i in 0..6 {
+        let vector = vec![1.0, i as f32 * 0.1, 0.0];
+        store.add(&format!("v{i}"), vector, None).await.unwrap();
+    }
+
+    let set = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<HashSet<_>>();
+    let ids = |results: &[smolpuff::QueryResult]| {
+        results.iter().map(|r| r.id.clone()).collect::<Vec<_>>()
+    };
+    let query = [1.0, 0.0, 0.0];
+
+    // Denied ids give up their places to the next best eligible records
+    let denied = store
+        .query_with_ids(&query, 2, None, &set(&["v0", "v1"]))
+        .await
+        .unwrap();
+    assert_eq!(ids(&denied), ["v2", "v3"]);
+
+    // The allow-list limits the scan, so the best allowed records come back
+    // even though better ones exist outside it
+    let allowed = set(&["v3", "v4", "v5"]);
+    let only = store
+        .query_with_ids(&query, 2, Some(&allowed), &HashSet::new())
+        .await
+        .unwrap();
+    assert_eq!(ids(&only), ["v3", "v4"]);
+
+    // Deny wins over allow
+    let both = store
+        .query_with_ids(&query, 2, Some(&allowed), &set(&["v3"]))
+        .await
+        .unwrap();
+    assert_eq!(ids(&both), ["v4", "v5"]);
+
+    let none = store
+        .query_with_ids(&query, 2, Some(&HashSet::new()), &HashSet::new())
+        .await
+        .unwrap();
+    assert!(none.is_empty());
+}
+
+#[tokio::test]
+async fn test_query_sparse_ranks_by_shared_terms() {
+    let store = open_store().await;
+
+    // Term ids: 0 = "rust", 1 = "vector", 2 = "database", 3 = "cooking"
+    let docs = [
+        ("rust-db", vec![(0, 1.5), (2, 1.0)]),
+        ("vector-db", vec![(1, 2.0), (2, 1.2)]),
+        ("recipes", vec![(3, 2.5)]),
+    ];
+    for (id, terms) in docs {
+        let metadata = serde_json::json!({ "title": id });
+        store
+            .add_sparse(id, SparseVector::from_iter(terms), Some(metadata))
+            .await
+            .unwrap();
+    }
+    // A dense record with the same id as a sparse one doesn't collide
+    store.add("rust-db", vec![1.0, 0.0], None).await.unwrap();
+
+    let query = SparseVector::from([(1, 1.0), (2, 1.0)]);
+    let results = store.query_sparse(&query, 3).await.unwrap();
+    let ranked: Vec<_> = results.iter().map(|r| (r.id.as_str(), r.score)).collect();
+    assert_eq!(
+        ranked,
+        [("vector-db", 3.2), ("rust-db", 1.0), ("recipes", 0.0)]
+    );
+    assert_eq!(
+        results[0].metadata,
+        Some(serde_json::json!({ "title": "vector-db" }))
+    );
+
+    assert_eq!(store.count().await.unwrap(), 1);
+    assert_eq!(store.query(&[1.0, 0.0], 10).await.unwrap().len(), 1);
+
+    let bad = SparseVector::from([(0, f32::NAN)]);
+    assert!(matches!(
+        store.add_sparse("bad", bad, None).await,
+        Err(VectorStoreError::InvalidVector(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_query_documents_scores_by_best_chunk() {
+    let store = open_store().await;
+
+    // Only the second of "manual"'s three chunks is about the query's topic
+    let manual = vec![
+        vec![0.0, 1.0, 0.0],
+        vec![0.95, 0.05, 0.0],
+        vec![0.0, 0.0, 1.0],
+    ];
+    store
+        .add_document("manual", manual, Some(serde_json::json!({ "pages": 3 })))
+        .await
+        .unwrap();
+    store
+        .add_document("faq", vec![vec![0.6, 0.8, 0.0], vec![0.5, 0.0, 0.85]], None)
+        .await
+        .unwrap();
+    store
+        .add_document("blog", vec![vec![0.0, 0.6, 0.8]], None)
+        .await
+        .unwrap();
+
+    let results = store.query_documents(&[1.0, 0.0, 0.0], 10).await.unwrap();
+    let ids: Vec<_> = results.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(ids, ["manual", "faq", "blog"]);
+
+    let expected = 0.95 / (0.95f32 * 0.95 + 0.05 * 0.05).sqrt();
+    assert!((results[0].score - expected).abs() < 1e-4);
+    assert!((results[1].score - 0.6).abs() < 1e-4);
+    assert_eq!(results[0].metadata, Some(serde_json::json!({ "pages": 3 })));
+
+    // Re-adding a document drops chunks it no longer has
+    store
+        .add_document("manual", vec![vec![0.0, 1.0, 0.0]], None)
+        .await
+        .unwrap();
+    let results = store.query_documents(&[1.0, 0.0, 0.0], 1).await.unwrap();
+    assert_eq!(results[0].id, "faq");
+
+    // Chunks are kept apart from plain records
+    assert_eq!(store.count().await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_query_documents_scores_with_the_namespace_metric() {
+    let store = open_store().await;
+    store
+        .create_namespace("_default", 2, "euclidean")
+        .await
+        .unwrap();
+    // "long" points the query's way but is far off; "near" is close
+    store
+        .add_document("long", vec![vec![9.0, 0.0], vec![0.0, 9.0]], None)
+        .await
+        .unwrap();
+    store
+        .add_document("near", vec![vec![1.0, 0.5], vec![-3.0, 1.0]], None)
+        .await
+        .unwrap();
+
+    let results = store.query_documents(&[1.0, 0.0], 2).await.unwrap();
+    let ranked: Vec<_> = results.iter().map(|r| (r.id.as_str(), r.score)).collect();
+    assert_eq!(ranked, [("near", 0.5), ("long", 8.0)]);
+}
+
+#[tokio::test]
+async fn test_query_documents_checks_dimension_and_standardizes_the_query() {
+    let store = open_store().await;
+    store
+        .add_document("early", vec![vec![3.0, 1.0, 2.0]], None)
+        .await
+        .unwrap();
+
+    let err = store.query_documents(&[1.0, 0.0], 10).await.unwrap_err();
+    assert!(matches!(
+        err,
+        VectorStoreError::DimensionMismatch {
+            expected: 3,
+            got: 2
+        }
+    ));
+
+    store
+        .batch_add(
+            vec![
+                ("a".to_string(), vec![1.0, 2.0, 3.0], None),
+                ("b".to_string(), vec![3.0, 2.0, 5.0], None),
+            ],
+            DuplicatePolicy::Error,
+        )
+        .await
+        .unwrap();
+    store.standardize("_default").await.unwrap();
+    store
+        .add_document("late", vec![vec![2.0, 3.0, 3.0]], None)
+        .await
+        .unwrap();
+
+    // Chunks written before and after standardizing both match their own
+    // raw vector exactly
+    for (id, chunk) in [("early", [3.0, 1.0, 2.0]), ("late", [2.0, 3.0, 3.0])] {
+        let results = store.query_documents(&chunk, 1).await.unwrap();
+        assert_eq!(results[0].id, id);
+        assert!((results[0].score - 1.0).abs() < 1e-5, "{id}");
+    }
+}
+
+#[tokio::test]
+async fn test_add_auto_generates_distinct_ids() {
+    let store = open_store().await;
+    // An explicit id that the counter would otherwise hand out first
+    store
+        .add(&format!("{:020}", 1), vec![0.0, 1.0], None)
+        .await
+        .unwrap();
+
+    let (a, b) = tokio::join!(
+        store.add_auto(vec![1.0, 0.0], Some(serde_json::json!({ "event": "a" }))),
+        store.add_auto(vec![0.9, 0.1], Some(serde_json::json!({ "event": "b" }))),
+    );
+    let (a, b) = (a.unwrap(), b.unwrap());
+    assert_ne!(a, b);
+    assert_ne!(a, format!("{:020}", 1));
+    assert_ne!(b, format!("{:020}", 1));
+
+    let results = store.query(&[1.0, 0.0], 2).await.unwrap();
+    let mut found: Vec<_> = results.iter().map(|r| r.id.clone()).collect();
+    let mut expected = vec![a.clone(), b.clone()];
+    found.sort();
+    expected.sort();
+    assert_eq!(found, expected);
+    assert_eq!(
+        store.get(&a).await.unwrap().unwrap().metadata,
+        Some(serde_json::json!({ "event": "a" }))
+    );
+}
+
+#[tokio::test]
+async fn test_flush_makes_writes_survive_reopen() {
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let store = VectorStore::open("/flushed", object_store.clone())
+        .await
+        .unwrap();
+    store
+        .add("kept", vec![1.0, 0.0], Some(serde_json::json!({ "n": 1 })))
+        .await
+        .unwrap();
+    store.flush().await.unwrap();
+    // Dropped without close, as in a crash right after the checkpoint
+    drop(store);
+
+    let reopened = VectorStore::open("/flushed", object_store).await.unwrap();
+    let record = reopened.get("kept").await.unwrap().unwrap();
+    assert_eq!(record.vector, vec![1.0, 0.0]);
+    assert_eq!(record.metadata, Some(serde_json::json!({ "n": 1 })));
+    reopened.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_flush_memtable_after_churn_keeps_latest_records() {
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let store = VectorStore::open("/churned", object_store.clone())
+        .await
+        .unwrap();
+    let items = |round: f32| -> Vec<(String, Vec<f32>, Option<serde_json::Value>)> {
+        (0..20)
+            .map(|i| (format!("v{i:02}"), vec![round, i as f32], None))
+            .collect()
+    };
+    for round in 0..3 {
+        store.clear().await.unwrap();
+        store
+            .batch_add(items(round as f32), DuplicatePolicy::Error)
+            .await
+            .unwrap();
+        store.flush_memtable().await.unwrap();
+    }
+    store.delete("v00").await.unwrap();
+    store.flush_memtable().await.unwrap();
+    drop(store);
+
+    // Level-0 tables hold every round; only the latest versions come back
+    let reopened = VectorStore::open("/churned", object_store).await.unwrap();
+    assert_eq!(reopened.get("v00").await.unwrap(), None);
+    assert_eq!(
+        reopened.get("v05").await.unwrap().unwrap().vector,
+        [2.0, 5.0]
+    );
+    assert_eq!(reopened.list_ids().await.unwrap().len(), 19);
+    reopened.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_cloned_store_serves_concurrent_queries() {
+    let store = open_store().await;
+    for i in 0..10 {
+        let vector = vec![1.0, i as f32 / 10.0];
+        store.add(&format!("v{i}"), vector, None).await.unwrap();
+    }
+
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let store = store.clone();
+            tokio::spawn(async move { store.query(&[1.0, 0.0], 3).await })
+        })
+        .collect();
+    for handle in handles {
+        let results = handle.await.unwrap().unwrap();
+        let ids: Vec<_> = results.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, ["v0", "v1", "v2"]);
+    }
+
+    // Writes through one handle are visible through the others, and closing
+    // a clone leaves the rest usable
+    let writer = store.clone();
+    writer.add("new", vec![1.0, -0.5], None).await.unwrap();
+    writer.close().await.unwrap();
+    assert!(store.contains("new").await.unwrap());
+    store.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_import_jsonl() {
+    let store = open_store().await;
+
+    let jsonl = r#"{"id": "a", "vector": [1.0, 0.0], "metadata": {"lang": "en"}}
+{"id": "b", "vector": [0.0, 1.0]}
+
+{"id": "c", "vector": [0.7, 0.7], "metadata": null}
+"#;
+    let imported = store.import_jsonl(jsonl.as_bytes()).await.unwrap();
+    assert_eq!(imported, 3);
+    assert_eq!(store.count().await.unwrap(), 3);
+
+    let results = store.query(&[1.0, 0.0], 1).await.unwrap();
+    assert_eq!(results[0].id, "a");
+    assert_eq!(
+        results[0].metadata,
+        Some(serde_json::json!({ "lang": "en" }))
+    );
+
+    let malformed = "{\"id\": \"d\", \"vector\": [1.0, 0.0]}\n{\"id\": \"e\", \"vector\": oops}\n";
+    let err = store.import_jsonl(malformed.as_bytes()).await.unwrap_err();
+    match err {
+        VectorStoreError::InvalidRequest(message) => assert!(message.starts_with("Line 2:")),
+        other => panic!("expected InvalidRequest, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_export_jsonl_round_trips_through_import() {
+    let source = open_store().await;
+    let records = vec![
+        (
+            "a".to_string(),
+            vec![1.0, 0.0, 0.5],
+            Some(serde_json::json!({ "n": 1 })),
+        ),
+        ("b".to_string(), vec![0.2, 0.9, 0.1], None),
+        (
+            "c".to_string(),
+            vec![0.6, 0.6, 0.6],
+            Some(serde_json::json!({ "tags": ["x"] })),
+        ),
+        ("d".to_string(), vec![-0.3, 0.1, 0.9], None),
+    ];
+    source
+        .batch_add(records, DuplicatePolicy::Error)
+        .await
+        .unwrap();
+
+    let mut dump = Vec::new();
+    assert_eq!(source.export_jsonl(&mut dump).await.unwrap(), 4);
+    assert_eq!(dump.iter().filter(|&&b| b == b'\n').count(), 4);
+
+    let target = open_store().await;
+    assert_eq!(target.import_jsonl(dump.as_slice()).await.unwrap(), 4);
+
+    for query in [[1.0, 0.0, 0.0], [0.0, 1.0, 1.0], [-1.0, 0.5, 0.5]] {
+        assert_eq!(
+            target.query(&query, 4).await.unwrap(),
+            source.query(&query, 4).await.unwrap()
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_search_normalizes_scores() {
+    let store = open_store().await;
+    for (id, x) in [("a", 3.0), ("b", 2.0), ("c", 1.0)] {
+        store.add(id, vec![x, 0.0], None).await.unwrap();
+    }
+    let scores =
+        |results: &[smolpuff::QueryResult]| results.iter().map(|r| r.score).collect::<Vec<_>>();
+    let search = || {
+        store
+            .search(vec![1.0, 0.0])
+            .k(3)
+            .metric(DistanceMetric::DotProduct)
+    };
+
+    // Raw dot products unless asked for
+    assert_eq!(
+        scores(&search().run().await.unwrap().results),
+        [3.0, 2.0, 1.0]
+    );
+
+    let min_max = search()
+        .normalize(ScoreNormalization::MinMax)
+        .run()
+        .await
+        .unwrap();
+    assert_eq!(scores(&min_max.results), [1.0, 0.5, 0.0]);
+
+    let softmax = search()
+        .normalize(ScoreNormalization::Softmax)
+        .run()
+        .await
+        .unwrap();
+    let sum = 1.0 + (-1.0f32).exp() + (-2.0f32).exp();
+    let expected = [1.0 / sum, (-1.0f32).exp() / sum, (-2.0f32).exp() / sum];
+    for (got, want) in scores(&softmax.results).iter().zip(expected) {
+        assert!((got - want).abs() < 1e-6);
+    }
+    assert!((scores(&softmax.results).iter().sum::<f32>() - 1.0).abs() < 1e-6);
+
+    // Under a distance the nearest match still gets the top score
+    let nearest = store
+        .search(vec![3.0, 0.0])
+        .k(3)
+        .metric(DistanceMetric::Euclidean)
+        .normalize(ScoreNormalization::MinMax)
+        .run()
+        .await
+        .unwrap();
+    assert_eq!(nearest.results[0].id, "a");
+    assert_eq!(scores(&nearest.results), [1.0, 0.5, 0.0]);
+}
+
+#[tokio::test]
+async fn test_query_pages_concatenate_to_full_query() {
+    let store = open_store().await;
+    let records = (0..45)
+        .map(|i| {
+            let angle = i as f32 * 0.05;
+            (format!("v{i}"), vec![angle.cos(), angle.sin()], None)
+        })
+        .collect();
+    store
+        .batch_add(records, DuplicatePolicy::Error)
+        .await
+        .unwrap();
+
+    let query = [1.0, 0.2];
+    let all = store.query(&query, 45).await.unwrap();
+
+    let mut paged = Vec::new();
+    for page in 0..3 {
+        let results = store.query_page(&query, page * 20, 20).await.unwrap();
+        assert_eq!(results.len(), if page < 2 { 20 } else { 5 });
+        paged.extend(results);
+    }
+    assert_eq!(paged, all);
+    assert!(store.query_page(&query, 45, 20).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_builder_queries_score_with_the_namespace_metric() {
+    let store = open_euclidean_default().await;
+    let query = [2.0, 0.5];
+    let expected = store.query(&query, 12).await.unwrap();
+
+    let mut pages = Vec::new();
+    for page in 0..4 {
+        pages.extend(store.query_page(&query, page * 3, 3).await.unwrap());
+    }
+    assert_eq!(pages, expected);
+
+    let worst_kept = expected[11].score;
+    let within = store
+        .query_with_threshold(&query, 40, worst_kept)
+        .await
+        .unwrap();
+    assert_eq!(within, expected);
+    let even = store
+        .query_filtered(&query, 6, |m| m["i"].as_u64().is_some_and(|i| i % 2 == 0))
+        .await
+        .unwrap();
+    let expected_even: Vec<_> = expected
+        .iter()
+        .filter(|r| r.metadata.as_ref().unwrap()["i"].as_u64().unwrap() % 2 == 0)
+        .take(6)
+        .cloned()
+        .collect();
+    assert_eq!(even, expected_even);
+}
+
+#[cfg(feature = "tracing")]
+#[tokio::test]
+async fn test_query_emits_tracing_spans() {
+    use std::sync::Mutex;
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing_subscriber::Layer;
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+
+    /// Collects `name` for every new span and `field=value` for every
+    /// recorded field
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl Visit for Recorder {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0.lock().unwrap().push(format!("{field}={value:?}"));
+        }
+    }
+
+    impl<S: tracing::Subscriber> Layer<S> for Recorder {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _: &Id, _: Context<'_, S>) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(attrs.metadata().name().to_string());
+            attrs.record(&mut self.clone());
+        }
+
+        fn on_record(&self, _: &Id, values: &Record<'_>, _: Context<'_, S>) {
+            values.record(&mut self.clone());
+        }
+    }
+
+    let recorder = Recorder::default();
+    let _guard =
+        tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()));
+
+    let store = open_store().await;
+    for i in 0..3 {
+        store
+            .add(&format!("v{i}"), vec![1.0, i as f32], None)
+            .await
+            .unwrap();
+    }
+    store.query(&[1.0, 0.0], 2).await.unwrap();
+
+    let seen = recorder.0.lock().unwrap().clone();
+    for expected in ["add", "id=\"v0\"", "query", "k=2", "scan", "record_count=3"] {
+        assert!(
+            seen.iter().any(|s| s == expected),
+            "{expected} not in {seen:?}"
+        );
+    }
+    assert!(seen.iter().any(|s| s.starts_with("scan_duration_us=")));
+}
+
+#[tokio::test]
+async fn test_query_with_stats_counts_every_record() {
+    let store = open_store().await;
+    let records = (0..25)
+        .map(|i| (format!("v{i}"), vec![1.0, i as f32], None))
+        .collect();
+    store
+        .batch_add(records, DuplicatePolicy::Error)
+        .await
+        .unwrap();
+
+    let (results, stats) = store.query_with_stats(&[1.0, 0.0], 5).await.unwrap();
+    assert_eq!(results, store.query(&[1.0, 0.0], 5).await.unwrap());
+    assert_eq!(stats.scanned, store.count().await.unwrap());
+    assert!(stats.elapsed > Duration::ZERO);
+}
+
+#[tokio::test]
+async fn test_squared_euclidean_ranks_like_euclidean() {
+    let store = open_store().await;
+    let records = (0..200)
+        .map(|i| {
+            let x = i as f32;
+            (
+                format!("v{i}"),
+                vec![(x * 0.37).sin(), (x * 0.11).cos(), x / 200.0],
+                None,
+            )
+        })
+        .collect();
+    store
+        .batch_add(records, DuplicatePolicy::Error)
+        .await
+        .unwrap();
+
+    let query = vec![0.2, -0.4, 0.5];
+    let run = |metric| store.search(query.clone()).k(20).metric(metric).run();
+    let exact = run(DistanceMetric::Euclidean).await.unwrap().results;
+    let squared = run(DistanceMetric::SquaredEuclidean).await.unwrap().results;
+
+    let ids = |results: &[smolpuff::QueryResult]| {
+        results.iter().map(|r| r.id.clone()).collect::<Vec<_>>()
+    };
+    assert_eq!(ids(&squared), ids(&exact));
+    for (s, e) in squared.iter().zip(&exact) {
+        assert!((s.score.sqrt() - e.score).abs() < 1e-5);
+    }
+    assert_eq!(
+        "l2sq".parse::<DistanceMetric>().unwrap(),
+        DistanceMetric::SquaredEuclidean
+    );
+}
+
+#[tokio::test]
+async fn test_query_exact_is_its_own_ground_truth() {
+    let store = VectorStoreOptions::new()
+        .hnsw(smolpuff::HnswParams::default())
+        .build_and_open("/test", Arc::new(InMemory::new()))
+        .await
+        .unwrap();
+    let records = (0..30)
+        .map(|i| {
+            let x = i as f32;
+            (format!("v{i}"), vec![x.sin(), x.cos(), 1.0], None)
+        })
+        .collect();
+    store
+        .batch_add(records, DuplicatePolicy::Error)
+        .await
+        .unwrap();
+
+    let query = [0.3, -0.2, 1.0];
+    let exact = store.query_exact(&query, 10).await.unwrap();
+    assert_eq!(exact.len(), 10);
+    assert_eq!(smolpuff::recall_at_k(&exact, &exact), 1.0);
+
+    // Missing half the true matches is a recall of 0.5, whatever the order
+    let mut partial = exact[5..].to_vec();
+    partial.reverse();
+    assert_eq!(smolpuff::recall_at_k(&exact, &partial), 0.5);
+    assert_eq!(smolpuff::recall_at_k(&exact, &[]), 0.0);
+    assert_eq!(smolpuff::recall_at_k(&[], &exact), 1.0);
+}
+
+#[tokio::test]
+async fn test_query_numeric_range() {
+    let store = open_store().await;
+    let records = vec![
+        ("low", serde_json::json!({ "score": 0.4 })),
+        ("min", serde_json::json!({ "score": 0.5 })),
+        ("mid", serde_json::json!({ "score": 0.7 })),
+        ("max", serde_json::json!({ "score": 0.9 })),
+        ("high", serde_json::json!({ "score": 1.2 })),
+        ("int", serde_json::json!({ "score": 0 })),
+        ("text", serde_json::json!({ "score": "0.7" })),
+        ("missing", serde_json::json!({ "other": 0.7 })),
+    ];
+    for (i, (id, metadata)) in records.into_iter().enumerate() {
+        let vector = vec![1.0, i as f32 * 0.1];
+        store.add(id, vector, Some(metadata)).await.unwrap();
+    }
+    store.add("bare", vec![1.0, 0.0], None).await.unwrap();
+
+    let ids = |results: Vec<smolpuff::QueryResult>| {
+        let mut ids: Vec<_> = results.into_iter().map(|r| r.id).collect();
+        ids.sort();
+        ids
+    };
+
+    // Both bounds are inclusive
+    let in_range = store
+        .query_numeric_range(&[1.0, 0.0], 10, "score", 0.5, 0.9)
+        .await
+        .unwrap();
+    assert_eq!(ids(in_range), ["max", "mid", "min"]);
+
+    // Integers are numbers too
+    let around_zero = store
+        .query_numeric_range(&[1.0, 0.0], 10, "score", -1.0, 0.45)
+        .await
+        .unwrap();
+    assert_eq!(ids(around_zero), ["int", "low"]);
+
+    let limited = store
+        .query_numeric_range(&[1.0, 0.0], 1, "score", 0.0, 2.0)
+        .await
+        .unwrap();
+    assert_eq!(ids(limited), ["low"]);
+}
+
+#[tokio::test]
+async fn test_query_where_nested_filter() {
+    let store = open_store().await;
+    let records = [
+        (
+            "en-2021",
+            serde_json::json!({ "lang": "en", "year": 2021, "draft": false }),
+        ),
+        (
+            "de-2022",
+            serde_json::json!({ "lang": "de", "year": 2022, "draft": false }),
+        ),
+        (
+            "fr-2022",
+            serde_json::json!({ "lang": "fr", "year": 2022, "draft": false }),
+        ),
+        (
+            "en-draft",
+            serde_json::json!({ "lang": "en", "year": 2023, "draft": true }),
+        ),
+        (
+            "en-old",
+            serde_json::json!({ "lang": "en", "year": 2010, "draft": false }),
+        ),
+    ];
+    for (i, (id, metadata)) in records.into_iter().enumerate() {
+        let vector = vec![1.0, i as f32 * 0.1];
+        store.add(id, vector, Some(metadata)).await.unwrap();
+    }
+    store.add("bare", vec![1.0, 0.0], None).await.unwrap();
+
+    // (lang = en OR lang = de) AND draft = false AND year in 2020..=2024,
+    // as a client would send it
+    let filter: Filter = serde_json::from_value(serde_json::json!({
+        "and": [
+            { "or": [{ "eq": ["lang", "en"] }, { "eq": ["lang", "de"] }] },
+            { "eq": ["draft", false] },
+            { "range": ["year", 2020, 2024] }
+        ]
+    }))
+    .unwrap();
+    assert_eq!(
+        filter,
+        Filter::And(vec![
+            Filter::Or(vec![
+                Filter::Eq("lang".into(), "en".into()),
+                Filter::Eq("lang".into(), "de".into()),
+            ]),
+            Filter::Eq("draft".into(), false.into()),
+            Filter::Range("year".into(), 2020.0, 2024.0),
+        ])
+    );
+
+    let results = store.query_where(&[1.0, 0.0], 10, &filter).await.unwrap();
+    let ids: Vec<_> = results.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(ids, ["en-2021", "de-2022"]);
+
+    // Numbers compare by value
+    let year = Filter::Eq("year".into(), serde_json::json!(2022.0));
+    assert_eq!(
+        store
+            .query_where(&[1.0, 0.0], 10, &year)
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+    assert!(Filter::And(vec![]).matches(&serde_json::json!({})));
+    assert!(!Filter::Or(vec![]).matches(&serde_json::json!({})));
+}
+
+#[tokio::test]
+async fn test_strict_lookups_report_missing_ids() {
+    let store = open_store().await;
+    store
+        .add(
+            "present",
+            vec![1.0, 0.0],
+            Some(serde_json::json!({ "n": 1 })),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        store.get_strict("present").await.unwrap().vector,
+        [1.0, 0.0]
+    );
+    match store.get_strict("absent").await {
+        Err(VectorStoreError::NotFound(id)) => assert_eq!(id, "absent"),
+        other => panic!("expected NotFound, got {other:?}"),
+    }
+    assert!(store.get("absent").await.unwrap().is_none());
+
+    store.delete_strict("present").await.unwrap();
+    assert!(store.get("present").await.unwrap().is_none());
+    let err = store.delete_strict("present").await.unwrap_err();
+    assert!(matches!(&err, VectorStoreError::NotFound(id) if id == "present"));
+    store.delete("present").await.unwrap();
+
+    // Distinct from storage failures, so an API can answer 404
+    use axum::response::IntoResponse;
+    let status = err.into_response().status();
+    assert_eq!(status, axum::http::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_bincode_metadata_round_trips_and_persists_format() {
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let metadata = serde_json::json!({
+        "title": "doc",
+        "tags": ["a", "b"],
+        "nested": { "n": -3, "big": u64::MAX, "ratio": 0.25, "none": null },
+    });
+    let store = VectorStoreOptions::new()
+        .serialization(SerializationFormat::Bincode)
+        .build_and_open("/bincode", object_store.clone())
+        .await
+        .unwrap();
+    store
+        .add("a", vec![1.0, 0.0], Some(metadata.clone()))
+        .await
+        .unwrap();
+    store.add("b", vec![0.0, 1.0], None).await.unwrap();
+    let lazy = store.query_lazy(&[1.0, 0.0], 1).await.unwrap();
+    assert!(serde_json::from_slice::<serde_json::Value>(lazy[0].raw_metadata().unwrap()).is_err());
+    store.close().await.unwrap();
+
+    // The format is remembered, so a plain open reads bincode back
+    let reopened = VectorStore::open("/bincode", object_store.clone())
+        .await
+        .unwrap();
+    let results = reopened.query(&[1.0, 0.0], 2).await.unwrap();
+    assert_eq!(results[0].metadata, Some(metadata.clone()));
+    assert_eq!(results[1].metadata, None);
+    let record = reopened.get("a").await.unwrap().unwrap();
+    assert_eq!(record.metadata, Some(metadata));
+    reopened.close().await.unwrap();
+
+    let err = VectorStoreOptions::new()
+        .serialization(SerializationFormat::MessagePack)
+        .build_and_open("/bincode", object_store)
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(err, VectorStoreError::InvalidRequest(_)));
+}
+
+#[tokio::test]
+async fn test_replacing_a_record_drops_its_metadata_and_buckets() {
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let store = VectorStoreOptions::new()
+        .lsh(smolpuff::LshParams::default())
+        .build_and_open("/replaced", object_store.clone())
+        .await
+        .unwrap();
+    let meta = Some(serde_json::json!({ "stale": true }));
+    for id in ["added", "batched", "batched_once"] {
+        store
+            .add(id, vec![1.0, 0.0, 0.5], meta.clone())
+            .await
+            .unwrap();
+    }
+
+    store
+        .add("added", vec![0.0, 1.0, -0.5], None)
+        .await
+        .unwrap();
+    store
+        .batch_add(
+            vec![("batched".to_string(), vec![0.0, -1.0, 0.5], None)],
+            DuplicatePolicy::Error,
+        )
+        .await
+        .unwrap();
+    store
+        .add_batch(vec![VectorRecord {
+            id: "batched_once".to_string(),
+            vector: vec![-1.0, 0.0, -0.5],
+            metadata: None,
+        }])
+        .await
+        .unwrap();
+    for id in ["added", "batched", "batched_once"] {
+        assert_eq!(store.get(id).await.unwrap().unwrap().metadata, None, "{id}");
+    }
+    let results = store.query(&[1.0, 1.0, 1.0], 3).await.unwrap();
+    assert!(results.iter().all(|r| r.metadata.is_none()));
+    store.close().await.unwrap();
+
+    // One bucket per table for each record: the old vectors' are gone
+    let db = slatedb::Db::open("/replaced", object_store).await.unwrap();
+    let mut iter = db.scan(b"ns:".to_vec()..b"ns;".to_vec()).await.unwrap();
+    let mut buckets = 0;
+    while let Some(item) = iter.next().await.unwrap() {
+        if String::from_utf8_lossy(&item.key).contains(":lsh:") {
+            buckets += 1;
+        }
+    }
+    assert_eq!(buckets, 3 * smolpuff::LshParams::default().tables);
+    db.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_update_metadata_keeps_vector_bytes() {
+    let store = open_store().await;
+    let vector = vec![0.1, -2.5, 3.75];
+    store
+        .add(
+            "v",
+            vector.clone(),
+            Some(serde_json::json!({ "category": "a" })),
+        )
+        .await
+        .unwrap();
+    let before = store.get_raw("v").await.unwrap().unwrap();
+
+    let retagged = serde_json::json!({ "category": "b", "reviewed": true });
+    store
+        .update_metadata("v", Some(retagged.clone()))
+        .await
+        .unwrap();
+    assert_eq!(store.get_raw("v").await.unwrap().unwrap(), before);
+    let record = store.get("v").await.unwrap().unwrap();
+    assert_eq!(record.vector, vector);
+    assert_eq!(record.metadata, Some(retagged));
+
+    store.update_metadata("v", None).await.unwrap();
+    assert_eq!(store.get("v").await.unwrap().unwrap().metadata, None);
+    assert_eq!(store.get_raw("v").await.unwrap().unwrap(), before);
+
+    let err = store
+        .update_metadata("absent", Some(serde_json::json!({})))
+        .await
+        .unwrap_err();
+    assert!(matches!(&err, VectorStoreError::NotFound(id) if id == "absent"));
+    assert!(!store.contains("absent").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_patch_metadata_adds_overwrites_and_removes_keys() {
+    let store = open_store().await;
+    let original = serde_json::json!({ "category": "a", "draft": true, "tags": ["x"] });
+    store
+        .add("v", vec![1.0, 0.0], Some(original))
+        .await
+        .unwrap();
+
+    store
+        .patch_metadata(
+            "v",
+            serde_json::json!({ "reviewed": "yes", "category": "b", "draft": null }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get("v").await.unwrap().unwrap().metadata,
+        Some(serde_json::json!({ "category": "b", "tags": ["x"], "reviewed": "yes" }))
+    );
+
+    // Without metadata, the patch becomes the metadata
+    store.add("bare", vec![0.0, 1.0], None).await.unwrap();
+    store
+        .patch_metadata("bare", serde_json::json!({ "category": "c" }))
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get("bare").await.unwrap().unwrap().metadata,
+        Some(serde_json::json!({ "category": "c" }))
+    );
+
+    let err = store
+        .patch_metadata("absent", serde_json::json!({ "category": "c" }))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, VectorStoreError::NotFound(_)));
+    let err = store
+        .patch_metadata("v", serde_json::json!(["not", "an", "object"]))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, VectorStoreError::InvalidRequest(_)));
+}
+
+#[tokio::test]
+async fn test_warmed_up_queries_match_uncached_ones() {
+    let (cached, uncached) = (open_store().await, open_store().await);
+    let items: Vec<_> = (0..20)
+        .map(|i| {
+            let x = i as f32;
+            let vector = vec![x.sin(), (x * 0.7).cos(), x / 20.0, 1.0];
+            (format!("v{i}"), vector, Some(serde_json::json!({ "i": i })))
+        })
+        .collect();
+    for store in [&cached, &uncached] {
+        store
+            .batch_add(items.clone(), DuplicatePolicy::KeepLast)
+            .await
+            .unwrap();
+    }
+    cached.warmup().await.unwrap();
+
+    let query = [0.3, -0.5, 0.8, 1.0];
+    let assert_agree = |label: &'static str| {
+        let (cached, uncached) = (cached.clone(), uncached.clone());
+        async move {
+            assert_eq!(
+                cached.query(&query, 5).await.unwrap(),
+                uncached.query(&query, 5).await.unwrap(),
+                "{label}"
+            );
+        }
+    };
+    assert_agree("after warmup").await;
+    // Only the results' metadata is read once the vectors are in memory
+    let (_, warm) = cached.query_profiled(&query, 5).await.unwrap();
+    let (_, cold) = uncached.query_profiled(&query, 5).await.unwrap();
+    assert_eq!(warm.records_scanned, 20);
+    assert!(warm.bytes_read < cold.bytes_read);
+
+    // Writes and deletes after warming up must show up in cached queries
+    for store in [&cached, &uncached] {
+        store
+            .add("new", vec![0.3, -0.5, 0.8, 1.0], None)
+            .await
+            .unwrap();
+        store
+            .add("v3", vec![0.2, -0.4, 0.9, 1.0], None)
+            .await
+            .unwrap();
+        store.delete("v7").await.unwrap();
+    }
+    assert_agree("after writes").await;
+    let results = cached.query(&query, 25).await.unwrap();
+    assert_eq!(results[0].id, "new");
+    assert!(results.iter().all(|r| r.id != "v7"));
+
+    for store in [&cached, &uncached] {
+        store.clear().await.unwrap();
+    }
+    assert!(cached.query(&query, 5).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_query_lsh_recall_against_exact() {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // Clusters, so every query has true neighbors to find
+    let mut rng = StdRng::seed_from_u64(11);
+    let dim = 32;
+    let centers: Vec<Vec<f32>> = (0..50)
+        .map(|_| (0..dim).map(|_| rng.gen_range(-1.0..1.0)).collect())
+        .collect();
+    let mut near = |center: &[f32]| -> Vec<f32> {
+        center
+            .iter()
+            .map(|x| x + rng.gen_range(-0.1..0.1))
+            .collect()
+    };
+    let items: Vec<_> = (0..1000)
+        .map(|i| (format!("v{i:04}"), near(&centers[i % 50]), None))
+        .collect();
+    let queries: Vec<Vec<f32>> = (0..20).map(|i| near(&centers[i])).collect();
+
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let store = VectorStore::open("/lsh", object_store.clone())
+        .await
+        .unwrap();
+    store
+        .batch_add(items, DuplicatePolicy::Error)
+        .await
+        .unwrap();
+    // Without LSH, query_lsh is an exact query
+    assert_eq!(
+        store.query_lsh(&queries[0], 10).await.unwrap(),
+        store.query(&queries[0], 10).await.unwrap()
+    );
+    store.close().await.unwrap();
+
+    // Records written before LSH was turned on are hashed on open
+    let store = VectorStoreOptions::new()
+        .lsh(smolpuff::LshParams::default())
+        .build_and_open("/lsh", object_store.clone())
+        .await
+        .unwrap();
+    let mut recall = 0.0;
+    for query in &queries {
+        let truth = store.query_exact(query, 10).await.unwrap();
+        let approx = store.query_lsh(query, 10).await.unwrap();
+        assert!(approx.windows(2).all(|w| w[0].score >= w[1].score));
+        recall += smolpuff::recall_at_k(&truth, &approx) / queries.len() as f64;
+    }
+    assert!(recall >= 0.9, "recall@10 was {recall}");
+
+    store.add("exact", queries[1].clone(), None).await.unwrap();
+    assert_eq!(
+        store.query_lsh(&queries[1], 1).await.unwrap()[0].id,
+        "exact"
+    );
+    store.delete("exact").await.unwrap();
+    let found = store.query_lsh(&queries[1], 10).await.unwrap();
+    assert!(found.iter().all(|r| r.id != "exact"));
+    let before = store.query_lsh(&queries[2], 10).await.unwrap();
+    store.close().await.unwrap();
+
+    // The hyperplanes are persisted, so buckets survive a plain reopen
+    let store = VectorStore::open("/lsh", object_store.clone())
+        .await
+        .unwrap();
+    assert_eq!(store.query_lsh(&queries[2], 10).await.unwrap(), before);
+    store.close().await.unwrap();
+
+    let err = VectorStoreOptions::new()
+        .lsh(smolpuff::LshParams {
+            tables: 4,
+            ..Default::default()
+        })
+        .build_and_open("/lsh", object_store)
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(err, VectorStoreError::InvalidRequest(_)));
+}
+
+#[tokio::test]
+async fn test_query_ann_scores_with_the_namespace_metric() {
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let open = || {
+        VectorStoreOptions::new()
+            .hnsw(smolpuff::HnswParams::default())
+            .build_and_open("/ann_metric", object_store.clone())
+    };
+    // The index is built on open, before the namespace picks its metric
+    let store = open().await.unwrap();
+    store
+        .create_namespace("_default", 2, "euclidean")
+        .await
+        .unwrap();
+    for (id, v) in [
+        ("near", [1.0, 0.1]),
+        ("same_dir", [9.0, 0.0]),
+        ("off", [1.0, 0.9]),
+    ] {
+        store.add(id, v.to_vec(), None).await.unwrap();
+    }
+
+    let query = [1.0, 0.0];
+    let expected = store.query(&query, 3).await.unwrap();
+    assert_eq!(expected[0].id, "near");
+    assert_eq!(store.query_ann(&query, 3, 16).await.unwrap(), expected);
+    store.clear().await.unwrap();
+    store.add("near", vec![1.0, 0.1], None).await.unwrap();
+    store.add("same_dir", vec![9.0, 0.0], None).await.unwrap();
+    assert_eq!(store.query_ann(&query, 1, 16).await.unwrap()[0].id, "near");
+    store.close().await.unwrap();
+
+    // Reopening builds it with the namespace's metric too
+    let store = open().await.unwrap();
+    assert_eq!(
+        store.query_ann(&query, 2, 16).await.unwrap(),
+        store.query(&query, 2).await.unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_query_lsh_scores_with_the_namespace_metric() {
+    let store = VectorStoreOptions::new()
+        .lsh(smolpuff::LshParams::default())
+        .build_and_open("/lsh_metric", Arc::new(InMemory::new()))
+        .await
+        .unwrap();
+    store
+        .create_namespace("_default", 2, "euclidean")
+        .await
+        .unwrap();
+    for (id, v) in [
+        ("near", [1.0, 0.1]),
+        ("same_dir", [9.0, 0.0]),
+        ("off", [1.0, 0.9]),
+    ] {
+        store.add(id, v.to_vec(), None).await.unwrap();
+    }
+
+    let query = [1.0, 0.0];
+    let found = store.query_lsh(&query, 3).await.unwrap();
+    assert!(found.windows(2).all(|w| w[0].score <= w[1].score));
+    let expected = store.query(&query, 3).await.unwrap();
+    assert_eq!(found[0], expected[0]);
+    for r in &found {
+        assert!(expected.contains(r), "{r:?}");
+    }
+}
+
+#[tokio::test]
+async fn test_query_scores_match_public_distance_functions() {
+    use smolpuff::distance::{cosine_similarity, euclidean_distance};
+
+    let vectors = [
+        vec![1.0, 2.0, 3.0],
+        vec![-0.5, 0.25, 4.0],
+        vec![0.0, 0.0, 0.0],
+    ];
+    let query = [0.3, -1.0, 2.0];
+    let cosine = open_store().await;
+    let euclidean = VectorStoreOptions::new()
+        .metric(DistanceMetric::Euclidean)
+        .build_and_open("/euclidean", Arc::new(InMemory::new()))
+        .await
+        .unwrap();
+    for store in [&cosine, &euclidean] {
+        let items = vectors
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i.to_string(), v.clone(), None))
+            .collect();
+        store
+            .batch_add(items, DuplicatePolicy::Error)
+            .await
+            .unwrap();
+    }
+
+    for result in cosine.query(&query, 3).await.unwrap() {
+        let vector = &vectors[result.id.parse::<usize>().unwrap()];
+        assert!((result.score - cosine_similarity(&query, vector)).abs() < 1e-6);
+    }
+    for result in euclidean.query(&query, 3).await.unwrap() {
+        let vector = &vectors[result.id.parse::<usize>().unwrap()];
+        assert!((result.score - euclidean_distance(&query, vector)).abs() < 1e-6);
+    }
+}
+
+#[tokio::test]
+async fn test_query_with_metric_overrides_store_metric() {
+    let store = open_store().await;
+    let items = vec![
+        ("far_aligned".to_string(), vec![10.0, 0.0], None),
+        ("near_skewed".to_string(), vec![0.9, 0.5], None),
+        ("opposite".to_string(), vec![-1.0, 0.0], None),
+    ];
+    store
+        .batch_add(items, DuplicatePolicy::Error)
+        .await
+        .unwrap();
+    let query = [1.0, 0.0];
+    let ids = |results: Vec<smolpuff::QueryResult>| -> Vec<String> {
+        results.into_iter().map(|r| r.id).collect()
+    };
+
+    let cosine = store
+        .query_with_metric(&query, 3, DistanceMetric::Cosine)
+        .await
+        .unwrap();
+    assert_eq!(ids(cosine), ["far_aligned", "near_skewed", "opposite"]);
+
+    // Distances rank lowest first
+    let euclidean = store
+        .query_with_metric(&query, 3, DistanceMetric::Euclidean)
+        .await
+        .unwrap();
+    assert!(euclidean.windows(2).all(|w| w[0].score <= w[1].score));
+    assert_eq!(ids(euclidean), ["near_skewed", "opposite", "far_aligned"]);
+
+    // The override doesn't stick
+    assert_eq!(store.metric(), DistanceMetric::Cosine);
+    assert_eq!(store.query(&query, 1).await.unwrap()[0].id, "far_aligned");
+}
+
+#[tokio::test]
+async fn test_namespace_scans_stay_isolated_with_adversarial_names() {
+    let ids = |items: Vec<smolpuff::models::QueryResultItem>| -> Vec<String> {
+        let mut ids: Vec<String> = items.into_iter().map(|item| item.id).collect();
+        ids.sort_unstable();
+        ids
+    };
+
+    for encoding in [KeyEncoding::Raw, KeyEncoding::Escaped] {
+        let store = VectorStoreOptions::new()
+            .key_encoding(encoding)
+            .build_and_open("/isolation", Arc::new(InMemory::new()))
+            .await
+            .unwrap();
+        let mut namespaces = vec!["a", "a;"];
+        if encoding == KeyEncoding::Escaped {
+            namespaces.extend(["a:b", "a:vec"]);
+        } else {
+            // Raw keys couldn't keep these out of namespace "a"'s scans
+            for name in ["a:b", "a:vec"] {
+                let err = store.create_namespace(name, 2, "cosine").await.unwrap_err();
+                assert!(matches!(err, VectorStoreError::InvalidRequest(_)));
+            }
+        }
+
+        // Every namespace holds the same adversarial ids, each tagged with its namespace
+        for ns in &namespaces {
+            store.create_namespace(ns, 2, "cosine").await.unwrap();
+            for id in ["a", "a:b", "vec:a"] {
+                let metadata = serde_json::json!({ "ns": ns });
+                store
+                    .upsert(ns, id, vec![1.0, 0.0], Some(metadata))
+                    .await
+                    .unwrap();
+            }
+        }
+        for ns in &namespaces {
+            let found = store.query_ns(ns, &[1.0, 0.0], 100).await.unwrap();
+            assert!(
+                found
+                    .iter()
+                    .all(|item| item.attributes == Some(serde_json::json!({ "ns": ns }))),
+                "{encoding:?} {ns}"
+            );
+            assert_eq!(ids(found), ["a", "a:b", "vec:a"], "{encoding:?} {ns}");
+        }
+
+        // Dropping one namespace leaves its neighbors whole
+        store.delete_namespace("a;").await.unwrap();
+        let found = store.query_ns("a", &[1.0, 0.0], 100).await.unwrap();
+        assert_eq!(ids(found).len(), 3, "{encoding:?}");
+    }
+}
+
+#[tokio::test]
+async fn test_query_batch_matches_serial_queries() {
+    let store = open_store().await;
+    let items: Vec<_> = (0..30)
+        .map(|i| {
+            let x = i as f32;
+            let metadata = (i % 2 == 0).then(|| serde_json::json!({ "i": i }));
+            (format!("v{i}"), vec![x.cos(), x.sin(), 0.5], metadata)
+        })
+        .collect();
+    store
+        .batch_add(items, DuplicatePolicy::Error)
+        .await
+        .unwrap();
+    let queries: Vec<Vec<f32>> = (0..6)
+        .map(|i| vec![i as f32 - 2.5, 1.0, (i % 3) as f32])
+        .collect();
+
+    let mut serial = Vec::new();
+    for query in &queries {
+        serial.push(store.query(query, 4).await.unwrap());
+    }
+    assert_eq!(store.query_batch(&queries, 4).await.unwrap(), serial);
+    store.warmup().await.unwrap();
+    assert_eq!(store.query_batch(&queries, 4).await.unwrap(), serial);
+
+    assert!(store.query_batch(&[], 4).await.unwrap().is_empty());
+    let err = store
+        .query_batch(&[vec![1.0, 0.0, 0.0], vec![1.0, 0.0]], 4)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, VectorStoreError::DimensionMismatch { .. }));
+}
+
+#[tokio::test]
+async fn test_delete_namespace_drops_records_of_every_kind() {
+    use smolpuff::ProductQuantizer;
+
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let store = VectorStoreOptions::new()
+        .lsh(smolpuff::LshParams::default())
+        .build_and_open("/dropped", object_store.clone())
+        .await
+        .unwrap();
+    for i in 0..8 {
+        let x = i as f32;
+        store
+            .add(&format!("v{i}"), vec![x.cos(), x.sin(), 0.5, 1.0], None)
+            .await
+            .unwrap();
+    }
+    let codebooks = vec![vec![vec![0.0, 0.0], vec![1.0, 1.0]]; 2];
+    store
+        .set_pq(ProductQuantizer::from_codebooks(codebooks).unwrap())
+        .await
+        .unwrap();
+    store
+        .add_sparse("sparse", SparseVector::from([(1, 1.0)]), None)
+        .await
+        .unwrap();
+    store
+        .add_document("doc", vec![vec![1.0, 0.0, 0.0, 0.0]], None)
+        .await
+        .unwrap();
+    let query = [1.0, 0.0, 0.5, 1.0];
+    assert!(!store.query_pq(&query, 10).await.unwrap().is_empty());
+    assert!(!store.query_lsh(&query, 10).await.unwrap().is_empty());
+
+    store.delete_namespace("_default").await.unwrap();
+    assert!(store.query_pq(&query, 10).await.unwrap().is_empty());
+    assert!(store.query_lsh(&query, 10).await.unwrap().is_empty());
+    let sparse = SparseVector::from([(1, 1.0)]);
+    assert!(store.query_sparse(&sparse, 10).await.unwrap().is_empty());
+    assert!(store.query_documents(&query, 10).await.unwrap().is_empty());
+
+    // The hyperplanes went with the old dimension, so a new one hashes afresh
+    store.add("flat", vec![1.0, 0.0], None).await.unwrap();
+    let found = store.query_lsh(&[1.0, 0.0], 10).await.unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].id, "flat");
+    store.delete_namespace("_default").await.unwrap();
+    store.close().await.unwrap();
+
+    let db = slatedb::Db::open("/dropped", object_store).await.unwrap();
+    let mut iter = db.scan(b"ns:".to_vec()..b"ns;".to_vec()).await.unwrap();
+    let mut left = Vec::new();
+    while let Some(item) = iter.next().await.unwrap() {
+        left.push(String::from_utf8_lossy(&item.key).into_owned());
+    }
+    assert!(left.is_empty(), "{left:?}");
+    db.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_namespace_with_legacy_distance_string_still_queries() {
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    // Written before the distance was checked, when any string was kept
+    let db = slatedb::Db::open("/legacy", object_store.clone())
+        .await
+        .unwrap();
+    for (name, distance) in [("shouty", "Euclidean"), ("unknown", "dotproduct")] {
+        let meta = serde_json::json!({
+            "name": name,
+            "vector_dim": 2,
+            "distance": distance,
+            "approx_row_count": 0,
+            "created_at": "2024-01-01T00:00:00Z",
+        });
+        db.put(
+            format!("ns:{name}:meta").as_bytes(),
+            &serde_json::to_vec(&meta).unwrap(),
+        )
+        .await
+        .unwrap();
+    }
+    db.close().await.unwrap();
+
+    let store = VectorStore::open("/legacy", object_store).await.unwrap();
+    for ns in ["shouty", "unknown"] {
+        store
+            .upsert(ns, "near", vec![1.0, 0.0], None)
+            .await
+            .unwrap();
+        store.upsert(ns, "far", vec![4.0, 3.0], None).await.unwrap();
+    }
+    // Parsed regardless of case
+    let found = store.query_ns("shouty", &[1.0, 0.0], 2).await.unwrap();
+    assert_eq!(found[0].id, "near");
+    assert!((found[1].score - 18f32.sqrt()).abs() < 1e-5);
+    // Unknown strings score with the store's metric, cosine
+    let found = store.query_ns("unknown", &[1.0, 0.0], 2).await.unwrap();
+    assert!((found[0].score - 1.0).abs() < 1e-5);
+    assert!((found[1].score - 0.8).abs() < 1e-5);
+
+    // New namespaces still have to name a known metric
+    let err = store.create_namespace("new", 2, "dotproduct").await;
+    assert!(matches!(err, Err(VectorStoreError::InvalidRequest(_))));
+    let meta = store.create_namespace("new", 2, "L2").await.unwrap();
+    assert_eq!(meta.distance, "euclidean");
+}
+
+#[tokio::test]
+async fn test_query_pq_scores_with_the_namespace_metric() {
+    let store = open_euclidean_default().await;
+    // More centroids than records, so every record encodes exactly
+    let params = smolpuff::PqParams {
+        subspaces: 2,
+        centroids: 64,
+        ..Default::default()
+    };
+    store.train_pq(params).await.unwrap();
+
+    let query = [2.0, 0.5];
+    let expected = store.query(&query, 5).await.unwrap();
+    let approx = store.query_pq(&query, 5).await.unwrap();
+    let ids = |results: &[smolpuff::QueryResult]| {
+        results.iter().map(|r| r.id.clone()).collect::<Vec<_>>()
+    };
+    assert_eq!(ids(&approx), ids(&expected));
+    for (a, e) in approx.iter().zip(&expected) {
+        assert!((a.score - e.score).abs() < 1e-4, "{a:?} vs {e:?}");
+    }
+}
+
+#[tokio::test]
+async fn test_query_pq_rejects_codes_that_dont_fit_the_codebooks() {
+    let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    let store = VectorStore::open("/pq_codes", object_store.clone())
+        .await
+        .unwrap();
+    store.add("a", vec![1.0, 0.0], None).await.unwrap();
+    let codebooks = vec![vec![vec![0.0], vec![1.0]]; 2];
+    store
+        .set_pq(smolpuff::ProductQuantizer::from_codebooks(codebooks).unwrap())
+        .await
+        .unwrap();
+    store.close().await.unwrap();
+
+    // Codes left behind by larger codebooks than the persisted ones
+    let db = slatedb::Db::open("/pq_codes", object_store.clone())
+        .await
+        .unwrap();
+    db.put(b"ns:_default:pq:a", &[200, 0]).await.unwrap();
+    db.close().await.unwrap();
+
+    let (store, report) = VectorStore::open_and_verify("/pq_codes", object_store)
+        .await
+        .unwrap();
+    assert_eq!(report.problems.len(), 1, "{:?}", report.problems);
+    let err = store.query_pq(&[1.0, 0.0], 1).await.unwrap_err();
+    assert!(matches!(err, VectorStoreError::InvalidVector(_)));
+}